@@ -5,7 +5,7 @@ use std::env;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 fn main() {
     if !license_accepted() {
@@ -22,11 +22,40 @@ fn main() {
     }
 
     let mut args: Vec<String> = env::args().skip(1).collect();
+    // Everything after `--` belongs to the script (see `cli.flags`).
+    let script_args = match args.iter().position(|arg| arg == "--") {
+        Some(idx) => args.split_off(idx).split_off(1),
+        None => Vec::new(),
+    };
     let json_mode = args.iter().any(|arg| arg == "--json");
-    args.retain(|arg| arg != "--json");
+    let leak_report = args.iter().any(|arg| arg == "--leak-report");
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let seed = match args.iter().position(|arg| arg == "--seed") {
+        Some(idx) => {
+            let value = args.get(idx + 1).cloned();
+            args.drain(idx..(idx + 2).min(args.len()));
+            match value.as_deref().map(str::parse::<u64>) {
+                Some(Ok(seed)) => Some(seed),
+                _ => {
+                    eprintln!("--seed expects a whole number");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+    // `--summary` reports to stderr, `--summary=<path>` to a JSON file.
+    let summary = args.iter().find_map(|arg| match arg.as_str() {
+        "--summary" => Some("stderr".to_string()),
+        _ => arg.strip_prefix("--summary=").map(|path| path.to_string()),
+    });
+    args.retain(|arg| {
+        !matches!(arg.as_str(), "--json" | "--leak-report" | "--dry-run")
+            && !arg.starts_with("--summary")
+    });
 
-    let (command, path) = match args.get(0).map(|s| s.as_str()) {
-        Some("check") | Some("eject") | Some("run") => {
+    let (command, path) = match args.first().map(|s| s.as_str()) {
+        Some("check") | Some("doc") | Some("eject") | Some("run") => {
             if args.len() != 2 {
                 print_usage();
                 std::process::exit(1);
@@ -55,17 +84,18 @@ fn main() {
         std::process::exit(1);
     }
 
-    let content = match fs::read_to_string(&path) {
-        Ok(c) => c,
+    let content = match fs::read(&path) {
+        Ok(bytes) => zinc_core::decode_source(&bytes),
         Err(err) => {
             eprintln!("Failed to read {}: {}", path, err);
             std::process::exit(1);
         }
     };
 
+    let cache = zinc_core::Cache::user();
     match command.as_str() {
         "check" => {
-            match zinc_core::transpile_with_error(&content) {
+            match zinc_core::transpile_file_cached(Path::new(&path), cache.as_ref()) {
                 Ok(_) => println!("OK"),
                 Err(err) => {
                     if json_mode {
@@ -82,9 +112,38 @@ fn main() {
                 }
             }
         }
+        "doc" => {
+            let docs = match zinc_core::function_docs(&content) {
+                Ok(docs) => docs,
+                Err(err) => {
+                    eprintln!(
+                        "Parse failed: {} (line {}, column {})",
+                        err.message, err.line, err.column
+                    );
+                    std::process::exit(1);
+                }
+            };
+            if json_mode {
+                let json = serde_json::to_string(&docs).unwrap_or_else(|_| "[]".to_string());
+                println!("{}", json);
+            } else {
+                for doc in docs {
+                    println!(
+                        "fn {}({})  (line {})",
+                        doc.name,
+                        doc.params.join(", "),
+                        doc.line
+                    );
+                    for line in doc.doc.lines() {
+                        println!("    {}", line);
+                    }
+                    println!();
+                }
+            }
+        }
         "eject" => {
-            let transpiled = match zinc_core::transpile_with_error(&content) {
-                Ok(out) => out,
+            let bundle = match zinc_core::transpile_file_cached(Path::new(&path), cache.as_ref()) {
+                Ok(bundle) => bundle,
                 Err(err) => {
                     eprintln!(
                         "Parse failed: {} (line {}, column {})",
@@ -93,7 +152,16 @@ fn main() {
                     std::process::exit(1);
                 }
             };
-            let wrapped = format!("fn main() {{\n{}\n zinc_std::check_leaks();\n}}", transpiled);
+            let sources = bundle
+                .files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>();
+            let wrapped = format!(
+                "// Transpiled from {}\n{}",
+                sources.join(", "),
+                wrap_program(&bundle.program)
+            );
             let stem = Path::new(&path)
                 .file_stem()
                 .and_then(|s| s.to_str())
@@ -106,8 +174,8 @@ fn main() {
             println!("Ejected to .rs");
         }
         _ => {
-            let transpiled = match zinc_core::transpile_with_error(&content) {
-                Ok(out) => out,
+            let bundle = match zinc_core::transpile_file_cached(Path::new(&path), cache.as_ref()) {
+                Ok(bundle) => bundle,
                 Err(err) => {
                     eprintln!(
                         "Parse failed: {} (line {}, column {})",
@@ -116,7 +184,14 @@ fn main() {
                     std::process::exit(1);
                 }
             };
-            let wrapped = format!("fn main() {{\n{}\n zinc_std::check_leaks();\n}}", transpiled);
+            let wrapped = wrap_program(&bundle.program);
+            let mut features = bundle.program.std_features();
+            if renders_pages(Path::new(&path)) {
+                features.push("render");
+            }
+            if env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_some() {
+                features.push("otel");
+            }
 
             let temp_path = "crates/zinc_std/src/bin/temp_runner.rs";
             if let Err(err) = fs::create_dir_all("crates/zinc_std/src/bin") {
@@ -128,15 +203,47 @@ fn main() {
                 std::process::exit(1);
             }
 
-            let status = Command::new("cargo")
-                .args([
-                    "run",
-                    "--manifest-path",
-                    "crates/zinc_std/Cargo.toml",
-                    "--bin",
-                    "temp_runner",
-                ])
-                .status();
+            let mut envs = vec![("ZINC_SCRIPT", path.clone())];
+            if leak_report {
+                envs.push(("ZINC_LEAK_REPORT", "1".to_string()));
+            }
+            if dry_run {
+                envs.push(("ZINC_DRY_RUN", "1".to_string()));
+            }
+            if let Some(seed) = seed {
+                envs.push(("ZINC_SEED", seed.to_string()));
+            }
+            if let Some(target) = &summary {
+                envs.push(("ZINC_SUMMARY", target.clone()));
+            }
+
+            // Leak tracking is compiled into zinc_std, and the host build has none.
+            let prebuilt = if leak_report {
+                None
+            } else {
+                prebuilt_runner(temp_path)
+            };
+            let status = match prebuilt {
+                Some(mut runner) => runner.envs(envs).args(&script_args).status(),
+                None => {
+                    let mut cargo = Command::new("cargo");
+                    cargo.args([
+                        "run",
+                        "--manifest-path",
+                        "crates/zinc_std/Cargo.toml",
+                        "--bin",
+                        "temp_runner",
+                        "--no-default-features",
+                    ]);
+                    if leak_report {
+                        features.push("alloc-tracking");
+                    }
+                    if !features.is_empty() {
+                        cargo.args(["--features", &features.join(",")]);
+                    }
+                    cargo.envs(envs).arg("--").args(&script_args).status()
+                }
+            };
 
             match status {
                 Ok(s) if s.success() => {
@@ -155,6 +262,116 @@ fn main() {
     }
 }
 
+const HOST_DIR: &str = "target/zinc-host";
+
+// The fast path for `run`: zinc_std is built once, with every feature, as a
+// Rust dylib, and each script is compiled by rustc against it. Linking to the
+// dylib never touches sqlx, pyo3 or wreq again, so only the script itself is
+// compiled. Returns None, to fall back to `cargo run`, if any step fails.
+fn prebuilt_runner(source: &str) -> Option<Command> {
+    let out_dir = Path::new(HOST_DIR).join("debug");
+    let deps = out_dir.join("deps");
+    let dylib = out_dir.join(format!(
+        "{}zinc_std{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    ));
+    if host_is_stale(&dylib) {
+        let built = Command::new("cargo")
+            .args([
+                "build",
+                "--manifest-path",
+                "crates/zinc_std/Cargo.toml",
+                "--lib",
+                "--target-dir",
+                HOST_DIR,
+            ])
+            .status()
+            .ok()?;
+        if !built.success() {
+            return None;
+        }
+    }
+
+    // Errors are left to the `cargo run` fallback to report.
+    let runner = Path::new(HOST_DIR).join(format!("temp_runner{}", env::consts::EXE_SUFFIX));
+    let compiled = Command::new("rustc")
+        .args(["--edition", "2021", "-C", "prefer-dynamic", "-L"])
+        .arg(format!("dependency={}", deps.display()))
+        .arg("--extern")
+        .arg(format!("zinc_std={}", dylib.display()))
+        .arg("-o")
+        .arg(&runner)
+        .arg(source)
+        .stderr(Stdio::null())
+        .status()
+        .ok()?;
+    if !compiled.success() {
+        return None;
+    }
+
+    // The runner loads zinc_std and the Rust std dylib at startup.
+    let libdir = Command::new("rustc")
+        .args(["--print", "target-libdir"])
+        .output()
+        .ok()?;
+    let std_dir = PathBuf::from(String::from_utf8_lossy(&libdir.stdout).trim());
+    let var = if cfg!(windows) {
+        "PATH"
+    } else if cfg!(target_os = "macos") {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+    let mut paths = vec![out_dir, deps, std_dir];
+    if let Some(existing) = env::var_os(var) {
+        paths.extend(env::split_paths(&existing));
+    }
+    let mut command = Command::new(&runner);
+    command.env(var, env::join_paths(paths).ok()?);
+    Some(command)
+}
+
+// Rebuilt whenever zinc_std changes.
+fn host_is_stale(dylib: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let built = match modified(dylib) {
+        Some(time) => time,
+        None => return true,
+    };
+    ["crates/zinc_std/src/lib.rs", "crates/zinc_std/Cargo.toml"]
+        .iter()
+        .any(|path| modified(Path::new(path)).is_none_or(|time| time > built))
+}
+
+// Domains marked `render = true` in zinc.toml fetch through headless Chrome,
+// whether or not the script itself mentions it.
+fn renders_pages(script: &Path) -> bool {
+    let Some(config) =
+        zinc_std::config::find(Some(script)).and_then(|path| zinc_std::config::read(&path).ok())
+    else {
+        return false;
+    };
+    config["spider"]["domains"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .any(|(_, domain)| domain["render"].as_bool() == Some(true))
+}
+
+// Functions and other items live at module level so they can be called from
+// each other; everything else runs inside `main`, in its own block so that
+// the tasks it holds are dropped before leaks are counted.
+fn wrap_program(program: &zinc_core::Program) -> String {
+    format!(
+        "{}fn main() {{\n zinc_std::limits::start();\n zinc_std::telemetry::init();\n zinc_std::track_values();\n\
+         {{\n{}\n}}\n\
+         zinc_std::run_exit_hooks();\n zinc_std::check_leaks();\n zinc_std::summary::emit();\n\
+         zinc_std::telemetry::shutdown();\n}}",
+        program.items, program.main
+    )
+}
+
 fn acceptance_path() -> Option<PathBuf> {
     let home = env::var_os("USERPROFILE")
         .or_else(|| env::var_os("HOME"))
@@ -199,7 +416,8 @@ fn prompt_acceptance() -> bool {
 
 fn print_usage() {
     eprintln!("Usage:");
-    eprintln!("  zn run <path>.zn");
+    eprintln!("  zn run <path>.zn [--dry-run] [--seed <n>] [--leak-report] [--summary[=<path>]] [-- <script args>]");
     eprintln!("  zn check <path>.zn [--json]");
+    eprintln!("  zn doc <path>.zn [--json]");
     eprintln!("  zn eject <path>.zn");
 }
@@ -0,0 +1,35 @@
+// Transpiles a large generated program; run with `cargo bench -p zinc_core`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use zinc_core::transpile_with_error;
+
+// Roughly what code generators emit: many small functions and a long main.
+fn generated_source(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source.push_str(&format!(
+            "fn step_{i}(items, limit) {{\n    let total = 0\n    for item in items {{\n        if item > limit {{\n            total += item * {i}\n        }}\n    }}\n    return {{ \"step\": {i}, \"total\": total, \"tags\": [\"a\", \"b\"] }}\n}}\n"
+        ));
+    }
+    for i in 0..functions {
+        source.push_str(&format!(
+            "let result_{i} = step_{i}([1, 2, 3, {i}], {i})\nprint(\"step {i}: {{result_{i}}}\")\n"
+        ));
+    }
+    source
+}
+
+fn transpile_large(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transpile");
+    for functions in [100, 1000] {
+        let source = generated_source(functions);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_function(format!("{}_functions", functions), |b| {
+            b.iter(|| transpile_with_error(black_box(&source)).ok())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, transpile_large);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = zinc_core::decode_source(data);
+    let _ = zinc_core::transpile_with_error(&source);
+    let _ = zinc_core::function_docs(&source);
+
+    let mut parser = zinc_core::IncrementalParser::new();
+    let _ = parser.update(&source);
+    let half = source
+        .char_indices()
+        .nth(source.chars().count() / 2)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let _ = parser.update(&source[..half]);
+});
@@ -5,13 +5,18 @@ use pest::iterators::Pair;
 use pest::error::LineColLocation;
 use pest::Parser;
 use pest_derive::Parser;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
 pub struct ZincParser;
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZincError {
     pub line: usize,
     pub column: usize,
@@ -19,9 +24,128 @@ pub struct ZincError {
     pub suggestion: String,
 }
 
+// Literals at least this long that appear more than once are hoisted into consts.
+const INTERN_MIN_LEN: usize = 24;
+const NIL: &str = "zinc_std::json::Value::Null";
+
+thread_local! {
+    static INTERNED: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    static IN_ASYNC: Cell<bool> = const { Cell::new(false) };
+    static RETURNS_VALUE: Cell<bool> = const { Cell::new(false) };
+    // Inside a `try` body, where fallible std calls propagate with `?`.
+    static IN_TRY: Cell<bool> = const { Cell::new(false) };
+    static USER_FNS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    // Parameters of the function being transpiled, which are `json::Value`s.
+    static VALUE_PARAMS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    // Variables assigned to after their `let`, which need `let mut`.
+    static REASSIGNED: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    // Variables updated with `+=` and friends, declared as a `json::Value` so
+    // that every result fits, or a `String` (true) when they start as text.
+    static ACCUMULATORS: RefCell<HashMap<String, bool>> = RefCell::new(HashMap::new());
+    // Every name bound by `let`, `for` or a parameter list.
+    static VARIABLES: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// Transpiled source split into module-level items (constants, enums and
+/// functions) and the statements that make up the body of `fn main`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Program {
+    pub items: String,
+    pub main: String,
+}
+
+// Generated Rust is typically about twice the size of its source.
+const OUTPUT_RATIO: usize = 2;
+
+/// Output buffer for generated code, sized up front from the source it is
+/// generated from so that appending statements rarely reallocates.
+struct Writer {
+    buf: String,
+}
+
+impl Writer {
+    fn for_source(source: &str) -> Self {
+        Writer {
+            buf: String::with_capacity(source.len() * OUTPUT_RATIO),
+        }
+    }
+
+    fn push(&mut self, code: &str) {
+        self.buf.push_str(code);
+    }
+
+    fn finish(self) -> String {
+        self.buf
+    }
+}
+
+impl std::fmt::Write for Writer {
+    fn write_str(&mut self, code: &str) -> std::fmt::Result {
+        self.push(code);
+        Ok(())
+    }
+}
+
+// zinc_std cargo features and the modules that need them.
+const STD_FEATURES: &[(&str, &[&str])] = &[
+    ("clipboard", &["zinc_std::clipboard::"]),
+    ("db", &["zinc_std::db::", "zinc_std::store::"]),
+    ("feed", &["zinc_std::feed::"]),
+    ("html", &["zinc_std::html::"]),
+    (
+        "http",
+        &[
+            "zinc_std::spider::",
+            "zinc_std::auth::",
+            "zinc_std::notify::",
+        ],
+    ),
+    ("parquet", &["zinc_std::parquet::"]),
+    ("python", &["zinc_std::python::"]),
+    ("redis", &["zinc_std::redis::"]),
+    ("render", &["zinc_std::spider::screenshot("]),
+    ("s3", &["zinc_std::s3::"]),
+    ("xlsx", &["zinc_std::xlsx::"]),
+];
+
+impl Program {
+    /// The zinc_std features this program uses, so that `zn run` only builds
+    /// the heavy dependencies it needs.
+    pub fn std_features(&self) -> Vec<&'static str> {
+        STD_FEATURES
+            .iter()
+            .filter(|(_, paths)| {
+                paths
+                    .iter()
+                    .any(|path| self.items.contains(path) || self.main.contains(path))
+            })
+            .map(|(feature, _)| *feature)
+            .collect()
+    }
+}
+
+/// A program assembled from an entry file and the files it imports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub program: Program,
+    /// Every file involved, each after the files it imports; the entry is last.
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+pub struct FnDoc {
+    pub name: String,
+    pub params: Vec<String>,
+    pub doc: String,
+    pub line: usize,
+}
+
 #[cfg(test)]
 mod tests {
-    use super::transpile;
+    use super::{
+        decode_source, function_docs, source_hash, transpile, transpile_file, transpile_program,
+        transpile_with_error, Cache, IncrementalParser, CACHE_MAX_ENTRIES,
+    };
 
     #[test]
     fn transpile_print_to_println() {
@@ -30,66 +154,2463 @@ mod tests {
         assert_eq!(output, "println!(\"{}\", \"x\");");
     }
 
-    #[test]
-    fn transpile_spider_get_default_profile() {
-        let input = "spider.get(url)";
-        let output = transpile(input);
-        assert_eq!(output, "zinc_std::spider::get(url, None);");
+    #[test]
+    fn transpile_spider_get_default_profile() {
+        let input = "spider.get(url)";
+        let output = transpile(input);
+        assert_eq!(output, "zinc_std::spider::get(url, None);");
+    }
+
+    #[test]
+    fn transpile_spider_get_with_profile() {
+        let input = "spider.get(url, profile)";
+        let output = transpile(input);
+        assert_eq!(output, "zinc_std::spider::get(url, Some(profile));");
+    }
+
+    #[test]
+    fn transpile_spider_get_with_encoding_override() {
+        let input = "spider.get(url, \"chrome\", \"shift_jis\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::get_with_encoding(url, Some(\"chrome\"), \"shift_jis\");"
+        );
+    }
+
+    #[test]
+    fn transpile_string_escapes_raw_delimiters() {
+        let input = "let s = \"a\\\"#b\"";
+        let output = transpile(input);
+        assert_eq!(output, "let s = \"a\\\"#b\";");
+    }
+
+    #[test]
+    fn transpile_interns_repeated_long_literals() {
+        let input =
+            "let a = \"https://example.com/api/items\"\nlet b = \"https://example.com/api/items\"";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "const __ZN_STR_0: &str = \"https://example.com/api/items\";\nlet a = __ZN_STR_0;let b = __ZN_STR_0;"
+        );
+    }
+
+    #[test]
+    fn explicit_main_is_the_entry_point() {
+        let input = "fn setup() { print(1) }\nfn main() { setup() }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "fn setup() {\nprintln!(\"{}\", zinc_std::fmt::show(&1));}\nsetup();"
+        );
+    }
+
+    #[test]
+    fn transpile_fn_with_params_and_return() {
+        let input = "fn label(a, b) { return a + b }\nprint(label(\"x\", n))";
+        let program = transpile_program(input).unwrap_or_default();
+        assert_eq!(
+            program.items,
+            "fn label(a: zinc_std::json::Value, b: zinc_std::json::Value) -> zinc_std::json::Value {\nreturn zinc_std::json::from(zinc_std::ops::add(a, b));}\n"
+        );
+        assert_eq!(
+            program.main,
+            "println!(\"{}\", zinc_std::fmt::show(&label(zinc_std::json::from(\"x\"), zinc_std::json::from(n))));"
+        );
+    }
+
+    #[test]
+    fn transpile_bare_return_and_lambda_return() {
+        let input = "fn stop() { if done { return } print(1) }\nlet f = |x| { return x }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "fn stop() {\nif done {\nreturn;}println!(\"{}\", zinc_std::fmt::show(&1));}\nlet f = |x| {\nreturn x;};"
+        );
+    }
+
+    #[test]
+    fn transpile_if_expression() {
+        let input = "let label = if score > 5 { \"hot\" } else if score > 2 { \"warm\" } else { \"cold\" }\nprint(if done { 1 } else { 0 })";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let label = if zinc_std::ops::gt(&score, &5) { \"hot\" } else if zinc_std::ops::gt(&score, &2) { \"warm\" } else { \"cold\" };println!(\"{}\", zinc_std::fmt::show(&if done { 1 } else { 0 }));"
+        );
+    }
+
+    #[test]
+    fn transpile_lambda_block_value() {
+        let input = "let next = data |> map(|x| { let y = x * 2\ny + 1 })";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let next = map(data, |x| {\nlet y = zinc_std::ops::mul(x, 2);zinc_std::ops::add(y, 1)});"
+        );
+    }
+
+    #[test]
+    fn transpile_higher_order_methods() {
+        let input = "let doubled = nums.map(|x| x * 2)\nlet big = nums.filter(|x| x > 2)\nlet total = nums.reduce(|acc, x| acc + x, 0)\nnums.each(|x| print(x))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let doubled = zinc_std::iter::items(&nums).map(|x| zinc_std::ops::mul(x, 2)).collect::<Vec<_>>();let big = zinc_std::iter::items(&nums).filter(|__item| (|x| zinc_std::ops::gt(&x, &2))(__item.clone())).collect::<Vec<_>>();let total = zinc_std::iter::items(&nums).fold(0, |acc, x| zinc_std::ops::add(acc, x));zinc_std::iter::items(&nums).for_each(|__item| { (|x| println!(\"{}\", zinc_std::fmt::show(&x)))(__item); });"
+        );
+    }
+
+    #[test]
+    fn transpile_list_methods() {
+        let input = "let xs = [3, 1, 2]\nxs.push(4)\nxs.sort()\nxs.reverse()\nprint(xs.len())\nprint(xs.contains(2))\nprint(xs.join(\",\"))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let mut xs = vec![3, 1, 2];zinc_std::list::push(&mut xs, 4);zinc_std::list::sort(&mut xs);zinc_std::list::reverse(&mut xs);println!(\"{}\", zinc_std::fmt::show(&zinc_std::list::len(&xs)));println!(\"{}\", zinc_std::fmt::show(&zinc_std::list::contains(&xs, &2)));println!(\"{}\", zinc_std::fmt::show(&zinc_std::list::join(&xs, &\",\")));"
+        );
+    }
+
+    #[test]
+    fn transpile_string_methods() {
+        let input = "let parts = line.trim().lower().split(\",\")\nlet ok = name.starts_with(\"zn\") and name.contains(\"c\")\nlet clean = text.replace(\"\\n\", \" \").upper()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let parts = zinc_std::string::split(&zinc_std::string::lower(&zinc_std::string::trim(&line)), &\",\");let ok = (zinc_std::string::starts_with(&name, &\"zn\") && zinc_std::list::contains(&name, &\"c\"));let clean = zinc_std::string::upper(&zinc_std::string::replace(&text, &\"\\n\", &\" \"));"
+        );
+    }
+
+    #[test]
+    fn std_features_follow_used_modules() {
+        let features = |source: &str| {
+            transpile_program(source)
+                .map(|program| program.std_features())
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            features("fs.write(\"a.txt\", json.to_string(x))"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            features("let page = spider.get(url)\nstore.save(\"pages\", page)"),
+            vec!["db", "http"]
+        );
+        assert_eq!(
+            features("spider.screenshot(url, \"a.png\")\nparquet.write(\"a.parquet\", rows)\ns3.put(\"b\", \"k\", body)"),
+            vec!["http", "parquet", "render", "s3"]
+        );
+        assert_eq!(
+            features("let rows = xlsx.read(\"a.xlsx\")\nredis.set(\"k\", \"v\")\nclipboard.set(\"x\")\nlet items = feed.parse(xml)"),
+            vec!["clipboard", "feed", "redis", "xlsx"]
+        );
+    }
+
+    #[test]
+    fn cache_reuses_results_and_diagnostics() {
+        let dir = std::env::temp_dir().join(format!("zinc_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir);
+
+        let program = cache.transpile("print(1)").unwrap();
+        assert_eq!(program, transpile_program("print(1)").unwrap());
+        let err = cache.transpile("let x = (1 +").unwrap_err();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Hits are served from disk rather than transpiled again.
+        let entry = dir.join(format!("{:016x}.json", source_hash("print(1)")));
+        let cached = std::fs::read_to_string(&entry).unwrap();
+        std::fs::write(&entry, cached.replace("zinc_std::fmt::show(&1)", "1")).unwrap();
+        assert_eq!(
+            cache.transpile("print(1)").unwrap().main,
+            "println!(\"{}\", 1);"
+        );
+        assert_eq!(
+            cache.transpile("let x = (1 +").unwrap_err().message,
+            err.message
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_evicts_oldest_entries() {
+        let dir = std::env::temp_dir().join(format!("zinc_cache_evict_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir);
+        for n in 0..CACHE_MAX_ENTRIES + 5 {
+            cache.transpile(&format!("print({})", n)).unwrap();
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), CACHE_MAX_ENTRIES);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn transpile_file_with_imports() {
+        let dir = std::env::temp_dir().join(format!("zinc_imports_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("lib/utils.zn"),
+            "use fmt_helpers\nfn greet(name) { return shout(name) }",
+        )
+        .unwrap();
+        std::fs::write(dir.join("lib/fmt_helpers.zn"), "fn shout(s) { return s }").unwrap();
+        std::fs::write(
+            dir.join("main.zn"),
+            "import \"lib/utils.zn\"\nprint(greet(\"zinc\"))",
+        )
+        .unwrap();
+        std::fs::write(dir.join("a.zn"), "import \"b.zn\"\nprint(1)").unwrap();
+        std::fs::write(dir.join("b.zn"), "import \"a.zn\"").unwrap();
+
+        let bundle = transpile_file(&dir.join("main.zn")).unwrap();
+        let names = bundle
+            .files
+            .iter()
+            .map(|f| f.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["fmt_helpers.zn", "utils.zn", "main.zn"]);
+        assert!(bundle
+            .program
+            .items
+            .contains("fn greet(name: zinc_std::json::Value)"));
+        assert_eq!(
+            bundle.program.main,
+            "println!(\"{}\", zinc_std::fmt::show(&greet(zinc_std::json::from(\"zinc\"))));"
+        );
+
+        let err = transpile_file(&dir.join("a.zn")).err().map(|e| e.message);
+        assert_eq!(err.as_deref(), Some("Import cycle: a.zn -> b.zn -> a.zn"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn transpile_fn_params_passed_to_std_calls() {
+        let input =
+            "fn save(url, row) {\nstore.save(\"pages\", row)\nspider.get(url, \"chrome\")\n}";
+        let program = transpile_program(input).unwrap_or_default();
+        assert_eq!(
+            program.items,
+            "fn save(url: zinc_std::json::Value, row: zinc_std::json::Value) {\nzinc_std::store::save(\"pages\", &row);zinc_std::spider::get(zinc_std::json::as_str(&url), Some(\"chrome\"));}\n"
+        );
+    }
+
+    #[test]
+    fn top_level_statements_with_main_is_an_error() {
+        let input = "print(1)\nfn main() { print(2) }";
+        let err = transpile_with_error(input)
+            .err()
+            .map(|e| (e.line, e.message));
+        assert_eq!(
+            err,
+            Some((
+                1,
+                "Top-level statement found alongside `fn main`".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn transpile_await_blocks_outside_async() {
+        let input = "let page = await spider.get_async(url)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let page = zinc_std::block_on(zinc_std::spider::get_async(url, None));"
+        );
+    }
+
+    #[test]
+    fn transpile_async_fn_awaits() {
+        let input = "async fn fetch() { let page = await spider.get_async(\"u\") }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "async fn fetch() {\nlet page = zinc_std::spider::get_async(\"u\", None).await;}\n"
+        );
+    }
+
+    #[test]
+    fn decode_source_transcodes_utf16_and_crlf() {
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "print(1)\r\n".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(decode_source(&bytes), "print(1)\n");
+        assert_eq!(decode_source(&[b'"', 0xE9, b'"']), "\"\u{e9}\"");
+    }
+
+    #[test]
+    fn crlf_source_reports_editor_positions() {
+        let input = "print(1)\r\n)\r\n";
+        let err = transpile_with_error(input)
+            .err()
+            .map(|e| (e.line, e.column));
+        assert_eq!(err, Some((2, 1)));
+    }
+
+    #[test]
+    fn incremental_parser_reparses_only_the_edited_statement() {
+        let body = (1..=10)
+            .map(|i| format!("    print({})\n", i))
+            .collect::<String>();
+        let before = format!("fn main() {{\n{}}}\n", body);
+        let mut parser = IncrementalParser::new();
+        assert!(parser.update(&before).is_ok());
+
+        let after = before.replace("print(5)", "print(55)");
+        assert!(parser.update(&after).is_ok());
+        assert!(parser.last_reparsed_bytes() < after.len() / 2);
+        assert_eq!(parser.statement_ranges(), vec![(0, after.len() - 1)]);
+
+        let broken = after.replace("print(55)", "print(55");
+        assert!(parser.update(&broken).is_err());
+        assert!(parser.update(&after).is_ok());
+    }
+
+    #[test]
+    fn incremental_parser_rechecks_deleted_closing_brace() {
+        let before = "fn main() {\n    if ok {\n        print(1)\n    }\n}\n";
+        let mut parser = IncrementalParser::new();
+        assert!(parser.update(before).is_ok());
+
+        let broken = before.replacen("    }\n", "\n", 1);
+        assert!(parser.update(&broken).is_err());
+        assert!(parser.update(before).is_ok());
+        assert_eq!(parser.statement_ranges(), vec![(0, before.len() - 1)]);
+    }
+
+    #[test]
+    fn transpile_for_over_items() {
+        let input = "for item in items { print(item) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "for item in zinc_std::iter::items(&items) {\nprintln!(\"{}\", zinc_std::fmt::show(&item));}"
+        );
+    }
+
+    #[test]
+    fn transpile_for_over_array_literal_and_query_rows() {
+        let input = "for n in [1, 2, 3] { print(n) }\nfor row in db.query(url, \"SELECT 1\") { print(row) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "for n in zinc_std::iter::items(&vec![1, 2, 3]) {\nprintln!(\"{}\", zinc_std::fmt::show(&n));}for row in zinc_std::iter::items(&zinc_std::db::query(url, \"SELECT 1\")) {\nprintln!(\"{}\", zinc_std::fmt::show(&row));}"
+        );
+    }
+
+    #[test]
+    fn transpile_chained_std_calls() {
+        let input = "let b = json.parse(x).get(\"a\").get(\"b\")\nlet n = json.parse(x).len()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let b = zinc_std::json::get(&zinc_std::json::get(&zinc_std::json::parse(x), \"a\"), \"b\");let n = zinc_std::list::len(&zinc_std::json::parse(x));"
+        );
+    }
+
+    #[test]
+    fn transpile_pipeline_placeholder() {
+        let input = "let page = \"chrome\" |> spider.get(url, _)\nlet n = count |> clamp(0, _, 10)\nlet first = rows |> pick(0)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let page = zinc_std::spider::get(url, Some(\"chrome\"));let n = clamp(0, count, 10);let first = pick(rows, 0);"
+        );
+    }
+
+    #[test]
+    fn transpile_ranges() {
+        let input = "for i in 0..10 { print(i) }\nlet xs = 0..=n |> collect()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "for i in zinc_std::iter::items(&(0..10)) {\nprintln!(\"{}\", zinc_std::fmt::show(&i));}let xs = zinc_std::iter::items((0..=n)).collect::<Vec<_>>();"
+        );
+    }
+
+    #[test]
+    fn transpile_for_over_entries() {
+        let input = "for key, val in obj { print(key) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "for (key, val) in zinc_std::iter::entries(&obj) {\nprintln!(\"{}\", zinc_std::fmt::show(&key));}"
+        );
+    }
+
+    #[test]
+    fn transpile_while_loop() {
+        let input = "while page < last { print(page) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "while zinc_std::ops::lt(&page, &last) {\nprintln!(\"{}\", zinc_std::fmt::show(&page));}"
+        );
+    }
+
+    #[test]
+    fn transpile_continue_and_loop_labels() {
+        let input = "outer: for row in rows {\nfor cell in row {\nif cell == nil { continue outer }\nif cell == 0 { continue }\nbreak outer\n}\n}\nloop { break }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "'outer: for row in zinc_std::iter::items(&rows) {\nfor cell in zinc_std::iter::items(&row) {\nif (cell == zinc_std::json::Value::Null) {\ncontinue 'outer;}if (cell == 0) {\ncontinue;}break 'outer;}}loop {\nbreak;}"
+        );
+    }
+
+    #[test]
+    fn transpile_boolean_logic_precedence() {
+        let input = "let ok = not a == b and ready or false";
+        let output = transpile(input);
+        assert_eq!(output, "let ok = ((!(a == b) && ready) || false);");
+    }
+
+    #[test]
+    fn transpile_arithmetic_precedence() {
+        let input = "let total = price * qty + shipping - 1\nlet label = \"n=\" + n % 2";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let total = zinc_std::ops::sub(zinc_std::ops::add(zinc_std::ops::mul(price, qty), shipping), 1);let label = format!(\"{}{}\", \"n=\", zinc_std::ops::Text::text(&zinc_std::ops::rem(n, 2)));"
+        );
+    }
+
+    #[test]
+    fn transpile_string_interpolation() {
+        let input = "fn show(n, total) {\nprint(\"Count: {n}\")\nlet label = \"{total + 1} items, {{raw}}\"\n}";
+        let output = transpile(input);
+        assert!(output.contains("println!(\"{}\", format!(\"Count: {}\", n));"));
+        assert!(output
+            .contains("let label = format!(\"{} items, {{raw}}\", zinc_std::ops::add(total, 1));"));
+    }
+
+    #[test]
+    fn unbound_names_stay_literal_in_strings() {
+        let input = "let id = 1\nprint(\"GET /users/{id}/posts/{post_id}\")";
+        let output = transpile(input);
+        assert!(output.contains("format!(\"GET /users/{}/posts/{{post_id}}\", id)"));
+    }
+
+    #[test]
+    fn transpile_else_if_chain() {
+        let input = "if x > 10 { print(\"big\") } else if x > 5 { print(\"medium\") } else { print(\"small\") }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "if zinc_std::ops::gt(&x, &10) {\nprintln!(\"{}\", \"big\");} else if zinc_std::ops::gt(&x, &5) {\nprintln!(\"{}\", \"medium\");} else {\nprintln!(\"{}\", \"small\");}"
+        );
+    }
+
+    #[test]
+    fn transpile_inspect() {
+        let input = "inspect(json.parse(page))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::inspect::print(&zinc_std::json::parse(page));"
+        );
+    }
+
+    #[test]
+    fn transpile_print_table() {
+        let input = "print_table(rows)\nprint_table(rows, {\"max_width\": 20})";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::table::print(&rows, &zinc_std::json::Value::Null);zinc_std::table::print(&rows, &zinc_std::json::object(vec![(\"max_width\", zinc_std::json::from(20))]));"
+        );
+    }
+
+    #[test]
+    fn transpile_cli_flags() {
+        let input = "let opts = cli.flags({\"pages\": 5})";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let opts = zinc_std::cli::flags(&zinc_std::json::object(vec![(\"pages\", zinc_std::json::from(5))]));"
+        );
+    }
+
+    #[test]
+    fn transpile_progress_builtin() {
+        let input = "let bar = progress(100)\nbar.tick()\nbar.finish()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let bar = zinc_std::progress::new(100);bar.tick();bar.finish();"
+        );
+        let output = transpile("let bar = progress(urls.len())");
+        assert_eq!(
+            output,
+            "let bar = zinc_std::progress::new(zinc_std::list::len(&urls));"
+        );
+    }
+
+    #[test]
+    fn transpile_retry_builtin() {
+        let input = "let rows = retry(3, 0.5, || db.query(url, sql))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let rows = zinc_std::retry(3, 0.5, || zinc_std::db::query(url, sql));"
+        );
+    }
+
+    #[test]
+    fn transpile_timeout_builtin() {
+        let input = "let rows = timeout(5, || db.query(url, sql))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let rows = zinc_std::timeout(5, move || zinc_std::db::query(url, sql));"
+        );
+    }
+
+    #[test]
+    fn transpile_timeout_copies_variables() {
+        let input = "let url = \"app.db\"\nlet rows = timeout(2.5, || db.query(url, \"select 1\"))\nprint(url)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let url = \"app.db\";let rows = zinc_std::timeout(2.5, { let url = url.clone(); move || zinc_std::db::query(url, \"select 1\") });println!(\"{}\", zinc_std::fmt::show(&url));"
+        );
+    }
+
+    #[test]
+    fn transpile_on_exit_hook() {
+        let input = "on_exit(|| { fs.write(\"partial.json\", \"[]\") })";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::on_exit(move || {\nzinc_std::fs::write(\"partial.json\", \"[]\")});"
+        );
+    }
+
+    #[test]
+    fn transpile_on_exit_hook_copies_variables() {
+        let input = "let pages = [1, 2]\non_exit(|| { print(pages.len()) })\nprint(pages)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let pages = vec![1, 2];zinc_std::on_exit({ let pages = pages.clone(); move || {\nprintln!(\"{}\", zinc_std::fmt::show(&zinc_std::list::len(&pages)))} });println!(\"{}\", zinc_std::fmt::show(&pages));"
+        );
+    }
+
+    #[test]
+    fn changing_a_variable_after_its_exit_hook_is_an_error() {
+        let input = "let pages = []\non_exit(|| { print(pages) })\npages.push(1)";
+        let err = transpile_with_error(input)
+            .err()
+            .map(|e| (e.line, e.message));
+        assert_eq!(
+            err,
+            Some((
+                3,
+                "`pages` changes after an `on_exit` hook that uses it is registered".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn transpile_try_catch() {
+        let input = "try { let page = spider.get(url)\nlet data = json.parse(page) } catch e { print(e.message()) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "if let Err(e) = (|| -> Result<(), zinc_std::Error> {\nlet page = zinc_std::spider::try_get(url, None)?;let data = zinc_std::json::try_parse(page)?;Ok(())\n})() {\nprintln!(\"{}\", zinc_std::fmt::show(&e.message()));}"
+        );
+    }
+
+    #[test]
+    fn transpile_try_catch_invalid_selector() {
+        let input = "try { let titles = html.select(page, \"h1[\") } catch e { print(e.kind()) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "if let Err(e) = (|| -> Result<(), zinc_std::Error> {\nlet titles = zinc_std::html::try_select_text(page, \"h1[\")?;Ok(())\n})() {\nprintln!(\"{}\", zinc_std::fmt::show(&e.kind()));}"
+        );
+    }
+
+    #[test]
+    fn jumping_out_of_try_is_an_error() {
+        let input = "for url in urls {\ntry {\nlet page = spider.get(url)\nif page == \"\" { continue }\n} catch e { print(e.message()) }\n}";
+        let err = transpile_with_error(input)
+            .err()
+            .map(|e| (e.line, e.message));
+        assert_eq!(
+            err,
+            Some((4, "`continue` cannot jump out of a `try` block".to_string()))
+        );
+
+        let input =
+            "fn fetch(url) {\ntry {\nreturn spider.get(url)\n} catch e { print(e.message()) }\n}";
+        let err = transpile_with_error(input)
+            .err()
+            .map(|e| (e.line, e.message));
+        assert_eq!(
+            err,
+            Some((3, "`return` cannot jump out of a `try` block".to_string()))
+        );
+    }
+
+    #[test]
+    fn loops_inside_try_may_break() {
+        let input =
+            "try {\nfor u in urls { if u == \"\" { break } }\n} catch e { print(e.message()) }";
+        assert!(transpile_with_error(input).is_ok());
+    }
+
+    #[test]
+    fn transpile_nil_and_coalesce() {
+        let input = "let title = json.get(page, \"title\") ?? \"untitled\"\nlet html = spider.get(url, nil)\nlet gone = json.get(page, \"id\") == nil";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let title = zinc_std::ops::coalesce(zinc_std::json::get(&page, \"title\"), \"untitled\");let html = zinc_std::spider::get(url, None);let gone = (zinc_std::json::get(&page, \"id\") == zinc_std::json::Value::Null);"
+        );
+    }
+
+    #[test]
+    fn transpile_compound_assignment() {
+        let input = "let count = 0\nlet step = 2\nfor x in 0..10 { count += step }\ncount *= 3";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let mut count = zinc_std::json::from(0);let step = 2;for x in zinc_std::iter::items(&(0..10)) {\ncount = zinc_std::ops::add(count, step);}count = zinc_std::ops::mul(count, 3);"
+        );
+        let output = transpile("let xs = [1, 2]\nxs[0] -= 1");
+        assert_eq!(
+            output,
+            "let mut xs = vec![1, 2];xs[0 as usize] = zinc_std::ops::sub(xs[0 as usize].clone(), 1);"
+        );
+        let output = transpile("let s = \"a\"\ns += \"b\"\ns = \"c\"");
+        assert_eq!(
+            output,
+            "let mut s = String::from(\"a\");s = zinc_std::ops::add(s, \"b\");s = zinc_std::ops::Text::text(&\"c\");"
+        );
+    }
+
+    #[test]
+    fn keywords_are_not_assignable() {
+        assert!(transpile_with_error("let = 1").is_err());
+        assert!(transpile_with_error("while = 2").is_err());
+        assert_eq!(transpile("lettuce = 1"), "lettuce = 1;");
+    }
+
+    #[test]
+    fn transpile_reassignment() {
+        let input =
+            "let page = 1\nlet last = 9\nwhile page < last { page = page + 1 }\nlast == page";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let mut page = 1;let last = 9;while zinc_std::ops::lt(&page, &last) {\npage = zinc_std::ops::add(page, 1);}(last == page);"
+        );
+    }
+
+    #[test]
+    fn transpile_float_literals() {
+        let input = "let pi = 3.14\nlet big = 6.02e23\nlet area = pi * r * 2.0\nfor i in 0..10 { print(i) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let pi = 3.14;let big = 6.02e23;let area = zinc_std::ops::mul(zinc_std::ops::mul(pi, r), 2.0);for i in zinc_std::iter::items(&(0..10)) {\nprintln!(\"{}\", zinc_std::fmt::show(&i));}"
+        );
+    }
+
+    #[test]
+    fn transpile_unary_operators() {
+        let input = "let x = -5\nlet y = -(a + b) * 2\nlet z = x - -y\nlet ok = !done and a != b";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let x = -5;let y = zinc_std::ops::mul(-(zinc_std::ops::add(a, b)), 2);let z = zinc_std::ops::sub(x, -y);let ok = (!done && (a != b));"
+        );
+    }
+
+    #[test]
+    fn transpile_triple_quoted_strings() {
+        let input = "let sql = \"\"\"\nSELECT \"name\"\nFROM t WHERE a = '{x}'\n\"\"\"\nprint(\"\"\"say \"#hi\" now\"\"\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let sql = r#\"\nSELECT \"name\"\nFROM t WHERE a = '{x}'\n\"#;println!(\"{}\", r##\"say \"#hi\" now\"##);"
+        );
+    }
+
+    #[test]
+    fn transpile_string_escapes() {
+        let input = "print(\"a\\tb\\n\\\"q\\\" \\u{e9} C:\\dir \\\\\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "println!(\"{}\", \"a\\tb\\n\\\"q\\\" é C:\\\\dir \\\\\");"
+        );
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let input = "let a = 1 // trailing\n/* block\n{ comment } */ print(a) # hash\n//// banner\n/// Doc\nfn f() { print(2) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "fn f() {\nprintln!(\"{}\", zinc_std::fmt::show(&2));}\nlet a = 1;println!(\"{}\", zinc_std::fmt::show(&a));"
+        );
+        let docs = function_docs(input).ok().unwrap_or_default();
+        assert_eq!(docs[0].doc, "Doc");
+    }
+
+    #[test]
+    fn transpile_fmt_builtin() {
+        let input = "let line = fmt(\"px {:.2} | {:>8}\", price, name)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let line = zinc_std::fmt::format(&\"px {:.2} | {:>8}\", &[zinc_std::fmt::arg(&price), zinc_std::fmt::arg(&name)]);"
+        );
+    }
+
+    #[test]
+    fn function_docs_from_comments_and_docstring() {
+        let input =
+            "/// Fetches a page.\nfn fetch(url) { \"Uses the chrome profile.\" spider.get(url) }";
+        let docs = function_docs(input).ok().unwrap_or_default();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].name, "fetch");
+        assert_eq!(docs[0].params, vec!["url".to_string()]);
+        assert_eq!(docs[0].doc, "Fetches a page.\nUses the chrome profile.");
+        assert_eq!(
+            transpile(input),
+            "fn fetch(url: zinc_std::json::Value) {\nzinc_std::spider::get(zinc_std::json::as_str(&url), None);}\n"
+        );
+    }
+
+    #[test]
+    fn transpile_oauth2_bearer_header() {
+        let input = "let api = auth.oauth2(token_url, id, secret, [\"read\"])\nspider.get_with_headers(url, {\"Authorization\": auth.header(api)})";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let api = zinc_std::auth::oauth2(token_url, id, secret, &vec![\"read\"]);zinc_std::spider::get_with_headers(url, &zinc_std::json::object(vec![(\"Authorization\", zinc_std::json::from(api.header()))]), None);"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_session_cookies() {
+        let input =
+            "spider.cookies_load(\"shop\", \"cookies.txt\")\nspider.session_get(\"shop\", url)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::cookies_load(\"shop\", \"cookies.txt\");zinc_std::spider::session_get(\"shop\", url, None);"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_get_all_with_per_host_limit() {
+        let input = "spider.get_all(urls, \"chrome\", 2, 16)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::get_all_with_limits(&urls, Some(\"chrome\"), 2, 16);"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_upload_with_maps() {
+        let input = "spider.upload(url, {\"title\": t}, {\"file\": path})";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::upload(url, &zinc_std::json::object(vec![(\"title\", zinc_std::json::from(t))]), &zinc_std::json::object(vec![(\"file\", zinc_std::json::from(path))]));"
+        );
+    }
+
+    #[test]
+    fn transpile_html_text_extraction() {
+        let input = "let body = html.to_text(page)\nlet plain = html.strip_tags(snippet)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let body = zinc_std::html::to_text(&page);let plain = zinc_std::html::strip_tags(&snippet);"
+        );
+    }
+
+    #[test]
+    fn transpile_html_article() {
+        let input = "let story = html.article(page)\nprint(json.get(story, \"title\"))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let story = zinc_std::html::article(&page);println!(\"{}\", zinc_std::fmt::show(&zinc_std::json::get(&story, \"title\")));"
+        );
+    }
+
+    #[test]
+    fn transpile_parquet_round_trip() {
+        let input =
+            "parquet.write(\"items.parquet\", rows)\nlet back = parquet.read(\"items.parquet\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::parquet::write(\"items.parquet\", &rows);let back = zinc_std::parquet::read(\"items.parquet\");"
+        );
+    }
+
+    #[test]
+    fn transpile_encoding_convert() {
+        let input = "let raw = fs.read_bytes(\"legacy.txt\")\nfs.write_bytes(\"out.txt\", encoding.convert(raw, \"auto\", \"utf-8\"))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let raw = zinc_std::fs::read_bytes(&\"legacy.txt\");zinc_std::fs::write_bytes(&\"out.txt\", &zinc_std::encoding::convert(&raw, &\"auto\", &\"utf-8\"));"
+        );
+    }
+
+    #[test]
+    fn transpile_fuzzy_best_match() {
+        let input = "let hit = fuzzy.best_match(name, known)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let hit = zinc_std::fuzzy::best_match(&name, &known);"
+        );
+    }
+
+    #[test]
+    fn transpile_text_helpers() {
+        let input = "let code = text.lang(body)\nlet n = text.words(body)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let code = zinc_std::text::lang(&body);let n = zinc_std::text::words(&body);"
+        );
+    }
+
+    #[test]
+    fn transpile_parse_helpers() {
+        let input = "let cost = parse.price(\"$1,299.99\")\nlet seen = parse.date(ago)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let cost = zinc_std::parse::price(&\"$1,299.99\");let seen = zinc_std::parse::date(&ago);"
+        );
+    }
+
+    #[test]
+    fn transpile_limits_set() {
+        let input = "limits.set({\"max_requests\": 500, \"max_runtime_secs\": 60})";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::limits::set(&zinc_std::json::object(vec![(\"max_requests\", zinc_std::json::from(500)), (\"max_runtime_secs\", zinc_std::json::from(60))]));"
+        );
+    }
+
+    #[test]
+    fn transpile_state_save_and_load() {
+        let input = "state.save(\"crawl.state\", {\"page\": page})\nlet resume = state.load(\"crawl.state\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::state::save(&\"crawl.state\", &zinc_std::json::object(vec![(\"page\", zinc_std::json::from(page))]));let resume = zinc_std::state::load(&\"crawl.state\");"
+        );
+    }
+
+    #[test]
+    fn transpile_xlsx_write_and_read() {
+        let input = "xlsx.write(\"report.xlsx\", {\"Prices\": rows})\nlet book = xlsx.read(\"report.xlsx\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::xlsx::write(&\"report.xlsx\", &zinc_std::json::object(vec![(\"Prices\", zinc_std::json::from(rows))]));let book = zinc_std::xlsx::read(&\"report.xlsx\");"
+        );
+    }
+
+    #[test]
+    fn transpile_random() {
+        let input = "let n = random.int(1, 6)\nlet order = random.shuffle(urls)\nrandom.jitter(200, 800)\nlet id = random.uuid()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let n = zinc_std::random::int(1, 6);let order = zinc_std::random::shuffle(&urls);zinc_std::random::jitter(200, 800);let id = zinc_std::random::uuid();"
+        );
+    }
+
+    #[test]
+    fn transpile_log_calls() {
+        let input = "log.set_format(\"json\")\nlog.info(\"fetched\", {\"url\": url})\nlog.metric(\"pages\", count)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::log::set_format(&\"json\");zinc_std::log::info(&\"fetched\", &zinc_std::json::object(vec![(\"url\", zinc_std::json::from(url))]));zinc_std::log::metric(&\"pages\", count);"
+        );
+    }
+
+    #[test]
+    fn transpile_mocks() {
+        let input = "mock.spider(\"https://example.com\", \"<h1>Hi</h1>\")\nmock.db(\"SELECT 1\", [{\"n\": 1}])\nmock.clear()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::mock::spider(&\"https://example.com\", &\"<h1>Hi</h1>\");zinc_std::mock::db(&\"SELECT 1\", &vec![zinc_std::json::object(vec![(\"n\", zinc_std::json::from(1))])]);zinc_std::mock::clear();"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_cassette() {
+        let input = "spider.cassette(\"fixtures/listing.json\")\nlet html = spider.get(url)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::cassette(&\"fixtures/listing.json\");let html = zinc_std::spider::get(url, None);"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_screenshot() {
+        let input = "spider.screenshot(url, \"shot.png\", {\"full_page\": true})";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::screenshot(&url, &\"shot.png\", &zinc_std::json::object(vec![(\"full_page\", zinc_std::json::from(true))]));"
+        );
+    }
+
+    #[test]
+    fn transpile_clipboard_roundtrip() {
+        let input = "clipboard.set(title)\nlet pasted = clipboard.get()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::clipboard::set(&title);let pasted = zinc_std::clipboard::get();"
+        );
+    }
+
+    #[test]
+    fn transpile_notify_slack() {
+        let input = "notify.slack(hook, \"Crawl finished\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::notify::slack(&hook, &\"Crawl finished\");"
+        );
+    }
+
+    #[test]
+    fn transpile_redis_queue_commands() {
+        let input = "redis.lpush(\"jobs\", url)\nlet next = redis.rpop(\"jobs\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::redis::lpush(&\"jobs\", &url);let next = zinc_std::redis::rpop(&\"jobs\");"
+        );
+    }
+
+    #[test]
+    fn transpile_s3_put_and_get() {
+        let input = "s3.put(\"crawls\", \"today.json\", body)\nlet again = s3.get(\"crawls\", \"today.json\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::s3::put(\"crawls\", \"today.json\", &body);let again = zinc_std::s3::get(\"crawls\", \"today.json\");"
+        );
+    }
+
+    #[test]
+    fn transpile_store_save_and_query() {
+        let input = "store.save(\"products\", {\"name\": name, \"price\": 12})\nprint(store.query(\"SELECT * FROM products\"))";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::store::save(\"products\", &zinc_std::json::object(vec![(\"name\", zinc_std::json::from(name)), (\"price\", zinc_std::json::from(12))]));println!(\"{}\", zinc_std::fmt::show(&zinc_std::store::query(\"SELECT * FROM products\")));"
+        );
+    }
+
+    #[test]
+    fn transpile_diff_text_and_json() {
+        let input = "let page = diff.text(before, after)\nlet data = diff.json(old, new)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let page = zinc_std::diff::text(&before, &after);let data = zinc_std::diff::json(&old, &new);"
+        );
+    }
+
+    #[test]
+    fn transpile_feed_parse() {
+        let input = "for entry in feed.parse(spider.get(url)) { print(json.get(entry, \"link\")) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "for entry in zinc_std::iter::items(&zinc_std::feed::parse(&zinc_std::spider::get(url, None))) {\nprintln!(\"{}\", zinc_std::fmt::show(&zinc_std::json::get(&entry, \"link\")));}"
+        );
+    }
+
+    #[test]
+    fn transpile_html_metadata() {
+        let input = "let meta = html.metadata(page)";
+        let output = transpile(input);
+        assert_eq!(output, "let meta = zinc_std::html::metadata(&page);");
+    }
+
+    #[test]
+    fn transpile_html_xpath() {
+        let input = "let prices = html.xpath(page, \"//span[@class='price']/text()\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let prices = zinc_std::html::xpath(&page, \"//span[@class='price']/text()\");"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_get_response_for_challenge() {
+        let input = "let r = spider.get_response(url)\nlet c = spider.is_challenge(r)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let r = zinc_std::spider::get_response(url, None);let c = zinc_std::spider::is_challenge(&r);"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_challenge_match() {
+        let input =
+            "match spider.is_challenge(page) { {\"vendor\": v} => print(v), _ => print(page) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "{\nlet __match = &zinc_std::spider::is_challenge(&page);\nif let Some(v) = zinc_std::json::field(__match, \"vendor\") {\nprintln!(\"{}\", zinc_std::fmt::show(&v));} else {\nprintln!(\"{}\", zinc_std::fmt::show(&page));}\n}"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_protocol_selection() {
+        let input = "spider.protocol(\"h2\")";
+        let output = transpile(input);
+        assert_eq!(output, "zinc_std::spider::set_protocol(\"h2\");");
+    }
+
+    #[test]
+    fn transpile_spider_crawl_profile() {
+        let input = "spider.crawl_profile(\"polite\")";
+        let output = transpile(input);
+        assert_eq!(output, "zinc_std::spider::set_crawl_profile(\"polite\");");
+    }
+
+    #[test]
+    fn transpile_spider_tls_options() {
+        let input = "spider.tls({\"ca\": \"corp.pem\", \"insecure\": true})";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::configure_tls(&zinc_std::json::object(vec![(\"ca\", zinc_std::json::from(\"corp.pem\")), (\"insecure\", zinc_std::json::from(true))]));"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_dns_overrides() {
+        let input = "spider.resolve(\"example.com\", \"10.0.0.5\")\nspider.doh(\"https://1.1.1.1/dns-query\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::resolve(\"example.com\", \"10.0.0.5\");zinc_std::spider::use_doh(\"https://1.1.1.1/dns-query\");"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_har_recording() {
+        let input = "spider.har_start()\nspider.get(url)\nspider.har_save(\"crawl.har\")";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::har_start();zinc_std::spider::get(url, None);zinc_std::spider::har_save(\"crawl.har\");"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_cache_toggle() {
+        let input = "spider.cache(\".zinc-cache\")\nspider.cache_off()";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::enable_cache(\".zinc-cache\");zinc_std::spider::disable_cache();"
+        );
+    }
+
+    #[test]
+    fn transpile_spider_stream_with_callback() {
+        let input = "spider.stream(url, |chunk| { print(chunk.len()) })";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "zinc_std::spider::stream(url, None, |chunk| {\nprintln!(\"{}\", zinc_std::fmt::show(&zinc_std::list::len(&chunk)))});"
+        );
+    }
+
+    #[test]
+    fn transpile_defer_to_drop_guard() {
+        let input = "defer fs.remove(tmp)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let __defer_1_1 = zinc_std::defer(|| { zinc_std::fs::remove(tmp); });"
+        );
+    }
+
+    #[test]
+    fn transpile_enum_and_match() {
+        let input = "enum Status { Ok, Blocked }\nmatch s { Status::Ok => print(1), Status::Blocked => print(2) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "#[derive(Debug, Clone, Copy, PartialEq, zinc_std::json::Serialize)]\n#[serde(crate = \"zinc_std::json::serde\")]\nenum Status { Ok, Blocked }\nmatch s {\nStatus::Ok => {\nprintln!(\"{}\", zinc_std::fmt::show(&1));},\nStatus::Blocked => {\nprintln!(\"{}\", zinc_std::fmt::show(&2));}\n}"
+        );
+    }
+
+    #[test]
+    fn transpile_struct_def_and_literal() {
+        let input = "struct Point { x, y }\nlet p = Point { x: 1, y: \"a\" }\nprint(p.x)";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "#[derive(Debug, Clone, PartialEq, zinc_std::json::Serialize)]\n#[serde(crate = \"zinc_std::json::serde\")]\nstruct Point { x: zinc_std::json::Value, y: zinc_std::json::Value }\nimpl From<Point> for zinc_std::json::Value {\nfn from(value: Point) -> Self { zinc_std::json::to_value(&value) }\n}\nimpl zinc_std::json::Fields for Point {\nfn field(&self, name: &str) -> zinc_std::json::Value { match name { \"x\" => self.x.clone(), \"y\" => self.y.clone(), _ => zinc_std::json::Value::Null } }\nfn field_mut(&mut self, name: &str) -> Option<&mut zinc_std::json::Value> { match name { \"x\" => Some(&mut self.x), \"y\" => Some(&mut self.y), _ => None } }\n}\nlet p = Point { x: zinc_std::json::from(1), y: zinc_std::json::from(\"a\") };println!(\"{}\", zinc_std::fmt::show(&zinc_std::json::path(&p, vec![\"x\".into()])));"
+        );
+    }
+
+    #[test]
+    fn transpile_json_paths_and_index_assignment() {
+        let input = "let obj = json.parse(raw)\nobj[\"name\"] = \"new\"\nobj.stats.count += 1\nprint(obj.name.first)\nlet xs = [1, 2]\nxs[0] = xs[1]";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let mut obj = zinc_std::json::parse(raw);zinc_std::json::set(&mut obj, vec![\"name\".into()], zinc_std::json::from(\"new\"));{ let __value = zinc_std::ops::add(zinc_std::json::path(&obj, vec![\"stats\".into(), \"count\".into()]), 1); zinc_std::json::set(&mut obj, vec![\"stats\".into(), \"count\".into()], zinc_std::json::from(__value)); }println!(\"{}\", zinc_std::fmt::show(&zinc_std::json::path(&obj, vec![\"name\".into(), \"first\".into()])));let mut xs = vec![1, 2];xs[0 as usize] = xs[1 as usize];"
+        );
+    }
+
+    #[test]
+    fn transpile_parallel_block() {
+        let input = "parallel {\nlet a = spider.get(u1)\nlet b = db.query(url, sql)\nprint(\"fetching\")\n}";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let (a, b, _) = std::thread::scope(|__scope| {\nlet __task_0 = __scope.spawn(|| zinc_std::spider::get(u1, None));\nlet __task_1 = __scope.spawn(|| zinc_std::db::query(url, sql));\nlet __task_2 = __scope.spawn(|| {\nprintln!(\"{}\", \"fetching\");});\n(zinc_std::join(__task_0), zinc_std::join(__task_1), zinc_std::join(__task_2))\n});"
+        );
+    }
+
+    #[test]
+    fn transpile_parallel_block_in_async_fn_blocks_on_await() {
+        let input = "async fn fetch() {\nparallel {\nlet a = await spider.get_async(u1)\nlet b = await spider.get_async(u2)\n}\nlet c = await spider.get_async(u3)\n}";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "async fn fetch() {\nlet (a, b) = std::thread::scope(|__scope| {\nlet __task_0 = __scope.spawn(|| zinc_std::block_on(zinc_std::spider::get_async(u1, None)));\nlet __task_1 = __scope.spawn(|| zinc_std::block_on(zinc_std::spider::get_async(u2, None)));\n(zinc_std::join(__task_0), zinc_std::join(__task_1))\n});let c = zinc_std::spider::get_async(u3, None).await;}\n"
+        );
+    }
+
+    #[test]
+    fn method_names_in_parallel_block_are_not_variables() {
+        let input = "parallel {\nlet get = spider.get(u1)\nlet b = spider.get(u2)\n}";
+        assert!(transpile_with_error(input).is_ok());
+    }
+
+    #[test]
+    fn dependent_statements_in_parallel_block_are_an_error() {
+        let input = "parallel {\nlet a = spider.get(u1)\nlet b = spider.get(a)\n}";
+        let err = transpile_with_error(input)
+            .err()
+            .map(|e| (e.line, e.message));
+        assert_eq!(
+            err,
+            Some((
+                3,
+                "`a` is bound by another statement in the same `parallel` block".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn transpile_slices() {
+        let input = "let a = items[1:4]\nlet b = items[2:]\nlet c = rows[0].tags[:-1]";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let a = zinc_std::list::slice(&items, Some(1 as i64), Some(4 as i64));let b = zinc_std::list::slice(&items, Some(2 as i64), None);let c = zinc_std::list::slice(&zinc_std::json::path(&rows, vec![0.into(), \"tags\".into()]), None, Some(-1 as i64));"
+        );
+    }
+
+    #[test]
+    fn non_exhaustive_enum_match_is_an_error() {
+        let input = "enum Status { Ok, Blocked, Retry }\nmatch s { Status::Ok => print(1) }";
+        let err = transpile_with_error(input)
+            .err()
+            .map(|e| e.message)
+            .unwrap_or_default();
+        assert_eq!(
+            err,
+            "Non-exhaustive match on `Status`: missing Blocked, Retry"
+        );
+    }
+
+    #[test]
+    fn transpile_array_spread() {
+        let input = "let all = [...a, ...b, c]";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let all = { let mut __items = Vec::new(); __items.extend(zinc_std::iter::items(&a)); __items.extend(zinc_std::iter::items(&b)); __items.push(c); __items };"
+        );
+    }
+
+    #[test]
+    fn transpile_map_literal() {
+        let input =
+            "let rec = { \"name\": \"zinc\", \"count\": 3, \"tags\": { \"lang\": \"rust\" } }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let rec = zinc_std::json::object(vec![(\"name\", zinc_std::json::from(\"zinc\")), (\"count\", zinc_std::json::from(3)), (\"tags\", zinc_std::json::from(zinc_std::json::object(vec![(\"lang\", zinc_std::json::from(\"rust\"))])))]);"
+        );
+    }
+
+    #[test]
+    fn transpile_map_spread() {
+        let input = "let opts = {...defaults, \"timeout\": 30}";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "let opts = { let mut __map = zinc_std::json::object(Vec::new()); zinc_std::json::merge(&mut __map, &defaults); zinc_std::json::insert(&mut __map, \"timeout\", 30); __map };"
+        );
+    }
+
+    #[test]
+    fn transpile_match_on_json_shape() {
+        let input = "match resp { {\"error\": msg} => print(msg), _ => print(resp) }";
+        let output = transpile(input);
+        assert_eq!(
+            output,
+            "{\nlet __match = &resp;\nif let Some(msg) = zinc_std::json::field(__match, \"error\") {\nprintln!(\"{}\", zinc_std::fmt::show(&msg));} else {\nprintln!(\"{}\", zinc_std::fmt::show(&resp));}\n}"
+        );
+    }
+}
+
+pub fn transpile(source: &str) -> String {
+    match transpile_with_error(source) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("Parse failed: {}", err.message);
+            String::new()
+        }
+    }
+}
+
+pub fn transpile_with_error(source: &str) -> Result<String, ZincError> {
+    transpile_program(source).map(|program| {
+        let mut out = program.items;
+        out.push_str(&program.main);
+        out
+    })
+}
+
+pub fn transpile_program(source: &str) -> Result<Program, ZincError> {
+    let mut items = Writer { buf: String::new() };
+    let mut main = Writer::for_source(source);
+    let normalized = normalize_source(source);
+    let src = normalized.as_str();
+
+    let mut pairs = ZincParser::parse(Rule::program, src).map_err(zinc_error_from_pest)?;
+
+    let program = pairs.next().ok_or_else(|| ZincError {
+        line: 0,
+        column: 0,
+        message: "No statements found".to_string(),
+        suggestion: "Add at least one statement.".to_string(),
+    })?;
+
+    check_enum_matches(program.clone())?;
+    check_entry_point(program.clone())?;
+    check_parallel_blocks(program.clone())?;
+    check_exit_hooks(program.clone())?;
+    check_try_blocks(program.clone())?;
+
+    // Set before interning, which needs to know which strings interpolate.
+    VARIABLES.with(|names| *names.borrow_mut() = bound_names(&program));
+    let interned = intern_literals(&program);
+    for (literal, name) in &interned {
+        let _ = writeln!(items, "const {}: &str = {};", name, literal);
+    }
+    INTERNED.with(|table| *table.borrow_mut() = interned.into_iter().collect());
+    USER_FNS.with(|fns| *fns.borrow_mut() = value_fn_names(&program));
+    REASSIGNED.with(|names| *names.borrow_mut() = reassigned_names(&program));
+    ACCUMULATORS.with(|names| *names.borrow_mut() = accumulator_names(&program));
+
+    let mut saw_statement = false;
+    for pair in program.into_inner() {
+        if pair.as_rule() == Rule::statement {
+            saw_statement = true;
+            let is_item = pair
+                .clone()
+                .into_inner()
+                .next()
+                .is_some_and(|p| match p.as_rule() {
+                    Rule::fn_def => fn_def_name(&p) != "main",
+                    Rule::enum_def | Rule::struct_def | Rule::import_stmt => true,
+                    _ => false,
+                });
+            let stmt_out = transpile_statement(pair);
+            if is_item {
+                items.push(&stmt_out);
+            } else {
+                main.push(&stmt_out);
+            }
+        }
+    }
+    INTERNED.with(|table| table.borrow_mut().clear());
+    USER_FNS.with(|fns| fns.borrow_mut().clear());
+    REASSIGNED.with(|names| names.borrow_mut().clear());
+    ACCUMULATORS.with(|names| names.borrow_mut().clear());
+    VARIABLES.with(|names| names.borrow_mut().clear());
+
+    if !saw_statement {
+        return Err(ZincError {
+            line: 0,
+            column: 0,
+            message: "No statements found".to_string(),
+            suggestion: "Add at least one statement.".to_string(),
+        });
+    }
+
+    Ok(Program {
+        items: items.finish(),
+        main: main.finish(),
+    })
+}
+
+/// Transpiles the file at `path` together with everything it imports,
+/// transitively. Imported files may only define functions, enums and structs.
+pub fn transpile_file(path: &Path) -> Result<Bundle, ZincError> {
+    transpile_file_cached(path, None)
+}
+
+/// [`transpile_file`], reusing results from `cache` when one is given.
+pub fn transpile_file_cached(path: &Path, cache: Option<&Cache>) -> Result<Bundle, ZincError> {
+    let mut sources = Vec::new();
+    load_imports(path, &mut Vec::new(), &mut sources)?;
+
+    // One source, dependencies first, so calls resolve across files.
+    let mut combined = String::with_capacity(sources.iter().map(|(_, t)| t.len() + 1).sum());
+    let mut starts = Vec::new();
+    let mut line = 1;
+    for (_, text) in &sources {
+        starts.push(line);
+        combined.push_str(text);
+        if !text.ends_with('\n') {
+            combined.push('\n');
+        }
+        line += text.matches('\n').count() + usize::from(!text.ends_with('\n'));
+    }
+    let program = match cache {
+        Some(cache) => cache.transpile(&combined),
+        None => transpile_program(&combined),
+    };
+    let program = program.map_err(|mut err| {
+        if let Some(i) = starts.iter().rposition(|&start| start <= err.line) {
+            err.line = err.line - starts[i] + 1;
+            if i + 1 < sources.len() {
+                err = in_file(err, &sources[i].0);
+            }
+        }
+        err
+    })?;
+    Ok(Bundle {
+        program,
+        files: sources.into_iter().map(|(file, _)| file).collect(),
+    })
+}
+
+/// On-disk cache of transpile results keyed by a hash of the source, so
+/// unchanged files are not parsed again by `zn check` or the language server.
+/// Past [`CACHE_MAX_ENTRIES`] files the least recently written are removed.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+enum CacheEntry {
+    Program(Program),
+    Error(ZincError),
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// `$ZINC_CACHE_DIR`, or `~/.cache/zinc`. Setting `ZINC_NO_CACHE`
+    /// turns caching off.
+    pub fn user() -> Option<Self> {
+        if std::env::var_os("ZINC_NO_CACHE").is_some() {
+            return None;
+        }
+        if let Some(dir) = std::env::var_os("ZINC_CACHE_DIR") {
+            return Some(Cache::new(dir));
+        }
+        let home = std::env::var_os("USERPROFILE").or_else(|| std::env::var_os("HOME"))?;
+        Some(Cache::new(PathBuf::from(home).join(".cache").join("zinc")))
+    }
+
+    /// Same as [`transpile_program`], diagnostics included.
+    pub fn transpile(&self, source: &str) -> Result<Program, ZincError> {
+        let path = self.dir.join(format!("{:016x}.json", source_hash(source)));
+        let cached = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+        match cached {
+            Some(CacheEntry::Program(program)) => return Ok(program),
+            Some(CacheEntry::Error(err)) => return Err(err),
+            None => {}
+        }
+
+        let result = transpile_program(source);
+        let entry = match &result {
+            Ok(program) => CacheEntry::Program(program.clone()),
+            Err(err) => CacheEntry::Error(err.clone()),
+        };
+        // A write that fails only costs a miss next time.
+        if let Ok(json) = serde_json::to_vec(&entry) {
+            let _ = self.write(&path, &json);
+            let _ = self.evict();
+        }
+        result
+    }
+
+    // Only runs after a miss, which is when the directory grows.
+    fn evict(&self) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect::<Vec<_>>();
+        if entries.len() <= CACHE_MAX_ENTRIES {
+            return Ok(());
+        }
+        entries.sort();
+        for (_, path) in &entries[..entries.len() - CACHE_MAX_ENTRIES] {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    // Written aside and renamed into place, so concurrent readers never see
+    // half an entry.
+    fn write(&self, path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&temp, contents)?;
+        std::fs::rename(&temp, path)
+    }
+}
+
+/// How many results a [`Cache`] keeps on disk.
+pub const CACHE_MAX_ENTRIES: usize = 512;
+
+// The transpiler itself, so that any change to the grammar or to code
+// generation invalidates the cache, not just version bumps.
+const CODEGEN: [&str; 3] = [
+    env!("CARGO_PKG_VERSION"),
+    include_str!("grammar.pest"),
+    include_str!("lib.rs"),
+];
+
+// FNV-1a, which unlike std's hasher is stable between builds. The transpiler
+// is part of the key so that a new zinc never serves stale output; its part
+// of the hash is the same for every source, so it is computed once.
+fn source_hash(source: &str) -> u64 {
+    static CODEGEN_HASH: OnceLock<u64> = OnceLock::new();
+    let codegen = *CODEGEN_HASH.get_or_init(|| {
+        fnv1a(
+            0xcbf2_9ce4_8422_2325,
+            CODEGEN.iter().flat_map(|part| part.bytes().chain([0])),
+        )
+    });
+    fnv1a(codegen, source.bytes())
+}
+
+fn fnv1a(hash: u64, bytes: impl Iterator<Item = u8>) -> u64 {
+    bytes.fold(hash, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn load_imports(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    loaded: &mut Vec<(PathBuf, String)>,
+) -> Result<(), ZincError> {
+    let key = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if let Some(start) = stack.iter().position(|file| *file == key) {
+        let cycle = stack[start..]
+            .iter()
+            .chain([&key])
+            .map(|file| file.file_name().unwrap_or_default().to_string_lossy())
+            .collect::<Vec<_>>();
+        return Err(ZincError {
+            line: 0,
+            column: 0,
+            message: format!("Import cycle: {}", cycle.join(" -> ")),
+            suggestion: "Move the shared definitions into a file that imports neither.".to_string(),
+        });
+    }
+    if loaded.iter().any(|(file, _)| *file == key) {
+        return Ok(());
+    }
+
+    let source = std::fs::read(path)
+        .map(|bytes| decode_source(&bytes))
+        .map_err(|err| ZincError {
+            line: 0,
+            column: 0,
+            message: format!("Failed to read {}: {}", path.display(), err),
+            suggestion: "Check the path.".to_string(),
+        })?;
+    let imported = !stack.is_empty();
+    let in_this_file = |err: ZincError| if imported { in_file(err, path) } else { err };
+    let imports = file_imports(&source, imported).map_err(in_this_file)?;
+
+    stack.push(key.clone());
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for (line, column, target) in imports {
+        let dep = dir.join(&target);
+        if !dep.is_file() {
+            return Err(in_this_file(ZincError {
+                line,
+                column,
+                message: format!("Cannot find imported file `{}`", target),
+                suggestion: "Import paths are relative to the importing file.".to_string(),
+            }));
+        }
+        load_imports(&dep, stack, loaded)?;
+    }
+    stack.pop();
+    loaded.push((key, source));
+    Ok(())
+}
+
+fn file_imports(source: &str, imported: bool) -> Result<Vec<(usize, usize, String)>, ZincError> {
+    let program = ZincParser::parse(Rule::program, source)
+        .map_err(zinc_error_from_pest)?
+        .next();
+    let mut imports = Vec::new();
+    let statements = program
+        .into_iter()
+        .flat_map(|p| p.into_inner())
+        .filter(|p| p.as_rule() == Rule::statement)
+        .filter_map(|p| p.into_inner().next());
+    for stmt in statements {
+        let (line, column) = stmt.as_span().start_pos().line_col();
+        match stmt.as_rule() {
+            Rule::import_stmt => {
+                let target = match stmt.into_inner().next() {
+                    Some(p) if p.as_rule() == Rule::string => string_value(p.as_str()),
+                    Some(p) => format!("{}.zn", p.as_str()),
+                    None => continue,
+                };
+                imports.push((line, column, target));
+            }
+            Rule::enum_def | Rule::struct_def => {}
+            Rule::fn_def if fn_def_name(&stmt) != "main" => {}
+            _ if imported => {
+                return Err(ZincError {
+                    line,
+                    column,
+                    message: "Imported files may only define functions, enums and structs"
+                        .to_string(),
+                    suggestion: "Move top-level statements into the file that imports this one."
+                        .to_string(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(imports)
+}
+
+fn in_file(mut err: ZincError, path: &Path) -> ZincError {
+    err.message = format!("{}: {}", path.display(), err.message);
+    err
+}
+
+// With an explicit `fn main`, every other top-level statement must be an item;
+// without one, the top-level statements form an implicit main.
+fn check_entry_point(program: Pair<Rule>) -> Result<(), ZincError> {
+    let statements = program
+        .into_inner()
+        .filter(|p| p.as_rule() == Rule::statement)
+        .filter_map(|p| p.into_inner().next())
+        .collect::<Vec<_>>();
+    let has_main = statements
+        .iter()
+        .any(|p| p.as_rule() == Rule::fn_def && fn_def_name(p) == "main");
+    if !has_main {
+        return Ok(());
+    }
+
+    let stray = statements.iter().find(|p| {
+        !matches!(
+            p.as_rule(),
+            Rule::fn_def | Rule::enum_def | Rule::struct_def | Rule::import_stmt
+        )
+    });
+    match stray {
+        Some(stmt) => {
+            let (line, column) = stmt.as_span().start_pos().line_col();
+            Err(ZincError {
+                line,
+                column,
+                message: "Top-level statement found alongside `fn main`".to_string(),
+                suggestion: "Move it into `fn main`, or remove `fn main` to use top-level statements as the entry point.".to_string(),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+fn fn_def_name(pair: &Pair<Rule>) -> String {
+    pair.clone()
+        .into_inner()
+        .find(|p| p.as_rule() == Rule::identifier)
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default()
+}
+
+// Functions taking parameters exchange `json::Value`s, so their call sites
+// convert arguments on the way in.
+fn value_fn_names(program: &Pair<Rule>) -> HashSet<String> {
+    program
+        .clone()
+        .into_inner()
+        .flatten()
+        .filter(|p| p.as_rule() == Rule::fn_def)
+        .filter(|p| {
+            p.clone()
+                .into_inner()
+                .any(|i| i.as_rule() == Rule::param_list)
+        })
+        .map(|p| fn_def_name(&p))
+        .filter(|name| name != "main")
+        .collect()
+}
+
+// Assignment targets, plus receivers of in-place list methods like `xs.push(v)`.
+fn reassigned_names(program: &Pair<Rule>) -> HashSet<String> {
+    program
+        .clone()
+        .into_inner()
+        .flatten()
+        .filter_map(|p| match p.as_rule() {
+            Rule::assign_stmt => p.into_inner().next(),
+            Rule::term => {
+                let mut inner = p.into_inner();
+                let target = inner.next()?.into_inner().next()?;
+                let method = inner.next()?.into_inner().next()?;
+                let mutating = method.as_rule() == Rule::member_suffix
+                    && matches!(
+                        method.into_inner().next()?.as_str(),
+                        "push" | "sort" | "reverse"
+                    );
+                (mutating && target.as_rule() == Rule::identifier).then_some(target)
+            }
+            _ => None,
+        })
+        .map(|name| name.as_str().to_string())
+        .collect()
+}
+
+fn bound_names(pair: &Pair<Rule>) -> HashSet<String> {
+    pair.clone()
+        .into_inner()
+        .flatten()
+        .flat_map(|p| match p.as_rule() {
+            Rule::let_stmt => p.into_inner().take(1).collect(),
+            Rule::for_stmt | Rule::param_list => p
+                .into_inner()
+                .filter(|i| i.as_rule() == Rule::identifier)
+                .collect(),
+            _ => Vec::new(),
+        })
+        .map(|name| name.as_str().to_string())
+        .collect()
+}
+
+// Targets of a compound assignment such as `n /= 2`, mapped to whether a
+// `let` binds them to a string literal.
+fn accumulator_names(program: &Pair<Rule>) -> HashMap<String, bool> {
+    let pairs = program.clone().into_inner().flatten();
+    let mut names: HashMap<String, bool> = pairs
+        .clone()
+        .filter(|p| p.as_rule() == Rule::assign_stmt)
+        .filter_map(|p| {
+            let mut inner = p.into_inner();
+            let name = inner.next()?;
+            let op = inner.next()?;
+            (op.as_rule() == Rule::assign_op && op.as_str().trim() != "=")
+                .then(|| (name.as_str().to_string(), false))
+        })
+        .collect();
+    for stmt in pairs.filter(|p| p.as_rule() == Rule::let_stmt) {
+        let mut inner = stmt.into_inner();
+        let (Some(name), Some(expr)) = (inner.next(), inner.next()) else {
+            continue;
+        };
+        let text = expr
+            .clone()
+            .into_inner()
+            .flatten()
+            .any(|p| p.as_rule() == Rule::string && p.as_str() == expr.as_str().trim());
+        if let Some(is_text) = names.get_mut(name.as_str()) {
+            *is_text |= text;
+        }
+    }
+    names
+}
+
+// Identifiers read as variables: those standing alone as an atom, so not
+// call, method or field names.
+fn variable_refs<'i>(pair: &Pair<'i, Rule>) -> Vec<Pair<'i, Rule>> {
+    pair.clone()
+        .into_inner()
+        .flatten()
+        .filter(|p| p.as_rule() == Rule::atom)
+        .filter_map(|atom| atom.into_inner().next())
+        .filter(|inner| inner.as_rule() == Rule::identifier)
+        .collect()
+}
+
+// Variables from outside an `on_exit` hook that the hook uses.
+fn hook_captures(call: &Pair<Rule>, variables: &HashSet<String>) -> Vec<String> {
+    let Some(hook) = call
+        .clone()
+        .into_inner()
+        .flatten()
+        .find(|p| p.as_rule() == Rule::lambda)
+    else {
+        return Vec::new();
+    };
+    let local = bound_names(&hook);
+    let mut captures = Vec::new();
+    for name in variable_refs(&hook).iter().map(|p| p.as_str().to_string()) {
+        if variables.contains(&name) && !local.contains(&name) && !captures.contains(&name) {
+            captures.push(name);
+        }
+    }
+    captures
+}
+
+fn is_exit_hook(pair: &Pair<Rule>) -> bool {
+    is_call_to(pair, "on_exit")
+}
+
+fn is_call_to(pair: &Pair<Rule>, function: &str) -> bool {
+    pair.as_rule() == Rule::call
+        && pair
+            .clone()
+            .into_inner()
+            .next()
+            .is_some_and(|name| name.as_str() == function)
+}
+
+// A hook gets its own copy of the variables it uses, since it can run from
+// the signal handler while `main` still owns them. Changing one of them after
+// registering would silently not reach the hook, so that is an error.
+fn check_exit_hooks(program: Pair<Rule>) -> Result<(), ZincError> {
+    let variables = bound_names(&program);
+    let sequences = std::iter::once(program.clone()).chain(
+        program
+            .into_inner()
+            .flatten()
+            .filter(|p| p.as_rule() == Rule::block),
+    );
+    for sequence in sequences {
+        let statements = sequence
+            .into_inner()
+            .filter(|p| p.as_rule() == Rule::statement)
+            .collect::<Vec<_>>();
+        for (i, stmt) in statements.iter().enumerate() {
+            let captures = stmt
+                .clone()
+                .into_inner()
+                .flatten()
+                .filter(is_exit_hook)
+                .flat_map(|call| hook_captures(&call, &variables))
+                .collect::<HashSet<_>>();
+            if captures.is_empty() {
+                continue;
+            }
+            for later in &statements[i + 1..] {
+                let changed = reassigned_names(later);
+                if let Some(name) = captures.iter().find(|name| changed.contains(*name)) {
+                    let (line, column) = later.as_span().start_pos().line_col();
+                    return Err(ZincError {
+                        line,
+                        column,
+                        message: format!(
+                            "`{}` changes after an `on_exit` hook that uses it is registered",
+                            name
+                        ),
+                        suggestion: "The hook keeps the value from when it was registered; register it after the last change.".to_string(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Whether a function body returns a value, not counting returns inside
+// lambdas or nested functions.
+fn returns_value(pair: &Pair<Rule>) -> bool {
+    pair.clone()
+        .into_inner()
+        .any(|inner| match inner.as_rule() {
+            Rule::return_stmt => inner.into_inner().next().is_some(),
+            Rule::lambda | Rule::fn_def => false,
+            _ => returns_value(&inner),
+        })
+}
+
+fn intern_literals(program: &Pair<Rule>) -> Vec<(String, String)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for pair in program.clone().into_inner().flatten() {
+        if pair.as_rule() == Rule::string && interpolate(pair.as_str()).is_none() {
+            let literal = rust_string_literal(pair.as_str());
+            if literal.len() >= INTERN_MIN_LEN {
+                let count = counts.entry(literal.clone()).or_insert(0);
+                if *count == 0 {
+                    order.push(literal);
+                }
+                *count += 1;
+            }
+        }
+    }
+    order
+        .into_iter()
+        .filter(|literal| counts[literal] > 1)
+        .enumerate()
+        .map(|(i, literal)| (literal, format!("__ZN_STR_{}", i)))
+        .collect()
+}
+
+// The statements of a `parallel` block run on separate threads, so none may
+// read what another binds, assign shared variables, or jump out of the block.
+fn check_parallel_blocks(program: Pair<Rule>) -> Result<(), ZincError> {
+    for block in program.into_inner().flatten() {
+        if block.as_rule() != Rule::parallel_stmt {
+            continue;
+        }
+        let statements = block
+            .into_inner()
+            .flat_map(|b| b.into_inner())
+            .filter(|p| p.as_rule() == Rule::statement)
+            .filter_map(|p| p.into_inner().next())
+            .collect::<Vec<_>>();
+        let bound = statements
+            .iter()
+            .map(|stmt| parallel_binding(stmt).unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        for (i, stmt) in statements.iter().enumerate() {
+            for inner in stmt.clone().into_inner().flatten() {
+                let (line, column) = inner.as_span().start_pos().line_col();
+                let error = |message: String, suggestion: &str| ZincError {
+                    line,
+                    column,
+                    message,
+                    suggestion: suggestion.to_string(),
+                };
+                match inner.as_rule() {
+                    Rule::assign_stmt => {
+                        return Err(error(
+                            "Assignment inside a `parallel` block".to_string(),
+                            "Bind each result with `let` and combine them after the block.",
+                        ));
+                    }
+                    Rule::return_stmt
+                    | Rule::break_stmt
+                    | Rule::continue_stmt
+                    | Rule::defer_stmt => {
+                        return Err(error(
+                            "`return`, `break`, `continue` and `defer` cannot be used in a `parallel` block"
+                                .to_string(),
+                            "Move it after the block.",
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            for var in variable_refs(stmt) {
+                let name = var.as_str();
+                if let Some(j) = bound.iter().position(|b| b == name) {
+                    if j != i || stmt.as_rule() != Rule::let_stmt {
+                        let (line, column) = var.as_span().start_pos().line_col();
+                        return Err(ZincError {
+                            line,
+                            column,
+                            message: format!("`{}` is bound by another statement in the same `parallel` block", name),
+                            suggestion: "Statements in a `parallel` block must be independent; move this one after the block.".to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// A `try` body runs in a closure, so it cannot `return` from the function or
+// `break`/`continue` a loop around the `try`.
+fn check_try_blocks(program: Pair<Rule>) -> Result<(), ZincError> {
+    for stmt in program.into_inner().flatten() {
+        if stmt.as_rule() != Rule::try_stmt {
+            continue;
+        }
+        let Some(body) = stmt.into_inner().next() else {
+            continue;
+        };
+        if let Some(jump) = escaping_jump(body, &[], false) {
+            let (line, column) = jump.as_span().start_pos().line_col();
+            let keyword = jump.as_str().split_whitespace().next().unwrap_or_default();
+            return Err(ZincError {
+                line,
+                column,
+                message: format!("`{}` cannot jump out of a `try` block", keyword),
+                suggestion: "Set a variable inside the `try` and act on it after the `catch`."
+                    .to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// The first `return`, `break` or `continue` under `pair` that leaves it;
+// `labels` and `in_loop` describe the loops entered so far.
+fn escaping_jump<'i>(
+    pair: Pair<'i, Rule>,
+    labels: &[&'i str],
+    in_loop: bool,
+) -> Option<Pair<'i, Rule>> {
+    for inner in pair.into_inner() {
+        let escaped = match inner.as_rule() {
+            Rule::lambda | Rule::fn_def => None,
+            Rule::return_stmt => Some(inner),
+            Rule::break_stmt | Rule::continue_stmt => {
+                let inside = match inner.clone().into_inner().next() {
+                    Some(label) => labels.contains(&label.as_str()),
+                    None => in_loop,
+                };
+                (!inside).then_some(inner)
+            }
+            Rule::for_stmt | Rule::while_stmt | Rule::loop_stmt => {
+                let mut labels = labels.to_vec();
+                labels.extend(
+                    inner
+                        .clone()
+                        .into_inner()
+                        .find(|p| p.as_rule() == Rule::loop_label)
+                        .and_then(|label| label.into_inner().next())
+                        .map(|name| name.as_str()),
+                );
+                escaping_jump(inner, &labels, true)
+            }
+            _ => escaping_jump(inner, labels, in_loop),
+        };
+        if escaped.is_some() {
+            return escaped;
+        }
+    }
+    None
+}
+
+fn parallel_binding(stmt: &Pair<Rule>) -> Option<String> {
+    if stmt.as_rule() != Rule::let_stmt {
+        return None;
+    }
+    stmt.clone()
+        .into_inner()
+        .next()
+        .map(|name| name.as_str().to_string())
+}
+
+fn check_enum_matches(program: Pair<Rule>) -> Result<(), ZincError> {
+    let mut enums: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in program.clone().into_inner().flatten() {
+        if pair.as_rule() == Rule::enum_def {
+            let mut names = pair.into_inner().map(|p| p.as_str().to_string());
+            if let Some(name) = names.next() {
+                enums.insert(name, names.collect());
+            }
+        }
+    }
+
+    for pair in program.into_inner().flatten() {
+        if pair.as_rule() != Rule::match_stmt {
+            continue;
+        }
+        let (line, column) = pair.as_span().start_pos().line_col();
+        let mut matched_enum = None;
+        let mut covered = Vec::new();
+        let mut catch_all = false;
+        for arm in pair.into_inner().filter(|p| p.as_rule() == Rule::match_arm) {
+            let pattern = match arm.into_inner().next().and_then(|p| p.into_inner().next()) {
+                Some(p) => p,
+                None => continue,
+            };
+            match pattern.as_rule() {
+                Rule::enum_path => {
+                    let (p_line, p_column) = pattern.as_span().start_pos().line_col();
+                    let mut parts = pattern.into_inner().map(|p| p.as_str().to_string());
+                    let name = parts.next().unwrap_or_default();
+                    let variant = parts.next().unwrap_or_default();
+                    let variants = enums.get(&name).ok_or_else(|| ZincError {
+                        line: p_line,
+                        column: p_column,
+                        message: format!("Unknown enum `{}`", name),
+                        suggestion: format!("Declare it with `enum {} {{ ... }}`.", name),
+                    })?;
+                    if !variants.contains(&variant) {
+                        return Err(ZincError {
+                            line: p_line,
+                            column: p_column,
+                            message: format!("`{}` has no variant `{}`", name, variant),
+                            suggestion: format!("Use one of: {}.", variants.join(", ")),
+                        });
+                    }
+                    covered.push(variant);
+                    matched_enum = Some(name);
+                }
+                Rule::identifier => catch_all = true,
+                _ => {}
+            }
+        }
+
+        if let (Some(name), false) = (matched_enum, catch_all) {
+            let missing = enums[&name]
+                .iter()
+                .filter(|v| !covered.contains(v))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                return Err(ZincError {
+                    line,
+                    column,
+                    message: format!(
+                        "Non-exhaustive match on `{}`: missing {}",
+                        name,
+                        missing.join(", ")
+                    ),
+                    suggestion: "Add arms for the missing variants or a `_ =>` arm.".to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn function_docs(source: &str) -> Result<Vec<FnDoc>, ZincError> {
+    let normalized = normalize_source(source);
+    let mut pairs = ZincParser::parse(Rule::program, &normalized).map_err(zinc_error_from_pest)?;
+
+    let mut docs = Vec::new();
+    if let Some(program) = pairs.next() {
+        for pair in program.into_inner().flatten() {
+            if pair.as_rule() == Rule::fn_def {
+                docs.push(fn_doc(pair));
+            }
+        }
+    }
+    Ok(docs)
+}
+
+fn fn_doc(pair: Pair<Rule>) -> FnDoc {
+    let line = pair.as_span().start_pos().line_col().0;
+    let mut name = String::new();
+    let mut params = Vec::new();
+    let mut lines = Vec::new();
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::doc_comment => {
+                let text = inner.as_str().trim_start_matches("///");
+                lines.push(
+                    text.strip_prefix(' ')
+                        .unwrap_or(text)
+                        .trim_end()
+                        .to_string(),
+                );
+            }
+            Rule::identifier => name = inner.as_str().to_string(),
+            Rule::param_list => {
+                params = inner.into_inner().map(|p| p.as_str().to_string()).collect();
+            }
+            Rule::block => {
+                if let Some(doc) = block_docstring(&inner) {
+                    lines.push(doc);
+                }
+            }
+            _ => {}
+        }
+    }
+    FnDoc {
+        name,
+        params,
+        doc: lines.join("\n"),
+        line,
+    }
+}
+
+// A string literal standing alone as the first statement of a function body
+// is its docstring, as in Python.
+fn block_docstring(block: &Pair<Rule>) -> Option<String> {
+    let stmt = block.clone().into_inner().next()?;
+    let expr_stmt = stmt.into_inner().next()?;
+    if expr_stmt.as_rule() != Rule::expr_stmt {
+        return None;
+    }
+    let mut terms = expr_stmt.into_inner().next()?.into_inner();
+    let term = terms.next()?;
+    if terms.next().is_some() {
+        return None;
+    }
+    let mut term_inner = term.into_inner();
+    let atom = term_inner.next()?;
+    if term_inner.next().is_some() {
+        return None;
+    }
+    let literal = atom.into_inner().next()?;
+    if literal.as_rule() != Rule::string {
+        return None;
+    }
+    Some(string_value(literal.as_str()).trim().to_string())
+}
+
+/// Decodes raw source bytes: UTF-8 (with or without BOM), UTF-16 (with BOM,
+/// or BOM-less ASCII-range text), falling back to latin-1.
+pub fn decode_source(bytes: &[u8]) -> String {
+    let text = if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(rest).into_owned()
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        decode_utf16(rest, u16::from_le_bytes)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        decode_utf16(rest, u16::from_be_bytes)
+    } else if let Some(little_endian) = guess_utf16(bytes) {
+        if little_endian {
+            decode_utf16(bytes, u16::from_le_bytes)
+        } else {
+            decode_utf16(bytes, u16::from_be_bytes)
+        }
+    } else {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => bytes.iter().map(|&b| b as char).collect(),
+        }
+    };
+    normalize_source(&text)
+}
+
+/// Strips a BOM and turns CRLF / lone CR line endings into LF so reported
+/// line and column numbers match what editors show.
+pub fn normalize_source(source: &str) -> String {
+    let src = source.strip_prefix('\u{feff}').unwrap_or(source);
+    src.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+// Returns Some(true) for little-endian, Some(false) for big-endian.
+fn guess_utf16(bytes: &[u8]) -> Option<bool> {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if even_zeros == 0 && odd_zeros * 2 > units {
+        Some(true)
+    } else if odd_zeros == 0 && even_zeros * 2 > units {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Parses a document repeatedly as it is edited, reusing the parse of every
+/// statement outside the edited region. Only syntax is checked; run
+/// `transpile_with_error` for the full set of diagnostics.
+#[derive(Default)]
+pub struct IncrementalParser {
+    source: String,
+    root: Option<BlockNode>,
+    error: Option<ZincError>,
+    reparsed: usize,
+}
+
+#[derive(Clone)]
+struct StatementNode {
+    start: usize,
+    end: usize,
+    blocks: Vec<BlockNode>,
+}
+
+// `start..end` is the interior of the braces; for the root it is the whole file.
+#[derive(Clone)]
+struct BlockNode {
+    start: usize,
+    end: usize,
+    statements: Vec<StatementNode>,
+}
+
+impl IncrementalParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, source: &str) -> Result<(), ZincError> {
+        let new = normalize_source(source);
+        if self.root.is_none() || self.error.is_some() {
+            return self.full_parse(new);
+        }
+
+        let prefix = common_prefix(&self.source, &new);
+        let suffix = common_suffix(&self.source[prefix..], &new[prefix..]);
+        let old_end = self.source.len() - suffix;
+        let new_end = new.len() - suffix;
+        if prefix == old_end && prefix == new_end {
+            self.reparsed = 0;
+            return Ok(());
+        }
+
+        if self
+            .root
+            .as_ref()
+            .is_some_and(|root| edits_braces(root, prefix, old_end))
+        {
+            return self.full_parse(new);
+        }
+
+        let delta = new_end as isize - old_end as isize;
+        let mut root = match self.root.take() {
+            Some(root) => root,
+            None => return self.full_parse(new),
+        };
+        shift_block(&mut root, old_end, delta);
+
+        match reparse_block(&mut root, &new, prefix, new_end) {
+            Some(reparsed) => {
+                self.source = new;
+                self.root = Some(root);
+                self.reparsed = reparsed;
+                Ok(())
+            }
+            // The edited region does not parse on its own; a full parse gives
+            // the exact error position.
+            None => self.full_parse(new),
+        }
+    }
+
+    /// Byte ranges of the top-level statements in the current document.
+    pub fn statement_ranges(&self) -> Vec<(usize, usize)> {
+        match &self.root {
+            Some(root) => root.statements.iter().map(|s| (s.start, s.end)).collect(),
+            None => Vec::new(),
+        }
     }
 
-    #[test]
-    fn transpile_spider_get_with_profile() {
-        let input = "spider.get(url, profile)";
-        let output = transpile(input);
-        assert_eq!(output, "zinc_std::spider::get(url, Some(profile));");
+    /// Number of bytes handed to the parser by the last `update`.
+    pub fn last_reparsed_bytes(&self) -> usize {
+        self.reparsed
+    }
+
+    fn full_parse(&mut self, source: String) -> Result<(), ZincError> {
+        self.reparsed = source.len();
+        let result = match ZincParser::parse(Rule::program, &source) {
+            Ok(mut pairs) => {
+                let statements = pairs
+                    .next()
+                    .map(|program| build_statements(program, 0))
+                    .unwrap_or_default();
+                self.root = Some(BlockNode {
+                    start: 0,
+                    end: source.len(),
+                    statements,
+                });
+                self.error = None;
+                Ok(())
+            }
+            Err(err) => {
+                let err = zinc_error_from_pest(err);
+                self.root = None;
+                self.error = Some(ZincError {
+                    line: err.line,
+                    column: err.column,
+                    message: err.message.clone(),
+                    suggestion: err.suggestion.clone(),
+                });
+                Err(err)
+            }
+        };
+        self.source = source;
+        result
     }
 }
 
-pub fn transpile(source: &str) -> String {
-    match transpile_with_error(source) {
-        Ok(output) => output,
-        Err(err) => {
-            eprintln!("Parse failed: {}", err.message);
-            String::new()
+fn reparse_block(block: &mut BlockNode, source: &str, start: usize, end: usize) -> Option<usize> {
+    for stmt in block.statements.iter_mut() {
+        for child in stmt.blocks.iter_mut() {
+            if child.start <= start && end <= child.end {
+                return reparse_block(child, source, start, end);
+            }
         }
     }
+
+    // A statement next to the edit may absorb the new text (`x` followed by a
+    // new `+ 1` line), so the neighbours on both sides are reparsed as well.
+    let mut before = block
+        .statements
+        .iter()
+        .take_while(|s| s.end < start)
+        .count();
+    before = before.saturating_sub(1);
+    let mut after = block.statements.len()
+        - block
+            .statements
+            .iter()
+            .rev()
+            .take_while(|s| s.start > end)
+            .count();
+    after = (after + 1).min(block.statements.len()).max(before);
+
+    let region_start = if before > 0 {
+        block.statements[before - 1].end
+    } else {
+        block.start
+    };
+    let region_end = if after < block.statements.len() {
+        block.statements[after].start
+    } else {
+        block.end
+    };
+
+    let pairs = ZincParser::parse(Rule::program, &source[region_start..region_end]).ok()?;
+    let fresh = pairs
+        .into_iter()
+        .next()
+        .map(|program| build_statements(program, region_start))
+        .unwrap_or_default();
+    block.statements.splice(before..after, fresh);
+    Some(region_end - region_start)
 }
 
-pub fn transpile_with_error(source: &str) -> Result<String, ZincError> {
-    let mut output = String::new();
-    let mut src = source;
-    if src.starts_with('\u{feff}') {
-        src = &src[3..];
-    }
+// Whether the edit replaces a `{` or `}` of some block. That changes the
+// nesting, so no single block can be reparsed on its own.
+fn edits_braces(block: &BlockNode, start: usize, end: usize) -> bool {
+    let touched = |pos: usize| start <= pos && pos < end;
+    block
+        .statements
+        .iter()
+        .flat_map(|stmt| stmt.blocks.iter())
+        .filter(|child| child.start <= end && start <= child.end)
+        .any(|child| {
+            touched(child.start - 1) || touched(child.end) || edits_braces(child, start, end)
+        })
+}
 
-    let mut pairs = ZincParser::parse(Rule::program, src).map_err(zinc_error_from_pest)?;
+fn build_statements(program: Pair<Rule>, offset: usize) -> Vec<StatementNode> {
+    program
+        .into_inner()
+        .filter(|p| p.as_rule() == Rule::statement)
+        .map(|p| build_statement(p, offset))
+        .collect()
+}
 
-    let program = pairs.next().ok_or_else(|| ZincError {
-        line: 0,
-        column: 0,
-        message: "No statements found".to_string(),
-        suggestion: "Add at least one statement.".to_string(),
-    })?;
+fn build_statement(pair: Pair<Rule>, offset: usize) -> StatementNode {
+    let span = pair.as_span();
+    let mut blocks = Vec::new();
+    collect_blocks(pair, offset, &mut blocks);
+    StatementNode {
+        start: span.start() + offset,
+        end: span.end() + offset,
+        blocks,
+    }
+}
 
-    let mut saw_statement = false;
-    for pair in program.into_inner() {
-        if pair.as_rule() == Rule::statement {
-            saw_statement = true;
-            let stmt_out = transpile_statement(pair);
-            output.push_str(&stmt_out);
+fn collect_blocks(pair: Pair<Rule>, offset: usize, out: &mut Vec<BlockNode>) {
+    for child in pair.into_inner() {
+        if child.as_rule() == Rule::block {
+            let span = child.as_span();
+            let statements = child
+                .into_inner()
+                .filter(|p| p.as_rule() == Rule::statement)
+                .map(|p| build_statement(p, offset))
+                .collect();
+            out.push(BlockNode {
+                start: span.start() + offset + 1,
+                end: span.end() + offset - 1,
+                statements,
+            });
+        } else {
+            collect_blocks(child, offset, out);
         }
     }
+}
 
-    if !saw_statement {
-        return Err(ZincError {
-            line: 0,
-            column: 0,
-            message: "No statements found".to_string(),
-            suggestion: "Add at least one statement.".to_string(),
-        });
+fn shift_block(block: &mut BlockNode, from: usize, delta: isize) {
+    block.start = shift_pos(block.start, from, delta);
+    block.end = shift_pos(block.end, from, delta);
+    for stmt in block.statements.iter_mut() {
+        stmt.start = shift_pos(stmt.start, from, delta);
+        stmt.end = shift_pos(stmt.end, from, delta);
+        for child in stmt.blocks.iter_mut() {
+            shift_block(child, from, delta);
+        }
+    }
+}
+
+fn shift_pos(pos: usize, from: usize, delta: isize) -> usize {
+    if pos >= from {
+        (pos as isize + delta) as usize
+    } else {
+        pos
     }
+}
+
+fn common_prefix(a: &str, b: &str) -> usize {
+    let mut n = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    while !b.is_char_boundary(n) {
+        n -= 1;
+    }
+    n
+}
 
-    Ok(output)
+fn common_suffix(a: &str, b: &str) -> usize {
+    let mut n = a
+        .bytes()
+        .rev()
+        .zip(b.bytes().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    while !b.is_char_boundary(b.len() - n) {
+        n -= 1;
+    }
+    n
 }
 
 pub fn format_error_json(err: &str) -> String {
@@ -121,10 +2642,21 @@ fn transpile_statement(pair: Pair<Rule>) -> String {
         match inner_pair.as_rule() {
             Rule::expr_stmt => transpile_expr_stmt(inner_pair),
             Rule::let_stmt => transpile_let_stmt(inner_pair),
+            Rule::assign_stmt => transpile_assign_stmt(inner_pair),
             Rule::if_stmt => transpile_if_stmt(inner_pair),
+            Rule::match_stmt => transpile_match_stmt(inner_pair),
+            Rule::for_stmt => transpile_for_stmt(inner_pair),
+            Rule::while_stmt => transpile_while_stmt(inner_pair),
             Rule::loop_stmt => transpile_loop_stmt(inner_pair),
-            Rule::break_stmt => transpile_break_stmt(inner_pair),
+            Rule::break_stmt => transpile_jump("break", inner_pair),
+            Rule::continue_stmt => transpile_jump("continue", inner_pair),
+            Rule::defer_stmt => transpile_defer_stmt(inner_pair),
+            Rule::try_stmt => transpile_try_stmt(inner_pair),
+            Rule::parallel_stmt => transpile_parallel_stmt(inner_pair),
+            Rule::return_stmt => transpile_return_stmt(inner_pair),
             Rule::fn_def => transpile_fn_def(inner_pair),
+            Rule::enum_def => transpile_enum_def(inner_pair),
+            Rule::struct_def => transpile_struct_def(inner_pair),
             _ => String::new(),
         }
     } else {
@@ -133,12 +2665,165 @@ fn transpile_statement(pair: Pair<Rule>) -> String {
 }
 
 fn transpile_fn_def(pair: Pair<Rule>) -> String {
+    let name = fn_def_name(&pair);
+    let is_async = pair
+        .clone()
+        .into_inner()
+        .any(|p| p.as_rule() == Rule::async_kw);
+    let mut params = Vec::new();
+    let mut returns = false;
+    let mut ends_in_return = false;
+    let mut body = String::new();
+    let was_async = IN_ASYNC.with(|flag| flag.replace(is_async));
+    let was_returning = RETURNS_VALUE.with(|flag| flag.get());
+    let was_trying = IN_TRY.with(|flag| flag.replace(false));
+    let mut was_params = HashSet::new();
     for inner in pair.into_inner() {
-        if inner.as_rule() == Rule::block {
-            return transpile_block(inner);
+        match inner.as_rule() {
+            Rule::param_list => {
+                params = inner.into_inner().map(|p| p.as_str().to_string()).collect();
+            }
+            Rule::block => {
+                let value_params = params.iter().cloned().collect();
+                was_params = VALUE_PARAMS.with(|set| set.replace(value_params));
+                returns = name != "main" && returns_value(&inner);
+                ends_in_return = inner
+                    .clone()
+                    .into_inner()
+                    .filter(|p| p.as_rule() == Rule::statement)
+                    .last()
+                    .and_then(|p| p.into_inner().next())
+                    .is_some_and(|p| p.as_rule() == Rule::return_stmt);
+                RETURNS_VALUE.with(|flag| flag.set(returns));
+                body = transpile_fn_body(inner);
+            }
+            _ => {}
         }
     }
-    String::new()
+    IN_ASYNC.with(|flag| flag.set(was_async));
+    RETURNS_VALUE.with(|flag| flag.set(was_returning));
+    IN_TRY.with(|flag| flag.set(was_trying));
+    VALUE_PARAMS.with(|set| *set.borrow_mut() = was_params);
+
+    // `main` is the entry point, so its body is the program itself.
+    if name == "main" {
+        return if is_async {
+            format!("zinc_std::block_on(async {{\n{}}});", body)
+        } else {
+            body
+        };
+    }
+
+    // Parameters and return values are dynamically typed as `json::Value`.
+    let params = params
+        .iter()
+        .map(|p| format!("{}: zinc_std::json::Value", p))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut out = Writer::for_source(&body);
+    if is_async {
+        out.push("async ");
+    }
+    let _ = write!(out, "fn {}({})", name, params);
+    if returns {
+        out.push(" -> zinc_std::json::Value");
+    }
+    let _ = write!(out, " {{\n{}", body);
+    if returns && !ends_in_return {
+        out.push("zinc_std::json::Value::Null\n");
+    }
+    out.push("}\n");
+    out.finish()
+}
+
+fn transpile_return_stmt(pair: Pair<Rule>) -> String {
+    let value = pair
+        .into_inner()
+        .next()
+        .map(transpile_expr)
+        .unwrap_or_default();
+    let returns_value = RETURNS_VALUE.with(|flag| flag.get());
+    match (value.is_empty(), returns_value) {
+        (true, true) => "return zinc_std::json::Value::Null;".to_string(),
+        (true, false) => "return;".to_string(),
+        (false, true) => format!("return zinc_std::json::from({});", value),
+        (false, false) => format!("return {};", value),
+    }
+}
+
+fn transpile_fn_body(block: Pair<Rule>) -> String {
+    if block_docstring(&block).is_some() {
+        return block
+            .into_inner()
+            .skip(1)
+            .filter(|stmt| stmt.as_rule() == Rule::statement)
+            .map(transpile_statement)
+            .collect();
+    }
+    transpile_block(block)
+}
+
+fn transpile_enum_def(pair: Pair<Rule>) -> String {
+    let mut names = pair.into_inner().map(|p| p.as_str().to_string());
+    let name = match names.next() {
+        Some(n) => n,
+        None => return String::new(),
+    };
+    let variants = names.collect::<Vec<_>>();
+    format!(
+        "#[derive(Debug, Clone, Copy, PartialEq, zinc_std::json::Serialize)]\n#[serde(crate = \"zinc_std::json::serde\")]\nenum {} {{ {} }}\n",
+        name,
+        variants.join(", ")
+    )
+}
+
+// Fields hold `json::Value`s, so a struct serializes like the map it replaces.
+fn transpile_struct_def(pair: Pair<Rule>) -> String {
+    let mut names = pair.into_inner().map(|p| p.as_str().to_string());
+    let name = match names.next() {
+        Some(n) => n,
+        None => return String::new(),
+    };
+    let names = names.collect::<Vec<_>>();
+    let fields = names
+        .iter()
+        .map(|field| format!("{}: zinc_std::json::Value", field))
+        .collect::<Vec<_>>();
+    let reads = names
+        .iter()
+        .map(|field| format!("\"{0}\" => self.{0}.clone(), ", field))
+        .collect::<String>();
+    let writes = names
+        .iter()
+        .map(|field| format!("\"{0}\" => Some(&mut self.{0}), ", field))
+        .collect::<String>();
+    format!(
+        "#[derive(Debug, Clone, PartialEq, zinc_std::json::Serialize)]\n\
+         #[serde(crate = \"zinc_std::json::serde\")]\nstruct {name} {{ {} }}\n\
+         impl From<{name}> for zinc_std::json::Value {{\n\
+         fn from(value: {name}) -> Self {{ zinc_std::json::to_value(&value) }}\n}}\n\
+         impl zinc_std::json::Fields for {name} {{\n\
+         fn field(&self, name: &str) -> zinc_std::json::Value {{ match name {{ {reads}_ => zinc_std::json::Value::Null }} }}\n\
+         fn field_mut(&mut self, name: &str) -> Option<&mut zinc_std::json::Value> {{ match name {{ {writes}_ => None }} }}\n}}\n",
+        fields.join(", "),
+    )
+}
+
+fn transpile_struct_lit(pair: Pair<Rule>) -> String {
+    let mut inner = pair.into_inner();
+    let name = inner
+        .next()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    let fields = inner
+        .filter_map(|field| {
+            let mut parts = field.into_inner();
+            let key = parts.next()?.as_str().to_string();
+            let value = parts.next().map(transpile_expr)?;
+            Some(format!("{}: zinc_std::json::from({})", key, value))
+        })
+        .collect::<Vec<_>>();
+    format!("{} {{ {} }}", name, fields.join(", "))
 }
 
 fn transpile_let_stmt(pair: Pair<Rule>) -> String {
@@ -152,13 +2837,80 @@ fn transpile_let_stmt(pair: Pair<Rule>) -> String {
         .map(transpile_expr)
         .unwrap_or_default();
 
+    let accumulator = ACCUMULATORS.with(|names| names.borrow().get(&name).copied());
     if name.is_empty() || expr.is_empty() {
         String::new()
+    } else if let Some(text) = accumulator {
+        if text {
+            format!("let mut {} = String::from({});", name, expr)
+        } else {
+            format!("let mut {} = zinc_std::json::from({});", name, expr)
+        }
+    } else if REASSIGNED.with(|names| names.borrow().contains(&name)) {
+        format!("let mut {} = {};", name, expr)
     } else {
         format!("let {} = {};", name, expr)
     }
 }
 
+fn transpile_assign_stmt(pair: Pair<Rule>) -> String {
+    let mut inner = pair.into_inner().peekable();
+    let name = inner.next().map(|p| p.as_str()).unwrap_or_default();
+    let mut keys = Vec::new();
+    while let Some(suffix) =
+        inner.next_if(|p| matches!(p.as_rule(), Rule::indexing_suffix | Rule::field_suffix))
+    {
+        keys.push(path_key(suffix));
+    }
+    let op = inner.next().map(|p| p.as_str()).unwrap_or_default();
+    let expr = inner.next().map(transpile_expr).unwrap_or_default();
+    if name.is_empty() || expr.is_empty() || keys.iter().any(|(key, _)| key.is_empty()) {
+        return String::new();
+    }
+    let func = match op {
+        "+=" => Some("add"),
+        "-=" => Some("sub"),
+        "*=" => Some("mul"),
+        "/=" => Some("div"),
+        "%=" => Some("rem"),
+        _ => None,
+    };
+    let accumulator = ACCUMULATORS.with(|names| names.borrow().get(name).copied());
+    if let (Some(text), None, true) = (accumulator, func, keys.is_empty()) {
+        return if text {
+            format!("{} = zinc_std::ops::Text::text(&{});", name, expr)
+        } else {
+            format!("{} = zinc_std::json::from({});", name, expr)
+        };
+    }
+    if !keys.iter().any(|(_, named)| *named) {
+        let indexed = !keys.is_empty();
+        let place = transpile_path(name.to_string(), keys);
+        return match func {
+            // Same `zinc_std::ops` semantics as the binary operators.
+            Some(func) if indexed => format!(
+                "{} = zinc_std::ops::{}({}.clone(), {});",
+                place, func, place, expr
+            ),
+            Some(func) => format!("{} = zinc_std::ops::{}({}, {});", place, func, place, expr),
+            None => format!("{} = {};", place, expr),
+        };
+    }
+
+    let path = path_keys(&keys);
+    let Some(func) = func else {
+        return format!(
+            "zinc_std::json::set(&mut {}, {}, zinc_std::json::from({}));",
+            name, path, expr
+        );
+    };
+    // Read first: `set` holds `name` mutably.
+    format!(
+        "{{ let __value = zinc_std::ops::{}(zinc_std::json::path(&{}, {}), {}); zinc_std::json::set(&mut {}, {}, zinc_std::json::from(__value)); }}",
+        func, name, path, expr, name, path
+    )
+}
+
 fn transpile_expr_stmt(pair: Pair<Rule>) -> String {
     let expr_pair = pair.into_inner().next();
     if let Some(expr_pair) = expr_pair {
@@ -183,81 +2935,534 @@ fn transpile_if_stmt(pair: Pair<Rule>) -> String {
         .next()
         .map(transpile_block)
         .unwrap_or_default();
-    let else_block = inner
+    let else_branch = inner.next();
+
+    if condition.is_empty() || then_block.is_empty() {
+        return String::new();
+    }
+
+    // `else if` recurses, so a chain comes out as a flat Rust ladder.
+    let else_part = match else_branch {
+        Some(p) if p.as_rule() == Rule::if_stmt => transpile_if_stmt(p),
+        Some(p) => {
+            let else_block = transpile_block(p);
+            if else_block.is_empty() {
+                String::new()
+            } else {
+                format!("{{\n{}}}", else_block)
+            }
+        }
+        None => String::new(),
+    };
+
+    if else_part.is_empty() {
+        format!("if {} {{\n{}}}", condition, then_block)
+    } else {
+        format!("if {} {{\n{}}} else {}", condition, then_block, else_part)
+    }
+}
+
+fn transpile_match_stmt(pair: Pair<Rule>) -> String {
+    let mut inner = pair.into_inner();
+    let subject = inner.next().map(transpile_expr).unwrap_or_default();
+    if subject.is_empty() {
+        return String::new();
+    }
+
+    let mut arms = Vec::new();
+    let mut enum_arms = Vec::new();
+    let mut fallback = None;
+    for arm in inner {
+        let mut arm_inner = arm.into_inner();
+        let pattern = match arm_inner.next().and_then(|p| p.into_inner().next()) {
+            Some(p) => p,
+            None => continue,
+        };
+        let body = match arm_inner.next() {
+            Some(b) if b.as_rule() == Rule::block => transpile_block(b),
+            Some(e) => {
+                let expr_out = transpile_expr(e);
+                if expr_out.is_empty() {
+                    String::new()
+                } else {
+                    format!("{};", expr_out)
+                }
+            }
+            None => String::new(),
+        };
+        match pattern.as_rule() {
+            Rule::map_pattern => {
+                let condition = transpile_map_pattern(pattern);
+                arms.push(format!("if {} {{\n{}}}", condition, body));
+            }
+            Rule::enum_path => {
+                enum_arms.push(format!(
+                    "{} => {{\n{}}}",
+                    transpile_enum_path(pattern),
+                    body
+                ));
+            }
+            Rule::string | Rule::number | Rule::boolean => {
+                let literal = transpile_atom(pattern);
+                arms.push(format!("if *__match == {} {{\n{}}}", literal, body));
+            }
+            Rule::identifier => {
+                fallback = Some((pattern.as_str().to_string(), body));
+                // Anything after a catch-all arm can never run.
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if !enum_arms.is_empty() {
+        if let Some((name, body)) = fallback {
+            enum_arms.push(format!("{} => {{\n{}}}", name, body));
+        }
+        return format!("match {} {{\n{}\n}}", subject, enum_arms.join(",\n"));
+    }
+
+    let mut chain = arms.join(" else ");
+    if let Some((name, body)) = fallback {
+        let fallback = if name == "_" {
+            body
+        } else {
+            format!("let {} = __match;\n{}", name, body)
+        };
+        if chain.is_empty() {
+            chain = format!("{{\n{}}}", fallback);
+        } else {
+            chain = format!("{} else {{\n{}}}", chain, fallback);
+        }
+    }
+    if chain.is_empty() {
+        return String::new();
+    }
+    format!("{{\nlet __match = &{};\n{}\n}}", subject, chain)
+}
+
+fn transpile_enum_path(pair: Pair<Rule>) -> String {
+    let parts = pair
+        .into_inner()
+        .map(|p| p.as_str().to_string())
+        .collect::<Vec<_>>();
+    parts.join("::")
+}
+
+fn transpile_map_pattern(pair: Pair<Rule>) -> String {
+    let mut bindings = Vec::new();
+    let mut lookups = Vec::new();
+    for field in pair.into_inner() {
+        let mut field_inner = field.into_inner();
+        let key = field_inner
+            .next()
+            .map(|p| transpile_string(p.as_str()))
+            .unwrap_or_default();
+        let value = match field_inner.next() {
+            Some(v) => v,
+            None => continue,
+        };
+        let lookup = format!("zinc_std::json::field(__match, {})", key);
+        if value.as_rule() == Rule::identifier {
+            bindings.push(format!("Some({})", value.as_str()));
+            lookups.push(lookup);
+        } else {
+            bindings.push("Some(_)".to_string());
+            lookups.push(format!(
+                "{}.filter(|v| *v == {})",
+                lookup,
+                transpile_atom(value)
+            ));
+        }
+    }
+
+    match bindings.len() {
+        0 => "__match.is_object()".to_string(),
+        1 => format!("let {} = {}", bindings[0], lookups[0]),
+        _ => format!("let ({}) = ({})", bindings.join(", "), lookups.join(", ")),
+    }
+}
+
+fn transpile_for_stmt(pair: Pair<Rule>) -> String {
+    let label = loop_label(&pair);
+    let mut names = Vec::new();
+    let mut iterable = String::new();
+    let mut body = String::new();
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::identifier => names.push(inner.as_str().to_string()),
+            Rule::expr => iterable = transpile_expr(inner),
+            Rule::block => body = transpile_block(inner),
+            _ => {}
+        }
+    }
+
+    if iterable.is_empty() {
+        return String::new();
+    }
+
+    match names.as_slice() {
+        [item] => format!(
+            "{}for {} in zinc_std::iter::items(&{}) {{\n{}}}",
+            label, item, iterable, body
+        ),
+        [key, val] => format!(
+            "{}for ({}, {}) in zinc_std::iter::entries(&{}) {{\n{}}}",
+            label, key, val, iterable, body
+        ),
+        _ => String::new(),
+    }
+}
+
+fn transpile_while_stmt(pair: Pair<Rule>) -> String {
+    let label = loop_label(&pair);
+    let mut inner = pair
+        .into_inner()
+        .filter(|p| p.as_rule() != Rule::loop_label);
+    let condition = inner.next().map(transpile_expr).unwrap_or_default();
+    let body = inner.next().map(transpile_block).unwrap_or_default();
+    if condition.is_empty() || body.is_empty() {
+        String::new()
+    } else {
+        format!("{}while {} {{\n{}}}", label, condition, body)
+    }
+}
+
+fn transpile_loop_stmt(pair: Pair<Rule>) -> String {
+    let label = loop_label(&pair);
+    let mut inner = pair
+        .into_inner()
+        .filter(|p| p.as_rule() != Rule::loop_label);
+    let body = inner.next().map(transpile_block).unwrap_or_default();
+    if body.is_empty() {
+        String::new()
+    } else {
+        format!("{}loop {{\n{}}}", label, body)
+    }
+}
+
+// Each statement becomes a scoped thread; `let` results come back through the
+// joined tuple and are bound after the block.
+fn transpile_parallel_stmt(pair: Pair<Rule>) -> String {
+    let statements = pair
+        .into_inner()
+        .flat_map(|b| b.into_inner())
+        .filter(|p| p.as_rule() == Rule::statement)
+        .collect::<Vec<_>>();
+    if statements.is_empty() {
+        return String::new();
+    }
+    // `?` inside a task would not reach an enclosing `try`, and a task is a
+    // plain thread, so `await` in it blocks instead of yielding.
+    let was_trying = IN_TRY.with(|flag| flag.replace(false));
+    let was_async = IN_ASYNC.with(|flag| flag.replace(false));
+    let mut tasks = Vec::new();
+    let mut bindings = Vec::new();
+    for (i, stmt) in statements.into_iter().enumerate() {
+        let inner = match stmt.clone().into_inner().next() {
+            Some(inner) => inner,
+            None => continue,
+        };
+        let binding = match parallel_binding(&inner) {
+            Some(name) if REASSIGNED.with(|names| names.borrow().contains(&name)) => {
+                format!("mut {}", name)
+            }
+            Some(name) => name,
+            None => "_".to_string(),
+        };
+        let body = if inner.as_rule() == Rule::let_stmt {
+            inner
+                .into_inner()
+                .nth(1)
+                .map(transpile_expr)
+                .unwrap_or_default()
+        } else {
+            format!("{{\n{}}}", transpile_statement(stmt))
+        };
+        tasks.push(format!("let __task_{} = __scope.spawn(|| {});\n", i, body));
+        bindings.push((binding, format!("zinc_std::join(__task_{})", i)));
+    }
+    IN_TRY.with(|flag| flag.set(was_trying));
+    IN_ASYNC.with(|flag| flag.set(was_async));
+
+    let tuple = |parts: Vec<&str>| match parts.len() {
+        1 => format!("({},)", parts[0]),
+        _ => format!("({})", parts.join(", ")),
+    };
+    format!(
+        "let {} = std::thread::scope(|__scope| {{\n{}{}\n}});",
+        tuple(bindings.iter().map(|(b, _)| b.as_str()).collect()),
+        tasks.concat(),
+        tuple(bindings.iter().map(|(_, j)| j.as_str()).collect()),
+    )
+}
+
+// `break` or `continue`, optionally naming the loop it applies to.
+fn transpile_jump(keyword: &str, pair: Pair<Rule>) -> String {
+    match pair.into_inner().next() {
+        Some(label) => format!("{} '{};", keyword, label.as_str()),
+        None => format!("{};", keyword),
+    }
+}
+
+fn loop_label(pair: &Pair<Rule>) -> String {
+    pair.clone()
+        .into_inner()
+        .find(|p| p.as_rule() == Rule::loop_label)
+        .and_then(|label| label.into_inner().next())
+        .map(|name| format!("'{}: ", name.as_str()))
+        .unwrap_or_default()
+}
+
+fn transpile_defer_stmt(pair: Pair<Rule>) -> String {
+    let (line, column) = pair.as_span().start_pos().line_col();
+    let body = match pair.into_inner().next() {
+        Some(b) if b.as_rule() == Rule::block => transpile_block(b),
+        Some(e) => {
+            let expr_out = transpile_expr(e);
+            if expr_out.is_empty() {
+                String::new()
+            } else {
+                format!("{};", expr_out)
+            }
+        }
+        None => String::new(),
+    };
+    if body.is_empty() {
+        return String::new();
+    }
+    // The guard needs a named binding: `let _ = ...` would drop it immediately.
+    format!(
+        "let __defer_{}_{} = zinc_std::defer(|| {{ {} }});",
+        line, column, body
+    )
+}
+
+// The body runs in an immediately-called closure so that `?` on the `try_*`
+// std variants lands in the `Err` arm.
+fn transpile_try_stmt(pair: Pair<Rule>) -> String {
+    let mut inner = pair.into_inner();
+    let was_trying = IN_TRY.with(|flag| flag.replace(true));
+    let body = inner.next().map(transpile_block).unwrap_or_default();
+    IN_TRY.with(|flag| flag.set(was_trying));
+    let name = inner
         .next()
-        .map(transpile_block)
+        .map(|p| p.as_str().to_string())
         .unwrap_or_default();
+    let handler = inner.next().map(transpile_block).unwrap_or_default();
+    if name.is_empty() {
+        return String::new();
+    }
+    format!(
+        "if let Err({}) = (|| -> Result<(), zinc_std::Error> {{\n{}Ok(())\n}})() {{\n{}}}",
+        name, body, handler
+    )
+}
 
-    if condition.is_empty() || then_block.is_empty() {
+fn transpile_expr(pair: Pair<Rule>) -> String {
+    match pair.as_rule() {
+        Rule::expr => transpile_logic(pair.into_inner().collect()),
+        Rule::term => transpile_term(pair),
+        Rule::unary => transpile_unary(pair),
+        Rule::call => transpile_call(pair),
+        Rule::array => transpile_array(pair),
+        Rule::map => transpile_map(pair),
+        Rule::string => {
+            transpile_string(pair.as_str())
+        }
+        Rule::number => pair.as_str().to_string(),
+        Rule::identifier => pair.as_str().to_string(),
+        _ => String::new(),
+    }
+}
+
+fn transpile_unary(pair: Pair<Rule>) -> String {
+    let mut inner = pair.into_inner();
+    let op = inner.next().map(|p| p.as_str()).unwrap_or_default();
+    let operand = inner.next().map(transpile_expr).unwrap_or_default();
+    if operand.is_empty() {
+        return String::new();
+    }
+    // Literals and plain names take the operator directly; anything else is
+    // parenthesised so it applies to the whole value.
+    if operand
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        format!("{}{}", op, operand)
+    } else {
+        format!("{}({})", op, operand)
+    }
+}
+
+// `or` binds loosest, then `and`, then `not`; the remaining operators are
+// applied left to right.
+fn transpile_logic(parts: Vec<Pair<Rule>>) -> String {
+    transpile_joined(parts, "or", " || ", |and_parts| {
+        transpile_joined(and_parts, "and", " && ", transpile_operands)
+    })
+}
+
+fn transpile_joined<F>(parts: Vec<Pair<Rule>>, keyword: &str, joiner: &str, next: F) -> String
+where
+    F: Fn(Vec<Pair<Rule>>) -> String,
+{
+    let mut groups = vec![Vec::new()];
+    for part in parts {
+        if part.as_rule() == Rule::op && part.as_str() == keyword {
+            groups.push(Vec::new());
+        } else if let Some(group) = groups.last_mut() {
+            group.push(part);
+        }
+    }
+    let outs = groups.into_iter().map(next).collect::<Vec<_>>();
+    if outs.iter().any(String::is_empty) {
         return String::new();
     }
+    if outs.len() == 1 {
+        outs.into_iter().next().unwrap_or_default()
+    } else {
+        format!("({})", outs.join(joiner))
+    }
+}
+
+enum Operand<'i> {
+    Pair(Pair<'i, Rule>),
+    Code(String),
+}
+
+impl Operand<'_> {
+    fn code(self) -> String {
+        match self {
+            Operand::Pair(p) => transpile_expr(p),
+            Operand::Code(code) => code,
+        }
+    }
+}
 
-    if else_block.is_empty() {
-        format!("if {} {{\n{}}}", condition, then_block)
-    } else {
-        format!("if {} {{\n{}}} else {{\n{}}}", condition, then_block, else_block)
+fn precedence(op: &str) -> u8 {
+    match op {
+        "*" | "/" | "%" => 6,
+        "+" | "-" => 5,
+        ".." | "..=" => 4,
+        "??" => 3,
+        "|>" => 2,
+        _ => 1,
     }
 }
 
-fn transpile_loop_stmt(pair: Pair<Rule>) -> String {
-    let mut inner = pair.into_inner();
-    let body = inner.next().map(transpile_block).unwrap_or_default();
-    if body.is_empty() {
-        String::new()
-    } else {
-        format!("loop {{\n{}}}", body)
+fn transpile_operands(parts: Vec<Pair<Rule>>) -> String {
+    let mut inner = parts.into_iter();
+    let mut negations = 0;
+    let first = loop {
+        match inner.next() {
+            Some(p) if p.as_rule() == Rule::not_op => negations += 1,
+            Some(p) => break p,
+            None => return String::new(),
+        }
+    };
+
+    // Shunting-yard over the flat `term (op term)*` list; equal precedence
+    // associates to the left.
+    let mut operands = vec![Operand::Pair(first)];
+    let mut ops: Vec<String> = Vec::new();
+    while let Some(op) = inner.next() {
+        let rhs = match inner.next() {
+            Some(p) if p.as_rule() == Rule::not_op => {
+                // `a == not b` negates everything to its right.
+                let rest = std::iter::once(p).chain(inner.by_ref()).collect();
+                Operand::Code(transpile_operands(rest))
+            }
+            Some(p) => Operand::Pair(p),
+            None => break,
+        };
+        let op = op.as_str().to_string();
+        while ops
+            .last()
+            .is_some_and(|top| precedence(top) >= precedence(&op))
+        {
+            reduce(&mut operands, &mut ops);
+        }
+        ops.push(op);
+        operands.push(rhs);
     }
+    while !ops.is_empty() {
+        reduce(&mut operands, &mut ops);
+    }
+    let current = operands.pop().map(Operand::code).unwrap_or_default();
+    format!("{}{}", "!".repeat(negations), current)
 }
 
-fn transpile_break_stmt(_pair: Pair<Rule>) -> String {
-    "break;".to_string()
+fn reduce(operands: &mut Vec<Operand>, ops: &mut Vec<String>) {
+    let (Some(op), Some(rhs), Some(lhs)) = (ops.pop(), operands.pop(), operands.pop()) else {
+        return;
+    };
+    let combined = match (op.as_str(), rhs) {
+        ("|>", Operand::Pair(term)) => transpile_pipeline(lhs.code(), term),
+        ("|>", Operand::Code(func)) => format!("{}({})", func, lhs.code()),
+        (other, rhs) => apply_binary_op(lhs.code(), other, rhs.code()),
+    };
+    operands.push(Operand::Code(combined));
 }
 
-fn transpile_expr(pair: Pair<Rule>) -> String {
-    match pair.as_rule() {
-        Rule::expr => {
-            let mut inner = pair.into_inner();
-            let mut current = match inner.next() {
-                Some(p) => transpile_expr(p),
-                None => return String::new(),
-            };
-            while let Some(op) = inner.next() {
-                let rhs_pair = match inner.next() {
-                    Some(p) => p,
-                    None => break,
-                };
-                match op.as_str() {
-                    "|>" => {
-                        current = transpile_pipeline(current, rhs_pair);
-                    }
-                    "+" => {
-                        let rhs = transpile_expr(rhs_pair);
-                        current = format!("format!(\"{{}}{{}}\", {}, {})", current, rhs);
-                    }
-                    "==" | "!=" | ">" | "<" | ">=" | "<=" => {
-                        let rhs = transpile_expr(rhs_pair);
-                        current = format!("({} {} {})", current, op.as_str(), rhs);
-                    }
-                    _ => {}
+fn apply_binary_op(lhs: String, op: &str, rhs: String) -> String {
+    match op {
+        // Known strings concatenate directly; anything else is decided by
+        // the operand types at compile time.
+        // The other side goes through `Text` so a JSON string joins unquoted.
+        "+" if is_string_literal(&lhs) || is_string_literal(&rhs) => {
+            let text = |code: String| {
+                if is_string_literal(&code) {
+                    code
+                } else {
+                    format!("zinc_std::ops::Text::text(&{})", code)
                 }
-            }
-            current
-        }
-        Rule::term => transpile_term(pair),
-        Rule::call => transpile_call(pair),
-        Rule::array => transpile_array(pair),
-        Rule::string => {
-            transpile_string(pair.as_str())
+            };
+            format!("format!(\"{{}}{{}}\", {}, {})", text(lhs), text(rhs))
         }
-        Rule::number => pair.as_str().to_string(),
-        Rule::identifier => pair.as_str().to_string(),
-        _ => String::new(),
+        "+" => format!("zinc_std::ops::add({}, {})", lhs, rhs),
+        "-" => format!("zinc_std::ops::sub({}, {})", lhs, rhs),
+        "*" => format!("zinc_std::ops::mul({}, {})", lhs, rhs),
+        "/" => format!("zinc_std::ops::div({}, {})", lhs, rhs),
+        "%" => format!("zinc_std::ops::rem({}, {})", lhs, rhs),
+        "??" => format!("zinc_std::ops::coalesce({}, {})", lhs, rhs),
+        ".." | "..=" => format!("({}{}{})", lhs, op, rhs),
+        "==" | "!=" => format!("({} {} {})", lhs, op, rhs),
+        // Through `zinc_std::ops` so that JSON values can be ordered too.
+        ">" => format!("zinc_std::ops::gt(&{}, &{})", lhs, rhs),
+        "<" => format!("zinc_std::ops::lt(&{}, &{})", lhs, rhs),
+        ">=" => format!("zinc_std::ops::ge(&{}, &{})", lhs, rhs),
+        "<=" => format!("zinc_std::ops::le(&{}, &{})", lhs, rhs),
+        _ => lhs,
     }
 }
 
 fn transpile_call(pair: Pair<Rule>) -> String {
+    let captures = if is_exit_hook(&pair) || is_call_to(&pair, "timeout") {
+        VARIABLES.with(|names| hook_captures(&pair, &names.borrow()))
+    } else {
+        Vec::new()
+    };
     let (name, args) = parse_call(pair);
-    transpile_call_with_args(&name, &args)
+    if captures.is_empty() {
+        return transpile_call_with_args(&name, &args);
+    }
+    // The closure owns what it captures, so it gets copies and the caller
+    // keeps its variables. See also `check_exit_hooks`.
+    let copies = captures
+        .iter()
+        .map(|name| format!("let {0} = {0}.clone(); ", name))
+        .collect::<String>();
+    match (name.as_str(), args.as_slice()) {
+        ("on_exit", [hook]) => format!("zinc_std::on_exit({{ {}{} }})", copies, move_closure(hook)),
+        ("timeout", [seconds, work]) => format!(
+            "zinc_std::timeout({}, {{ {}{} }})",
+            seconds,
+            copies,
+            move_closure(work)
+        ),
+        _ => transpile_call_with_args(&name, &args),
+    }
 }
 
 
@@ -273,30 +3478,103 @@ fn transpile_arg_list(pair: Pair<Rule>) -> Vec<String> {
 }
 
 fn transpile_block(pair: Pair<Rule>) -> String {
-    let mut out = String::new();
+    let mut out = Writer::for_source(pair.as_str());
     for stmt in pair.into_inner() {
         if stmt.as_rule() == Rule::statement {
-            out.push_str(&transpile_statement(stmt));
+            out.push(&transpile_statement(stmt));
         }
     }
-    out
+    out.finish()
 }
 
 
 fn transpile_array(pair: Pair<Rule>) -> String {
     let mut items = Vec::new();
+    let mut steps = Vec::new();
+    let mut has_spread = false;
     let mut inner = pair.into_inner();
     if let Some(elements) = inner.next() {
-        for expr in elements.into_inner() {
-            if expr.as_rule() == Rule::expr {
-                let value = transpile_expr(expr);
-                if !value.is_empty() {
-                    items.push(value);
+        for element in elements.into_inner() {
+            match element.as_rule() {
+                Rule::expr => {
+                    let value = transpile_expr(element);
+                    if !value.is_empty() {
+                        steps.push(format!("__items.push({});", value));
+                        items.push(value);
+                    }
+                }
+                Rule::spread => {
+                    has_spread = true;
+                    let source = element
+                        .into_inner()
+                        .next()
+                        .map(transpile_expr)
+                        .unwrap_or_default();
+                    if !source.is_empty() {
+                        steps.push(format!(
+                            "__items.extend(zinc_std::iter::items(&{}));",
+                            source
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if has_spread {
+        format!(
+            "{{ let mut __items = Vec::new(); {} __items }}",
+            steps.join(" ")
+        )
+    } else {
+        format!("vec![{}]", items.join(", "))
+    }
+}
+
+fn transpile_map(pair: Pair<Rule>) -> String {
+    let mut fields = Vec::new();
+    let mut steps = Vec::new();
+    let mut has_spread = false;
+    for entry in pair.into_inner() {
+        match entry.as_rule() {
+            Rule::map_entry => {
+                let mut inner = entry.into_inner();
+                let key = inner
+                    .next()
+                    .map(|p| transpile_string(p.as_str()))
+                    .unwrap_or_default();
+                let value = inner.next().map(transpile_expr).unwrap_or_default();
+                if key.is_empty() || value.is_empty() {
+                    continue;
+                }
+                fields.push(format!("({}, zinc_std::json::from({}))", key, value));
+                steps.push(format!(
+                    "zinc_std::json::insert(&mut __map, {}, {});",
+                    key, value
+                ));
+            }
+            Rule::spread => {
+                has_spread = true;
+                let source = entry
+                    .into_inner()
+                    .next()
+                    .map(transpile_expr)
+                    .unwrap_or_default();
+                if !source.is_empty() {
+                    steps.push(format!("zinc_std::json::merge(&mut __map, &{});", source));
                 }
             }
+            _ => {}
         }
     }
-    format!("vec![{}]", items.join(", "))
+    if has_spread {
+        format!(
+            "{{ let mut __map = zinc_std::json::object(Vec::new()); {} __map }}",
+            steps.join(" ")
+        )
+    } else {
+        format!("zinc_std::json::object(vec![{}])", fields.join(", "))
+    }
 }
 
 fn transpile_pipeline(lhs: String, rhs_pair: Pair<Rule>) -> String {
@@ -321,12 +3599,8 @@ fn transpile_pipeline(lhs: String, rhs_pair: Pair<Rule>) -> String {
     match atom.as_rule() {
         Rule::call => {
             let (name, mut args) = parse_call(atom);
-            args.insert(0, lhs);
-            let mut out = transpile_call_with_args(&name, &args);
-            for suffix in inner {
-                out = transpile_suffix(out, suffix);
-            }
-            out
+            pipe_into(&mut args, lhs);
+            transpile_suffixes(transpile_call_with_args(&name, &args), inner)
         }
         Rule::identifier => {
             let ident = atom.as_str().to_string();
@@ -342,32 +3616,30 @@ fn transpile_pipeline(lhs: String, rhs_pair: Pair<Rule>) -> String {
                         .next()
                         .map(transpile_arg_list)
                         .unwrap_or_default();
-                    args.insert(0, lhs);
-                    let mut out = transpile_member_call_with_args(&ident, &method, &args);
-                    for suffix in inner {
-                        out = transpile_suffix(out, suffix);
-                    }
-                    return out;
-                }
-                let mut out = ident;
-                out = transpile_suffix(out, first_suffix);
-                for suffix in inner {
-                    out = transpile_suffix(out, suffix);
+                    pipe_into(&mut args, lhs);
+                    let out = transpile_member_call_with_args(&ident, &method, &args);
+                    return transpile_suffixes(out, inner);
                 }
+                let out = transpile_suffixes(ident, std::iter::once(first_suffix).chain(inner));
                 return format!("{}({})", out, lhs);
             }
-            return transpile_call_with_args(&ident, &[lhs]);
+            transpile_call_with_args(&ident, &[lhs])
         }
         _ => {
-            let mut out = transpile_atom(atom);
-            for suffix in inner {
-                out = transpile_suffix(out, suffix);
-            }
+            let out = transpile_suffixes(transpile_atom(atom), inner);
             format!("{}({})", out, lhs)
         }
     }
 }
 
+// The piped value replaces a `_` argument, or goes first when there is none.
+fn pipe_into(args: &mut Vec<String>, value: String) {
+    match args.iter().position(|arg| arg == "_") {
+        Some(i) => args[i] = value,
+        None => args.insert(0, value),
+    }
+}
+
 fn parse_call(pair: Pair<Rule>) -> (String, Vec<String>) {
     let mut inner = pair.into_inner();
     let name = inner
@@ -382,35 +3654,381 @@ fn parse_call(pair: Pair<Rule>) -> (String, Vec<String>) {
 fn transpile_call_with_args(name: &str, args: &[String]) -> String {
     let args_joined = args.join(", ");
     match name {
-        "print" => format!("println!(\"{{:?}}\", {})", args_joined),
+        "print" => {
+            if args.len() == 1 && is_string_literal(&args[0]) {
+                format!("println!(\"{{}}\", {})", args_joined)
+            } else if args.len() == 1 {
+                format!("println!(\"{{}}\", zinc_std::fmt::show(&{}))", args[0])
+            } else {
+                format!("println!(\"{{:?}}\", {})", args_joined)
+            }
+        }
+        "inspect" => {
+            if args.len() != 1 {
+                return String::new();
+            }
+            format!("zinc_std::inspect::print(&{})", args[0])
+        }
+        "print_table" => match args.len() {
+            1 => format!("zinc_std::table::print(&{}, &{})", args[0], NIL),
+            2 => format!("zinc_std::table::print(&{}, &{})", args[0], args[1]),
+            _ => String::new(),
+        },
         "leak" => "zinc_std::leak()".to_string(),
+        "collect" => {
+            if args.len() != 1 {
+                return String::new();
+            }
+            format!("zinc_std::iter::items({}).collect::<Vec<_>>()", args[0])
+        }
+        "spawn" => format!("zinc_std::spawn({})", args_joined),
+        "on_exit" => {
+            if args.len() != 1 {
+                return String::new();
+            }
+            // Hooks can fire from the signal handler after `main` has moved
+            // on, so they take ownership of what they capture; `transpile_call`
+            // hands them copies of variables.
+            format!("zinc_std::on_exit({})", move_closure(&args[0]))
+        }
+        "progress" => {
+            if args.len() != 1 {
+                return String::new();
+            }
+            format!("zinc_std::progress::new({})", args[0])
+        }
+        "retry" => {
+            if args.len() != 3 {
+                return String::new();
+            }
+            format!("zinc_std::retry({}, {}, {})", args[0], args[1], args[2])
+        }
+        "timeout" => {
+            if args.len() != 2 {
+                return String::new();
+            }
+            // The work may outlive the call, so it owns what it captures.
+            format!("zinc_std::timeout({}, {})", args[0], move_closure(&args[1]))
+        }
+        "fmt" => {
+            if args.is_empty() {
+                return String::new();
+            }
+            let values = args[1..]
+                .iter()
+                .map(|a| format!("zinc_std::fmt::arg(&{})", a))
+                .collect::<Vec<_>>();
+            format!(
+                "zinc_std::fmt::format(&{}, &[{}])",
+                args[0],
+                values.join(", ")
+            )
+        }
+        _ if USER_FNS.with(|fns| fns.borrow().contains(name)) => {
+            let values = args
+                .iter()
+                .map(|a| format!("zinc_std::json::from({})", a))
+                .collect::<Vec<_>>();
+            format!("{}({})", name, values.join(", "))
+        }
         _ => format!("{}({})", name, args_joined),
     }
-}
-
-fn transpile_member_call_with_args(obj: &str, method: &str, args: &[String]) -> String {
-    let args_joined = args.join(", ");
-    if obj == "db" && method == "query" {
+}
+
+fn move_closure(arg: &str) -> String {
+    if arg.starts_with('|') {
+        format!("move {}", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+fn transpile_member_call_with_args(obj: &str, method: &str, args: &[String]) -> String {
+    // `nil` passed where std takes an `Option`, such as a profile, is `None`.
+    let call = transpile_std_call(obj, method, args).replace(&format!("Some({})", NIL), "None");
+    let params = VALUE_PARAMS.with(|set| {
+        let set = set.borrow();
+        args.iter()
+            .filter(|arg| set.contains(arg.as_str()))
+            .cloned()
+            .collect::<Vec<_>>()
+    });
+    if params.is_empty() || !call.starts_with(&format!("zinc_std::{}::", obj)) {
+        return call;
+    }
+
+    // Function parameters are `json::Value`s. Std functions take text where
+    // they take an argument by value, and `&Value` where they borrow one.
+    let args = args
+        .iter()
+        .map(|arg| {
+            if params.contains(arg) {
+                format!("zinc_std::json::as_str(&{})", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut call =
+        transpile_std_call(obj, method, &args).replace(&format!("Some({})", NIL), "None");
+    for param in &params {
+        let text = format!("zinc_std::json::as_str(&{})", param);
+        call = call
+            .replace(&format!("&mut {}", text), &format!("&mut {}", param))
+            .replace(&format!("&{}", text), &format!("&{}", param));
+    }
+    call
+}
+
+fn transpile_std_call(obj: &str, method: &str, args: &[String]) -> String {
+    let args_joined = args.join(", ");
+    if IN_TRY.with(|flag| flag.get()) {
+        if let Some(call) = transpile_fallible_call(obj, method, args) {
+            return call;
+        }
+    }
+    if obj == "cli" && method == "flags" {
+        if args.len() == 1 {
+            return format!("zinc_std::cli::flags(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "db" && method == "query" {
+        if args.len() == 2 {
+            return format!("zinc_std::db::query({}, {})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "limits" && method == "set" {
+        if args.len() == 1 {
+            return format!("zinc_std::limits::set(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "log" && matches!(method, "info" | "warn" | "error") {
+        if args.len() == 1 {
+            return format!(
+                "zinc_std::log::{}(&{}, &zinc_std::json::Value::Null)",
+                method, args[0]
+            );
+        } else if args.len() == 2 {
+            return format!("zinc_std::log::{}(&{}, &{})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "log" && method == "metric" {
+        if args.len() == 2 {
+            return format!("zinc_std::log::metric(&{}, {})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "log" && method == "set_format" {
+        if args.len() == 1 {
+            return format!("zinc_std::log::set_format(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "mock" && matches!(method, "spider" | "fs" | "db") {
+        if args.len() == 2 {
+            return format!("zinc_std::mock::{}(&{}, &{})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "mock" && method == "clear" {
+        return "zinc_std::mock::clear()".to_string();
+    }
+    if obj == "random" && matches!(method, "float" | "uuid") {
+        if args.is_empty() {
+            return format!("zinc_std::random::{}()", method);
+        }
+        return String::new();
+    }
+    if obj == "random" && matches!(method, "int" | "jitter") {
+        if args.len() == 2 {
+            return format!("zinc_std::random::{}({}, {})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "random" && method == "shuffle" {
+        if args.len() == 1 {
+            return format!("zinc_std::random::shuffle(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "random" && method == "seed" {
+        if args.len() == 1 {
+            return format!("zinc_std::random::seed({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "notify" && matches!(method, "slack" | "discord") {
+        if args.len() == 2 {
+            return format!("zinc_std::notify::{}(&{}, &{})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "parse" && matches!(method, "price" | "number" | "date") {
+        if args.len() == 1 {
+            return format!("zinc_std::parse::{}(&{})", method, args[0]);
+        }
+        return String::new();
+    }
+    if obj == "state" && method == "save" {
+        if args.len() == 2 {
+            return format!("zinc_std::state::save(&{}, &{})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "state" && method == "load" {
+        if args.len() == 1 {
+            return format!("zinc_std::state::load(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "xlsx" && method == "write" {
+        if args.len() == 2 {
+            return format!("zinc_std::xlsx::write(&{}, &{})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "xlsx" && method == "read" {
+        if args.len() == 1 {
+            return format!("zinc_std::xlsx::read(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "parquet" && method == "write" {
+        if args.len() == 2 {
+            return format!("zinc_std::parquet::write({}, &{})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "parquet" && method == "read" {
+        if args.len() == 1 {
+            return format!("zinc_std::parquet::read({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "redis" && matches!(method, "get" | "rpop") {
+        if args.len() == 1 {
+            return format!("zinc_std::redis::{}(&{})", method, args[0]);
+        }
+        return String::new();
+    }
+    if obj == "redis" && matches!(method, "set" | "lpush") {
+        if args.len() == 2 {
+            return format!("zinc_std::redis::{}(&{}, &{})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "s3" && method == "put" {
+        if args.len() == 3 {
+            return format!("zinc_std::s3::put({}, {}, &{})", args[0], args[1], args[2]);
+        }
+        return String::new();
+    }
+    if obj == "s3" && method == "get" {
+        if args.len() == 2 {
+            return format!("zinc_std::s3::get({}, {})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "store" && method == "save" {
+        if args.len() == 2 {
+            return format!("zinc_std::store::save({}, &{})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "store" && method == "query" {
+        if args.len() == 1 {
+            return format!("zinc_std::store::query({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "encoding" && method == "detect" {
+        if args.len() == 1 {
+            return format!("zinc_std::encoding::detect(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "encoding" && method == "convert" {
+        if args.len() == 3 {
+            return format!(
+                "zinc_std::encoding::convert(&{}, &{}, &{})",
+                args[0], args[1], args[2]
+            );
+        }
+        return String::new();
+    }
+    if obj == "fs" && method == "read" {
+        if args.len() == 1 {
+            return format!("zinc_std::fs::read({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "fs" && method == "read_bytes" {
+        if args.len() == 1 {
+            return format!("zinc_std::fs::read_bytes(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "fs" && method == "remove" {
+        if args.len() == 1 {
+            return format!("zinc_std::fs::remove({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "fs" && method == "write" {
+        if args.len() == 2 {
+            return format!("zinc_std::fs::write({}, {})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "fs" && method == "write_bytes" {
+        if args.len() == 2 {
+            return format!("zinc_std::fs::write_bytes(&{}, &{})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "fuzzy" && matches!(method, "ratio" | "best_match") {
+        if args.len() == 2 {
+            return format!("zinc_std::fuzzy::{}(&{}, &{})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "text" && matches!(method, "lang" | "words" | "sentences") {
+        if args.len() == 1 {
+            return format!("zinc_std::text::{}(&{})", method, args[0]);
+        }
+        return String::new();
+    }
+    if obj == "html" && method == "select" {
         if args.len() == 2 {
-            return format!("zinc_std::db::query({}, {})", args[0], args[1]);
+            return format!("zinc_std::html::select_text({}, {})", args[0], args[1]);
         }
         return String::new();
     }
-    if obj == "fs" && method == "read" {
+    if obj == "html" && matches!(method, "to_text" | "strip_tags" | "article" | "metadata") {
         if args.len() == 1 {
-            return format!("zinc_std::fs::read({})", args[0]);
+            return format!("zinc_std::html::{}(&{})", method, args[0]);
         }
         return String::new();
     }
-    if obj == "fs" && method == "write" {
+    if obj == "html" && method == "xpath" {
         if args.len() == 2 {
-            return format!("zinc_std::fs::write({}, {})", args[0], args[1]);
+            return format!("zinc_std::html::xpath(&{}, {})", args[0], args[1]);
         }
         return String::new();
     }
-    if obj == "html" && method == "select" {
+    if obj == "diff" && (method == "text" || method == "json") {
         if args.len() == 2 {
-            return format!("zinc_std::html::select_text({}, {})", args[0], args[1]);
+            return format!("zinc_std::diff::{}(&{}, &{})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "feed" && method == "parse" {
+        if args.len() == 1 {
+            return format!("zinc_std::feed::parse(&{})", args[0]);
         }
         return String::new();
     }
@@ -438,6 +4056,195 @@ fn transpile_member_call_with_args(obj: &str, method: &str, args: &[String]) ->
         }
         return String::new();
     }
+    if obj == "spider" && method == "get_async" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::get_async({}, None)", args[0]);
+        } else if args.len() >= 2 {
+            return format!(
+                "zinc_std::spider::get_async({}, Some({}))",
+                args[0], args[1]
+            );
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "get_all" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::get_all(&{}, None)", args[0]);
+        } else if args.len() == 2 {
+            return format!("zinc_std::spider::get_all(&{}, Some({}))", args[0], args[1]);
+        } else if args.len() == 3 {
+            return format!(
+                "zinc_std::spider::get_all_with_limits(&{}, Some({}), {}, zinc_std::spider::DEFAULT_TOTAL_CONCURRENCY)",
+                args[0], args[1], args[2]
+            );
+        } else if args.len() >= 4 {
+            return format!(
+                "zinc_std::spider::get_all_with_limits(&{}, Some({}), {}, {})",
+                args[0], args[1], args[2], args[3]
+            );
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "get_with_headers" {
+        if args.len() == 2 {
+            return format!(
+                "zinc_std::spider::get_with_headers({}, &{}, None)",
+                args[0], args[1]
+            );
+        } else if args.len() >= 3 {
+            return format!(
+                "zinc_std::spider::get_with_headers({}, &{}, Some({}))",
+                args[0], args[1], args[2]
+            );
+        }
+        return String::new();
+    }
+    if obj == "auth" && method == "oauth2" {
+        if args.len() == 4 {
+            return format!(
+                "zinc_std::auth::oauth2({}, {}, {}, &{})",
+                args[0], args[1], args[2], args[3]
+            );
+        }
+        return String::new();
+    }
+    if obj == "auth" && (method == "token" || method == "header") {
+        if args.len() == 1 {
+            return format!("{}.{}()", args[0], method);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "session_get" {
+        if args.len() == 2 {
+            return format!(
+                "zinc_std::spider::session_get({}, {}, None)",
+                args[0], args[1]
+            );
+        } else if args.len() >= 3 {
+            return format!(
+                "zinc_std::spider::session_get({}, {}, Some({}))",
+                args[0], args[1], args[2]
+            );
+        }
+        return String::new();
+    }
+    if obj == "spider" && (method == "cookies_save" || method == "cookies_load") {
+        if args.len() == 2 {
+            return format!("zinc_std::spider::{}({}, {})", method, args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "cassette" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::cassette(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "screenshot" {
+        if args.len() == 2 {
+            return format!(
+                "zinc_std::spider::screenshot(&{}, &{}, &zinc_std::json::Value::Null)",
+                args[0], args[1]
+            );
+        }
+        if args.len() == 3 {
+            return format!(
+                "zinc_std::spider::screenshot(&{}, &{}, &{})",
+                args[0], args[1], args[2]
+            );
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "upload" {
+        if args.len() == 3 {
+            return format!(
+                "zinc_std::spider::upload({}, &{}, &{})",
+                args[0], args[1], args[2]
+            );
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "get_response" {
+        return match args {
+            [url] => format!("zinc_std::spider::get_response({}, None)", url),
+            [url, profile] => format!("zinc_std::spider::get_response({}, Some({}))", url, profile),
+            _ => String::new(),
+        };
+    }
+    if obj == "spider" && method == "is_challenge" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::is_challenge(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "protocol" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::set_protocol({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "crawl_profile" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::set_crawl_profile({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "tls" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::configure_tls(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "resolve" {
+        if args.len() == 2 {
+            return format!("zinc_std::spider::resolve({}, {})", args[0], args[1]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "doh" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::use_doh({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "clipboard" && method == "get" {
+        return "zinc_std::clipboard::get()".to_string();
+    }
+    if obj == "clipboard" && method == "set" {
+        if args.len() == 1 {
+            return format!("zinc_std::clipboard::set(&{})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "har_start" {
+        return "zinc_std::spider::har_start()".to_string();
+    }
+    if obj == "spider" && method == "har_save" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::har_save({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "cache" {
+        if args.len() == 1 {
+            return format!("zinc_std::spider::enable_cache({})", args[0]);
+        }
+        return String::new();
+    }
+    if obj == "spider" && method == "cache_off" {
+        return "zinc_std::spider::disable_cache()".to_string();
+    }
+    if obj == "spider" && method == "stream" {
+        if args.len() == 2 {
+            return format!("zinc_std::spider::stream({}, None, {})", args[0], args[1]);
+        } else if args.len() >= 3 {
+            return format!(
+                "zinc_std::spider::stream({}, Some({}), {})",
+                args[0], args[1], args[2]
+            );
+        }
+        return String::new();
+    }
     if obj == "spider" && method == "get_proxy" {
         if args.len() == 3 {
             return format!(
@@ -453,27 +4260,171 @@ fn transpile_member_call_with_args(obj: &str, method: &str, args: &[String]) ->
     if obj == "spider" && method == "get" {
         if args.len() == 1 {
             format!("zinc_std::spider::get({}, None)", args[0])
-        } else if args.len() >= 2 {
+        } else if args.len() == 2 {
             format!("zinc_std::spider::get({}, Some({}))", args[0], args[1])
+        } else if args.len() >= 3 {
+            format!(
+                "zinc_std::spider::get_with_encoding({}, Some({}), {})",
+                args[0], args[1], args[2]
+            )
         } else {
             String::new()
         }
+    } else if let Some(call) = transpile_value_method(obj, method, args) {
+        call
     } else {
         format!("{}.{}({})", obj, method, args_joined)
     }
 }
 
+fn transpile_value_method(receiver: &str, method: &str, args: &[String]) -> Option<String> {
+    transpile_list_method(receiver, method, args)
+        .or_else(|| transpile_string_method(receiver, method, args))
+}
+
+// `list.map(f)` and friends work on anything `iter::items` accepts: vecs,
+// ranges and JSON arrays alike.
+fn transpile_list_method(receiver: &str, method: &str, args: &[String]) -> Option<String> {
+    let items = format!("zinc_std::iter::items(&{})", receiver);
+    let call = match (method, args) {
+        ("len", []) => format!("zinc_std::list::len(&{})", receiver),
+        ("push", [item]) => format!("zinc_std::list::push(&mut {}, {})", receiver, item),
+        ("contains", [item]) => format!("zinc_std::list::contains(&{}, &{})", receiver, item),
+        ("join", [sep]) => format!("zinc_std::list::join(&{}, &{})", receiver, sep),
+        ("sort" | "reverse", []) => format!("zinc_std::list::{}(&mut {})", method, receiver),
+        ("map", [f]) => format!("{}.map({}).collect::<Vec<_>>()", items, f),
+        ("filter", [f]) => format!(
+            "{}.filter(|__item| ({})(__item.clone())).collect::<Vec<_>>()",
+            items, f
+        ),
+        ("reduce", [f, init]) => format!("{}.fold({}, {})", items, init, f),
+        ("each", [f]) => format!("{}.for_each(|__item| {{ ({})(__item); }})", items, f),
+        _ => return None,
+    };
+    Some(call)
+}
+
+// Std calls that have a `Result`-returning `try_*` variant. On success they
+// produce the same value as the plain call.
+fn transpile_fallible_call(obj: &str, method: &str, args: &[String]) -> Option<String> {
+    let call = match (obj, method, args) {
+        ("spider", "get", [url]) => format!("zinc_std::spider::try_get({}, None)", url),
+        ("spider", "get", [url, profile]) => {
+            format!("zinc_std::spider::try_get({}, Some({}))", url, profile)
+        }
+        ("db", "query", [url, sql]) => format!("zinc_std::db::try_query({}, {})", url, sql),
+        ("fs", "read", [path]) => format!("zinc_std::fs::try_read({})", path),
+        ("fs", "write", [path, content]) => {
+            format!("zinc_std::fs::try_write({}, {})", path, content)
+        }
+        ("json", "parse", [text]) => format!("zinc_std::json::try_parse({})", text),
+        ("html", "select", [html, selector]) => {
+            format!("zinc_std::html::try_select_text({}, {})", html, selector)
+        }
+        ("store", "save", [table, row]) => {
+            format!("zinc_std::store::try_save({}, &{})", table, row)
+        }
+        ("store", "query", [sql]) => format!("zinc_std::store::try_query({})", sql),
+        _ => return None,
+    };
+    Some(format!("{}?", call))
+}
+
 fn transpile_term(pair: Pair<Rule>) -> String {
     let mut inner = pair.into_inner();
     let atom = match inner.next() {
         Some(p) => p,
         None => return String::new(),
     };
-    let mut current = transpile_atom(atom);
-    for suffix in inner {
-        current = transpile_suffix(current, suffix);
+    transpile_suffixes(transpile_atom(atom), inner)
+}
+
+// Runs of `.field` and `["key"]` become a single `json::path` lookup, which
+// works on JSON values and structs alike; plain numeric indexing stays native
+// so that vecs keep working.
+fn transpile_suffixes<'i>(
+    mut current: String,
+    suffixes: impl Iterator<Item = Pair<'i, Rule>>,
+) -> String {
+    let mut keys = Vec::new();
+    for suffix in suffixes {
+        let suffix = unwrap_suffix(suffix);
+        let slice = suffix
+            .clone()
+            .into_inner()
+            .next()
+            .filter(|p| p.as_rule() == Rule::slice);
+        if let Some(slice) = slice {
+            current = transpile_path(current, std::mem::take(&mut keys));
+            current = transpile_slice(current, slice);
+            continue;
+        }
+        match suffix.as_rule() {
+            Rule::indexing_suffix | Rule::field_suffix => keys.push(path_key(suffix)),
+            _ => {
+                current = transpile_path(current, std::mem::take(&mut keys));
+                current = transpile_suffix(current, suffix);
+            }
+        }
+    }
+    transpile_path(current, keys)
+}
+
+fn transpile_slice(current: String, slice: Pair<Rule>) -> String {
+    let mut start = "None".to_string();
+    let mut end = "None".to_string();
+    for bound in slice.into_inner() {
+        let value = match bound.clone().into_inner().next().map(transpile_expr) {
+            Some(value) if !value.is_empty() => format!("Some({} as i64)", value),
+            _ => return String::new(),
+        };
+        match bound.as_rule() {
+            Rule::slice_start => start = value,
+            _ => end = value,
+        }
+    }
+    if current.is_empty() {
+        return String::new();
+    }
+    format!("zinc_std::list::slice(&{}, {}, {})", current, start, end)
+}
+
+// A key expression, and whether it names a field rather than an index.
+fn path_key(suffix: Pair<Rule>) -> (String, bool) {
+    let is_field = suffix.as_rule() == Rule::field_suffix;
+    let inner = match suffix.into_inner().next() {
+        Some(p) => p,
+        None => return (String::new(), false),
+    };
+    if is_field {
+        return (format!("\"{}\"", inner.as_str()), true);
+    }
+    let key = transpile_expr(inner);
+    let named = is_string_literal(&key);
+    (key, named)
+}
+
+fn transpile_path(current: String, keys: Vec<(String, bool)>) -> String {
+    if keys.is_empty() {
+        return current;
     }
-    current
+    if current.is_empty() || keys.iter().any(|(key, _)| key.is_empty()) {
+        return String::new();
+    }
+    if keys.iter().any(|(_, named)| *named) {
+        return format!("zinc_std::json::path(&{}, {})", current, path_keys(&keys));
+    }
+    keys.iter().fold(current, |out, (key, _)| {
+        format!("{}[{} as usize]", out, key)
+    })
+}
+
+fn path_keys(keys: &[(String, bool)]) -> String {
+    let keys = keys
+        .iter()
+        .map(|(key, _)| format!("{}.into()", key))
+        .collect::<Vec<_>>();
+    format!("vec![{}]", keys.join(", "))
 }
 
 fn transpile_atom(pair: Pair<Rule>) -> String {
@@ -486,12 +4437,19 @@ fn transpile_atom(pair: Pair<Rule>) -> String {
                 String::new()
             }
         }
+        Rule::lambda => transpile_lambda(pair),
+        Rule::if_expr => transpile_if_expr(pair),
         Rule::array => transpile_array(pair),
+        Rule::map => transpile_map(pair),
+        Rule::enum_path => transpile_enum_path(pair),
+        Rule::struct_lit => transpile_struct_lit(pair),
+        Rule::await_expr => transpile_await_expr(pair),
         Rule::call => transpile_call(pair),
         Rule::string => {
             transpile_string(pair.as_str())
         }
-        Rule::number => pair.as_str().to_string(),
+        Rule::number | Rule::boolean => pair.as_str().to_string(),
+        Rule::nil => NIL.to_string(),
         Rule::identifier => pair.as_str().to_string(),
         Rule::expr => transpile_expr(pair),
         Rule::term => transpile_term(pair),
@@ -499,18 +4457,73 @@ fn transpile_atom(pair: Pair<Rule>) -> String {
     }
 }
 
+fn transpile_lambda(pair: Pair<Rule>) -> String {
+    let mut params = Vec::new();
+    let mut body = String::new();
+    // `?` inside the closure would not reach the enclosing `try`.
+    let was_trying = IN_TRY.with(|flag| flag.replace(false));
+    let was_params = VALUE_PARAMS.with(|set| set.borrow().clone());
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::param_list => {
+                params = inner.into_inner().map(|p| p.as_str().to_string()).collect();
+                // The closure's own parameters shadow the function's.
+                VALUE_PARAMS.with(|set| set.borrow_mut().retain(|p| !params.contains(p)));
+            }
+            Rule::block => {
+                // A `return` inside a lambda leaves the closure, not the
+                // enclosing function.
+                let was_returning = RETURNS_VALUE.with(|flag| flag.replace(false));
+                body = format!("{{\n{}}}", transpile_value_block(inner));
+                RETURNS_VALUE.with(|flag| flag.set(was_returning));
+            }
+            _ => body = transpile_expr(inner),
+        }
+    }
+    IN_TRY.with(|flag| flag.set(was_trying));
+    VALUE_PARAMS.with(|set| *set.borrow_mut() = was_params);
+    if body.is_empty() {
+        body = "{}".to_string();
+    }
+    format!("|{}| {}", params.join(", "), body)
+}
+
+fn transpile_if_expr(pair: Pair<Rule>) -> String {
+    let mut inner = pair.into_inner();
+    let condition = inner.next().map(transpile_expr).unwrap_or_default();
+    let then_block = inner.next().map(transpile_value_block).unwrap_or_default();
+    let else_part = match inner.next() {
+        Some(p) if p.as_rule() == Rule::if_expr => transpile_if_expr(p),
+        Some(p) => format!("{{ {} }}", transpile_value_block(p)),
+        None => String::new(),
+    };
+    if condition.is_empty() || else_part.is_empty() {
+        return String::new();
+    }
+    format!("if {} {{ {} }} else {}", condition, then_block, else_part)
+}
+
+fn transpile_await_expr(pair: Pair<Rule>) -> String {
+    let future = pair
+        .into_inner()
+        .next()
+        .map(transpile_term)
+        .unwrap_or_default();
+    if future.is_empty() {
+        return String::new();
+    }
+    // Outside an async fn there is nothing to yield to, so block on the
+    // shared runtime instead.
+    if IN_ASYNC.with(|flag| flag.get()) {
+        format!("{}.await", future)
+    } else {
+        format!("zinc_std::block_on({})", future)
+    }
+}
+
 fn transpile_suffix(current: String, suffix: Pair<Rule>) -> String {
     let suffix = unwrap_suffix(suffix);
     match suffix.as_rule() {
-        Rule::indexing_suffix => {
-            let mut inner = suffix.into_inner();
-            let index_expr = inner.next().map(transpile_expr).unwrap_or_default();
-            if current.is_empty() || index_expr.is_empty() {
-                String::new()
-            } else {
-                format!("{}[{} as usize]", current, index_expr)
-            }
-        }
         Rule::member_suffix => {
             let mut inner = suffix.into_inner();
             let method = inner
@@ -524,12 +4537,82 @@ fn transpile_suffix(current: String, suffix: Pair<Rule>) -> String {
             if is_simple_identifier(&current) {
                 return transpile_member_call_with_args(&current, &method, &args);
             }
+            if let Some(call) = transpile_value_method(&current, &method, &args) {
+                return call;
+            }
+            if let Some(call) = transpile_chained_std_call(&current, &method, &args) {
+                return call;
+            }
             format!("{}.{}({})", current, method, args.join(", "))
         }
         _ => current,
     }
 }
 
+// `json.parse(x).get("a")`: a method called on the result of a std call goes
+// back through that call's module, with the result as the first argument.
+fn transpile_chained_std_call(current: &str, method: &str, args: &[String]) -> Option<String> {
+    let (module, _) = current.strip_prefix("zinc_std::")?.split_once("::")?;
+    if !module.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+        return None;
+    }
+    let mut full = vec![current.to_string()];
+    full.extend_from_slice(args);
+    let call = transpile_member_call_with_args(module, method, &full);
+    // Anything the module does not define falls back to treating `module`
+    // itself as the receiver, which is never what was meant here.
+    let fallback = transpile_value_method(module, method, &full)
+        .unwrap_or_else(|| format!("{}.{}({})", module, method, full.join(", ")));
+    (call.starts_with(&format!("zinc_std::{}::", module)) && call != fallback).then_some(call)
+}
+
+// Like a Rust block, a trailing expression without `;` is the block's value,
+// for closures and `if` expressions.
+fn transpile_value_block(pair: Pair<Rule>) -> String {
+    let source = pair.as_str();
+    let mut statements = pair
+        .into_inner()
+        .filter(|stmt| stmt.as_rule() == Rule::statement)
+        .collect::<Vec<_>>();
+    let tail = statements
+        .last()
+        .and_then(|stmt| stmt.clone().into_inner().next())
+        .filter(|stmt| {
+            stmt.as_rule() == Rule::expr_stmt && !stmt.as_str().trim_end().ends_with(';')
+        })
+        .and_then(|stmt| stmt.into_inner().next());
+    if tail.is_some() {
+        statements.pop();
+    }
+    let mut out = Writer::for_source(source);
+    for stmt in statements {
+        out.push(&transpile_statement(stmt));
+    }
+    if let Some(expr) = tail {
+        out.push(&transpile_expr(expr));
+    }
+    out.finish()
+}
+
+// `contains` is shared with lists and handled there.
+fn transpile_string_method(receiver: &str, method: &str, args: &[String]) -> Option<String> {
+    let call = match (method, args) {
+        ("split", []) => format!("zinc_std::string::split(&{}, \"\")", receiver),
+        ("trim" | "upper" | "lower", []) => {
+            format!("zinc_std::string::{}(&{})", method, receiver)
+        }
+        ("split" | "starts_with" | "ends_with", [arg]) => {
+            format!("zinc_std::string::{}(&{}, &{})", method, receiver, arg)
+        }
+        ("replace", [from, to]) => format!(
+            "zinc_std::string::replace(&{}, &{}, &{})",
+            receiver, from, to
+        ),
+        _ => return None,
+    };
+    Some(call)
+}
+
 fn is_simple_identifier(value: &str) -> bool {
     let mut chars = value.chars();
     let first = match chars.next() {
@@ -550,12 +4633,171 @@ fn unwrap_suffix(pair: Pair<Rule>) -> Pair<Rule> {
 }
 
 fn transpile_string(raw: &str) -> String {
+    if let Some(formatted) = interpolate(raw) {
+        return formatted;
+    }
+    let literal = rust_string_literal(raw);
+    INTERNED
+        .with(|table| table.borrow().get(&literal).cloned())
+        .unwrap_or(literal)
+}
+
+fn rust_string_literal(raw: &str) -> String {
+    if let Some(inner) = triple_quoted(raw) {
+        return rust_raw_literal(inner);
+    }
     if raw.len() < 2 {
         return String::new();
     }
-    let inner = &raw[1..raw.len() - 1];
-    let unescaped = inner.replace("\\\"", "\"").replace("\\\\", "\\");
-    format!("r#\"{}\"#", unescaped)
+    // Debug formatting of a str is always a valid, fully escaped Rust literal.
+    format!("{:?}", unescape(&raw[1..raw.len() - 1]))
+}
+
+// The text a string literal denotes.
+fn string_value(raw: &str) -> String {
+    match triple_quoted(raw) {
+        Some(inner) => inner.to_string(),
+        None if raw.len() >= 2 => unescape(&raw[1..raw.len() - 1]),
+        None => String::new(),
+    }
 }
 
+fn triple_quoted(raw: &str) -> Option<&str> {
+    if raw.len() >= 6 && raw.starts_with("\"\"\"") && raw.ends_with("\"\"\"") {
+        Some(&raw[3..raw.len() - 3])
+    } else {
+        None
+    }
+}
+
+// `r"..."` with one more `#` than the longest `"#...` run in the text.
+fn rust_raw_literal(text: &str) -> String {
+    let hashes = text
+        .split('"')
+        .skip(1)
+        .map(|after| after.chars().take_while(|c| *c == '#').count() + 1)
+        .max()
+        .unwrap_or(0);
+    let fence = "#".repeat(hashes);
+    format!("r{}\"{}\"{}", fence, text, fence)
+}
+
+// Rust-style escapes: `\n`, `\r`, `\t`, `\0`, `\\`, `\"`, `\'` and `\u{...}`.
+// Anything else keeps its backslash, so `"C:\dir"` means what it says.
+fn unescape(inner: &str) -> String {
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('0') => out.push('\0'),
+            Some(c @ ('\\' | '"' | '\'')) => out.push(c),
+            Some('u') => {
+                let rest = chars.clone().skip(1).collect::<String>();
+                let code = rest
+                    .strip_prefix('{')
+                    .and_then(|r| r.split_once('}'))
+                    .and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok().map(|n| (hex.len(), n)))
+                    .and_then(|(len, n)| char::from_u32(n).map(|c| (len, c)));
+                match code {
+                    Some((len, c)) => {
+                        out.push(c);
+                        // `u`, the braces and the hex digits.
+                        for _ in 0..len + 3 {
+                            chars.next();
+                        }
+                    }
+                    None => out.push('\\'),
+                }
+                continue;
+            }
+            _ => {
+                out.push('\\');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    out
+}
+
+// `"Count: {n}"` -> `format!("Count: {}", n)`. Only braces holding an
+// expression over names bound in the program are interpolated, so `fmt`
+// specs like `{:.2}`, positional `{0}`, placeholders such as `{id}` for an
+// unbound name and JSON text stay literal; `{{` and `}}` escape.
+fn interpolate(raw: &str) -> Option<String> {
+    if raw.len() < 2 || triple_quoted(raw).is_some() {
+        return None;
+    }
+    let inner = unescape(&raw[1..raw.len() - 1]);
+    let mut template = String::new();
+    let mut args = Vec::new();
+    let mut rest = inner.as_str();
+    while let Some(pos) = rest.find(['{', '}']) {
+        let (text, tail) = rest.split_at(pos);
+        push_literal(&mut template, text);
+        if let Some(after) = tail.strip_prefix("{{").or_else(|| tail.strip_prefix("}}")) {
+            template.push_str(&tail[..2]);
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix('}') {
+            template.push_str("}}");
+            rest = after;
+        } else if let Some(end) = tail.find('}') {
+            match interpolated_expr(&tail[1..end]) {
+                Some(code) => {
+                    template.push_str("{}");
+                    args.push(code);
+                }
+                None => push_literal(&mut template, &tail[..=end]),
+            }
+            rest = &tail[end + 1..];
+        } else {
+            push_literal(&mut template, tail);
+            rest = "";
+        }
+    }
+    push_literal(&mut template, rest);
+    if args.is_empty() {
+        return None;
+    }
+    Some(format!("format!({:?}, {})", template, args.join(", ")))
+}
 
+fn push_literal(template: &mut String, text: &str) {
+    template.push_str(&text.replace('{', "{{").replace('}', "}}"));
+}
+
+fn interpolated_expr(content: &str) -> Option<String> {
+    let content = content.trim();
+    if !content.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+    let expr = ZincParser::parse(Rule::expr, content).ok()?.next()?;
+    if expr.as_span().end() != content.len() {
+        return None;
+    }
+    let bound = VARIABLES.with(|names| {
+        let names = names.borrow();
+        variable_refs(&expr)
+            .iter()
+            .all(|name| names.contains(name.as_str()))
+    });
+    if !bound {
+        return None;
+    }
+    Some(transpile_expr(expr))
+}
+
+fn is_string_literal(value: &str) -> bool {
+    value.starts_with('"')
+        || value.starts_with("r\"")
+        || value.starts_with("r#")
+        || value.starts_with("__ZN_STR_")
+        || value.starts_with("format!(")
+}
@@ -0,0 +1,92 @@
+// Generated programs must build against zinc_std and print what the zinc
+// source says, not only parse as Rust. Each case is compiled as its own bin
+// in one scratch crate so that zinc_std is only built once.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use zinc_core::transpile_program;
+
+const CASES: &[(&str, &str, &str)] = &[
+    (
+        "compound_assignment",
+        "let n = 10\nn /= 4\nprint(n)\nlet s = \"a\"\ns += \"b\"\ns += 1\nprint(s)\n\
+         let count = 0\nfor x in 0..5 { count += x }\ncount *= 3\nprint(count)\n\
+         let page = 1\nwhile page < 4 { page += 1 }\nprint(page)\n\
+         if count >= 30 { print(\"big\") }\nn = 7\nprint(n)\ns = \"reset\"\nprint(s)",
+        "2.5\nab1\n30\n4\nbig\n7\nreset\n",
+    ),
+    (
+        "functions_and_strings",
+        "fn greet(name) {\n return \"hi \" + name\n}\nlet who = \"zinc\"\nprint(greet(who))\n\
+         print(\"{who} has {who.len()} letters, {{literal}}\")\nprint(7 / 2)\nprint(6 / 2)",
+        "hi zinc\nzinc has 4 letters, {literal}\n3.5\n3\n",
+    ),
+    (
+        "collections_and_json",
+        "let xs = [3, 1, 2]\nlet total = 0\nfor x in xs { total += x }\nprint(total)\n\
+         print(xs.filter(|x| x > 1))\nlet doc = json.parse(\"{\\\"a\\\": {\\\"b\\\": [1, 2]}}\")\n\
+         print(doc.a.b)\nlet label = if total > 5 { \"many\" } else { \"few\" }\nprint(label)",
+        "6\n[3,2]\n[1,2]\nmany\n",
+    ),
+    (
+        "enums_and_match",
+        "enum Color { Red, Green }\nlet c = Color::Green\n\
+         match c {\n Color::Red => print(\"red\"),\n Color::Green => print(\"green\"),\n}\n\
+         print(c)",
+        "green\nGreen\n",
+    ),
+];
+
+fn scratch_crate() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("compile_run");
+    let bins = dir.join("src/bin");
+    std::fs::create_dir_all(&bins).unwrap();
+    let zinc_std = Path::new(env!("CARGO_MANIFEST_DIR")).join("../zinc_std");
+    let manifest = format!(
+        "[package]\nname = \"compile_run\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+         [workspace]\n\n[dependencies]\nzinc_std = {{ path = {:?}, default-features = false }}\n",
+        zinc_std
+    );
+    std::fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+    // The workspace lock keeps the scratch crate on the same versions.
+    let lock = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../Cargo.lock");
+    if lock.exists() {
+        std::fs::copy(lock, dir.join("Cargo.lock")).unwrap();
+    }
+    for (name, source, _) in CASES {
+        let program = transpile_program(source).unwrap();
+        let main = format!(
+            "{}fn main() {{\n{{\n{}\n}}\n}}\n",
+            program.items, program.main
+        );
+        std::fs::write(bins.join(format!("{}.rs", name)), main).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn generated_programs_compile_and_run() {
+    let dir = scratch_crate();
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    for (name, source, expected) in CASES {
+        let output = Command::new(&cargo)
+            .args(["run", "-q", "--bin", name])
+            .current_dir(&dir)
+            .env("CARGO_TARGET_DIR", dir.join("target"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{} failed to build or run:\n{}\n{}",
+            name,
+            source,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            *expected,
+            "{}",
+            name
+        );
+    }
+}
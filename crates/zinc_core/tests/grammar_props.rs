@@ -0,0 +1,81 @@
+// Property tests: random programs drawn from the grammar must transpile
+// without panicking and produce Rust that `syn` accepts.
+
+use proptest::prelude::*;
+use zinc_core::transpile_with_error;
+
+fn ident() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["a", "b", "items", "url", "page", "count"]).prop_map(str::to_string)
+}
+
+fn expr() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        (0u32..1000).prop_map(|n| n.to_string()),
+        "[a-zA-Z0-9 ]{0,12}".prop_map(|s| format!("\"{}\"", s)),
+        ident(),
+    ];
+    leaf.prop_recursive(4, 32, 4, |inner| {
+        let ops = prop::sample::select(vec!["==", "!=", "<", ">", "<=", ">=", "+"]);
+        prop_oneof![
+            (inner.clone(), ops, inner.clone())
+                .prop_map(|(lhs, op, rhs)| format!("{} {} {}", lhs, op, rhs)),
+            prop::collection::vec(inner.clone(), 0..4)
+                .prop_map(|items| format!("[{}]", items.join(", "))),
+            prop::collection::vec(("[a-z]{1,6}", inner.clone()), 0..3).prop_map(|fields| {
+                let fields = fields
+                    .iter()
+                    .map(|(k, v)| format!("\"{}\": {}", k, v))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", fields.join(", "))
+            }),
+            (ident(), prop::collection::vec(inner.clone(), 0..3)).prop_map(|(name, args)| format!(
+                "{}({})",
+                name,
+                args.join(", ")
+            )),
+            (inner.clone(), inner.clone()).prop_map(|(items, idx)| format!("({})[{}]", items, idx)),
+            inner.prop_map(|e| format!("({})", e)),
+        ]
+    })
+}
+
+fn statement() -> impl Strategy<Value = String> {
+    let simple = prop_oneof![
+        (ident(), expr()).prop_map(|(name, e)| format!("let {} = {};", name, e)),
+        expr().prop_map(|e| format!("print({});", e)),
+        expr().prop_map(|e| format!("{};", e)),
+    ];
+    simple.prop_recursive(3, 16, 4, |inner| {
+        let body = prop::collection::vec(inner, 0..4)
+            .prop_map(|stmts| format!("{{\n{}\n}}", stmts.join("\n")));
+        prop_oneof![
+            (expr(), body.clone()).prop_map(|(cond, b)| format!("if {} {}", cond, b)),
+            (expr(), body.clone(), body.clone())
+                .prop_map(|(cond, t, e)| format!("if {} {} else {}", cond, t, e)),
+            (ident(), expr(), body.clone())
+                .prop_map(|(var, e, b)| format!("for {} in {} {}", var, e, b)),
+            body.clone().prop_map(|b| format!("loop {}", b)),
+            body.prop_map(|b| format!("defer {}", b)),
+        ]
+    })
+}
+
+fn program() -> impl Strategy<Value = String> {
+    prop::collection::vec(statement(), 1..8).prop_map(|stmts| stmts.join("\n"))
+}
+
+proptest! {
+    #[test]
+    fn generated_programs_transpile_to_valid_rust(source in program()) {
+        let output = transpile_with_error(&source).map_err(|e| e.message);
+        prop_assert!(output.is_ok(), "{:?}\n{}", output, source);
+        let wrapped = format!("fn main() {{\n{}\n}}", output.unwrap_or_default());
+        let parsed = syn::parse_file(&wrapped);
+        prop_assert!(parsed.is_ok(), "{:?}\n{}\n{}", parsed.err(), source, wrapped);
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics(source in "\\PC{0,200}") {
+        let _ = transpile_with_error(&source);
+    }
+}
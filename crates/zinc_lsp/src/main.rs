@@ -10,6 +10,10 @@ use tower_lsp::{Client, LanguageServer, LspService, Server};
 struct Backend {
     client: Client,
     documents: Arc<RwLock<std::collections::HashMap<Url, String>>>,
+    parsers: Arc<RwLock<std::collections::HashMap<Url, zinc_core::IncrementalParser>>>,
+    // The semantic error from the last open or save, kept across keystrokes.
+    semantic: Arc<RwLock<std::collections::HashMap<Url, zinc_core::ZincError>>>,
+    cache: Option<zinc_core::Cache>,
 }
 
 #[tower_lsp::async_trait]
@@ -17,9 +21,15 @@ impl LanguageServer for Backend {
     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::FULL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
                 )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
                     trigger_characters: None,
@@ -48,7 +58,7 @@ impl LanguageServer for Backend {
         let uri = params.text_document.uri;
         let text = params.text_document.text;
         self.documents.write().await.insert(uri.clone(), text);
-        self.publish_diagnostics(uri).await;
+        self.publish_diagnostics(uri, true).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
@@ -56,7 +66,52 @@ impl LanguageServer for Backend {
         if let Some(change) = params.content_changes.into_iter().last() {
             self.documents.write().await.insert(uri.clone(), change.text);
         }
-        self.publish_diagnostics(uri).await;
+        self.publish_diagnostics(uri, false).await;
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        self.publish_diagnostics(params.text_document.uri, true)
+            .await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.write().await.remove(&uri);
+        self.parsers.write().await.remove(&uri);
+        self.semantic.write().await.remove(&uri);
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let text = match self.documents.read().await.get(&uri) {
+            Some(t) => t.clone(),
+            None => return Ok(None),
+        };
+
+        let word = match word_at(&text, position) {
+            Some(w) => w,
+            None => return Ok(None),
+        };
+        let docs = match zinc_core::function_docs(&text) {
+            Ok(docs) => docs,
+            Err(_) => return Ok(None),
+        };
+
+        Ok(docs.into_iter().find(|d| d.name == word).map(|d| {
+            let mut value = format!("```zinc\nfn {}({})\n```", d.name, d.params.join(", "));
+            if !d.doc.is_empty() {
+                value.push_str("\n\n");
+                value.push_str(&d.doc);
+            }
+            Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+                range: None,
+            }
+        }))
     }
 
     async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
@@ -72,18 +127,45 @@ impl LanguageServer for Backend {
 }
 
 impl Backend {
-    async fn publish_diagnostics(&self, uri: Url) {
+    // Keystrokes only get the incremental syntax check; opening and saving a
+    // document run the full transpiler for semantic diagnostics too, which
+    // stay shown until the next full run.
+    async fn publish_diagnostics(&self, uri: Url, full: bool) {
         let text = match self.documents.read().await.get(&uri) {
             Some(t) => t.clone(),
             None => String::new(),
         };
 
-        let diags = match zinc_core::transpile_with_error(&text) {
-            Ok(_) => Vec::new(),
-            Err(err) => {
+        let syntax = {
+            let mut parsers = self.parsers.write().await;
+            let parser = parsers.entry(uri.clone()).or_default();
+            parser.update(&text)
+        };
+        let mut semantic = self.semantic.write().await;
+        if full {
+            let result = match (&syntax, &self.cache) {
+                (Err(_), _) => Ok(()),
+                (Ok(()), Some(cache)) => cache.transpile(&text).map(|_| ()),
+                (Ok(()), None) => zinc_core::transpile_with_error(&text).map(|_| ()),
+            };
+            match result {
+                Ok(()) => semantic.remove(&uri),
+                Err(err) => semantic.insert(uri.clone(), err),
+            };
+        }
+        let errors = syntax
+            .err()
+            .into_iter()
+            .chain(semantic.get(&uri).cloned())
+            .collect::<Vec<_>>();
+        drop(semantic);
+
+        let diags = errors
+            .into_iter()
+            .map(|err| {
                 let line = err.line.saturating_sub(1);
                 let column = err.column.saturating_sub(1);
-                vec![Diagnostic {
+                Diagnostic {
                     range: Range {
                         start: Position {
                             line: line as u32,
@@ -98,9 +180,9 @@ impl Backend {
                     source: Some("zinc".to_string()),
                     message: err.message,
                     ..Default::default()
-                }]
-            }
-        };
+                }
+            })
+            .collect();
 
         self.client
             .publish_diagnostics(uri, diags, None)
@@ -108,6 +190,27 @@ impl Backend {
     }
 }
 
+fn word_at(text: &str, position: Position) -> Option<String> {
+    let line = text.lines().nth(position.line as usize)?;
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut start = (position.character as usize).min(chars.len());
+    while start > 0 && is_word(chars[start - 1]) {
+        start -= 1;
+    }
+    let mut end = start;
+    while end < chars.len() && is_word(chars[end]) {
+        end += 1;
+    }
+
+    if start == end {
+        None
+    } else {
+        Some(chars[start..end].iter().collect())
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let stdin = tokio::io::stdin();
@@ -116,6 +219,9 @@ async fn main() {
     let (service, socket) = LspService::new(|client| Backend {
         client,
         documents: Arc::new(RwLock::new(std::collections::HashMap::new())),
+        parsers: Arc::new(RwLock::new(std::collections::HashMap::new())),
+        semantic: Arc::new(RwLock::new(std::collections::HashMap::new())),
+        cache: zinc_core::Cache::user(),
     });
     Server::new(stdin, stdout, socket).serve(service).await;
 }
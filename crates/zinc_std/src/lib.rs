@@ -1,30 +1,992 @@
 ﻿// PLAN: 1. Add leak tracking counters -> 2. Expose leak check API -> 3. Add std.db query -> 4. Add std.fs/html/spider proxy -> 5. Add Python bridge
 // Library choice: std::sync::atomic is the safest zero-dependency counter.
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
 
-static LIVE_OBJECTS: AtomicUsize = AtomicUsize::new(0);
+// Live objects by kind: "task" for spawned work not yet finished and "leak"
+// for `leak()`. Values the script creates are counted by the allocator, see
+// `track_values`.
+static LIVE_OBJECTS: Mutex<BTreeMap<&'static str, usize>> = Mutex::new(BTreeMap::new());
+static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
 
+pub fn runtime() -> &'static tokio::runtime::Runtime {
+    RUNTIME.get_or_init(|| tokio::runtime::Runtime::new().unwrap())
+}
+
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    // Blocking calls made from inside async zinc code must not start a
+    // nested runtime, so hand the worker thread over instead.
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(fut)),
+        Err(_) => runtime().block_on(fut),
+    }
+}
+
+pub fn spawn<F>(fut: F) -> impl Future<Output = F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let live = Tracked::new("task", ());
+    let handle = runtime().spawn(async move {
+        let _live = live;
+        fut.await
+    });
+    async move { handle.await.expect("spawned task panicked") }
+}
+
+/// For host code counting objects of its own; generated code does not call it.
 pub fn track_alloc() {
-    LIVE_OBJECTS.fetch_add(1, Ordering::Relaxed);
+    track_alloc_of("object");
 }
 
 pub fn track_free() {
-    LIVE_OBJECTS.fetch_sub(1, Ordering::Relaxed);
+    track_free_of("object");
+}
+
+pub fn track_alloc_of(kind: &'static str) {
+    *LIVE_OBJECTS.lock().unwrap().entry(kind).or_insert(0) += 1;
+}
+
+pub fn track_free_of(kind: &'static str) {
+    if let Some(count) = LIVE_OBJECTS.lock().unwrap().get_mut(kind) {
+        *count = count.saturating_sub(1);
+    }
+}
+
+/// Counts a value as live until it is dropped.
+pub struct Tracked<T> {
+    kind: &'static str,
+    value: T,
+}
+
+impl<T> Tracked<T> {
+    pub fn new(kind: &'static str, value: T) -> Self {
+        track_alloc_of(kind);
+        Tracked { kind, value }
+    }
+}
+
+impl<T> std::ops::Deref for Tracked<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> std::ops::DerefMut for Tracked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        track_free_of(self.kind);
+    }
+}
+
+fn live_objects() -> Vec<(&'static str, usize)> {
+    LIVE_OBJECTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .map(|(kind, count)| (*kind, *count))
+        .collect()
 }
 
+/// Marks the start of the script. When built with `alloc-tracking`, every
+/// heap block allocated after this and still live at `check_leaks` is a
+/// zinc value the script never freed.
+pub fn track_values() {
+    // State that lives for the whole process is set up first so that it is
+    // not mistaken for the script's values.
+    let _ = runtime();
+    let _ = std::io::stdout();
+    LIVE_OBJECTS.lock().unwrap().entry("leak").or_insert(0);
+    LIVE_OBJECTS.lock().unwrap().entry("task").or_insert(0);
+    #[cfg(feature = "alloc-tracking")]
+    alloc_tracking::mark();
+}
+
+/// Reports spawned tasks that never finished, `leak()` calls and, when built
+/// with `alloc-tracking`, values still allocated since `track_values`. With
+/// `ZINC_LEAK_REPORT` set the counts are listed by kind, followed by the
+/// process's live heap.
 pub fn check_leaks() {
-    let count = LIVE_OBJECTS.load(Ordering::Relaxed);
-    if count > 0 {
-        eprintln!("Memory Leak Detected: {} objects leaked.", count);
+    // Read before anything here allocates.
+    #[cfg(feature = "alloc-tracking")]
+    let (value_blocks, value_bytes) = alloc_tracking::since_mark();
+    #[allow(unused_mut)]
+    let mut live = live_objects();
+    #[cfg(feature = "alloc-tracking")]
+    if value_blocks > 0 {
+        live.push(("value", value_blocks));
+    }
+    let total = live.iter().map(|(_, count)| count).sum::<usize>();
+    if total > 0 {
+        eprintln!("Memory Leak Detected: {} objects leaked.", total);
+    }
+    if std::env::var_os("ZINC_LEAK_REPORT").is_some() {
+        eprintln!("Leak report:");
+        for (kind, count) in &live {
+            eprintln!("  {:<12} {}", kind, count);
+        }
+        #[cfg(feature = "alloc-tracking")]
+        {
+            eprintln!("  {:<12} {} bytes", "value bytes", value_bytes);
+            let (blocks, bytes) = alloc_tracking::live();
+            eprintln!("  {:<12} {} blocks, {} bytes", "heap", blocks, bytes);
+        }
     }
 }
 
 pub fn leak() {
-    track_alloc();
+    track_alloc_of("leak");
     eprintln!("Leaking an object...");
 }
 
+/// Counts live heap blocks for `zn run --leak-report`. The numbers include
+/// long-lived runtime state such as the tokio runtime and caches.
+#[cfg(feature = "alloc-tracking")]
+mod alloc_tracking {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LIVE_BLOCKS: AtomicUsize = AtomicUsize::new(0);
+    static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+    static MARK: (AtomicUsize, AtomicUsize) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+    struct CountingAlloc;
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                LIVE_BLOCKS.fetch_add(1, Ordering::Relaxed);
+                LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            LIVE_BLOCKS.fetch_sub(1, Ordering::Relaxed);
+            LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                LIVE_BYTES.fetch_add(new_size, Ordering::Relaxed);
+                LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+            }
+            new_ptr
+        }
+    }
+
+    pub fn live() -> (usize, usize) {
+        (
+            LIVE_BLOCKS.load(Ordering::Relaxed),
+            LIVE_BYTES.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn mark() {
+        let (blocks, bytes) = live();
+        MARK.0.store(blocks, Ordering::Relaxed);
+        MARK.1.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Blocks and bytes allocated since `mark` and not freed yet.
+    pub fn since_mark() -> (usize, usize) {
+        let (blocks, bytes) = live();
+        (
+            blocks.saturating_sub(MARK.0.load(Ordering::Relaxed)),
+            bytes.saturating_sub(MARK.1.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cli, diff, fmt, parse, table};
+    use serde_json::json;
+
+    #[test]
+    fn diff_text_finds_the_shortest_edit() {
+        let out = diff::text("a\nb\nc\nd", "a\nc\nd\ne");
+        assert_eq!(
+            out,
+            json!({
+                "changed": true,
+                "added": 1,
+                "removed": 1,
+                "changes": [
+                    { "op": "removed", "line": 2, "text": "b" },
+                    { "op": "added", "line": 4, "text": "e" },
+                ],
+            })
+        );
+        assert_eq!(diff::text("same\n", "same\n")["changed"], json!(false));
+        assert_eq!(diff::text("", "x")["added"], json!(1));
+        assert_eq!(diff::text("x", "")["removed"], json!(1));
+    }
+
+    #[test]
+    fn diff_json_reports_paths() {
+        let old = json!({ "items": [{ "price": 1 }, { "price": 2 }], "gone": true });
+        let new = json!({ "items": [{ "price": 1 }, { "price": 3 }], "new": "x" });
+        assert_eq!(
+            diff::json(&old, &new)["changes"],
+            json!([
+                { "path": "gone", "op": "removed", "old": true },
+                { "path": "items[1].price", "op": "changed", "old": 2, "new": 3 },
+                { "path": "new", "op": "added", "new": "x" },
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_date_accepts_absolute_and_relative_dates() {
+        assert_eq!(
+            parse::date("2024-03-05T10:20:30Z"),
+            json!("2024-03-05T10:20:30Z")
+        );
+        assert_eq!(parse::date("garbage"), json!(null));
+        let yesterday = parse::date("yesterday");
+        let parsed = chrono::DateTime::parse_from_rfc3339(yesterday.as_str().unwrap()).unwrap();
+        let hours = (chrono::Utc::now() - parsed.with_timezone(&chrono::Utc)).num_hours();
+        assert!((23..=48).contains(&hours), "{} hours ago", hours);
+    }
+
+    #[test]
+    fn cli_parse_converts_flags_and_fills_defaults() {
+        let spec = json!({
+            "count": 3,
+            "name": { "type": "string", "required": true },
+            "verbose": false,
+        });
+        let args = ["--name=web", "--count", "5", "--verbose", "rest"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            cli::parse(&spec, &args).unwrap(),
+            json!({ "count": 5, "name": "web", "verbose": true, "_args": ["rest"] })
+        );
+        let args = ["--name", "a", "--no-verbose"].map(String::from).to_vec();
+        assert_eq!(cli::parse(&spec, &args).unwrap()["count"], json!(3));
+        assert_eq!(
+            cli::parse(&spec, &[]).unwrap_err(),
+            "Missing required flag --name"
+        );
+        let args = ["--name", "a", "--count", "x"].map(String::from).to_vec();
+        assert_eq!(
+            cli::parse(&spec, &args).unwrap_err(),
+            "--count expects int, got `x`"
+        );
+    }
+
+    #[test]
+    fn fmt_formats_placeholders() {
+        let args = [
+            fmt::arg(&1.23456),
+            fmt::arg("ab"),
+            fmt::arg(&7),
+            fmt::arg(&json!(2)),
+        ];
+        assert_eq!(
+            fmt::format("{:.2}|{:>4}|{:03}|{:*^5}|{{}}", &args),
+            "1.23|  ab|007|**2**|{}"
+        );
+        assert_eq!(fmt::show(&json!({ "a": [1, "x"] })), "{\"a\":[1,\"x\"]}");
+        assert_eq!(fmt::show("text"), "text");
+    }
+
+    #[test]
+    fn table_renders_aligned_columns() {
+        let rows = json!([{ "name": "apple", "qty": 3 }, { "name": "kiwi", "qty": 12 }]);
+        assert_eq!(
+            table::render(&rows, &json!({})),
+            "+-------+-----+\n\
+             | name  | qty |\n\
+             +-------+-----+\n\
+             | apple |   3 |\n\
+             | kiwi  |  12 |\n\
+             +-------+-----+"
+        );
+        let narrow = table::render(
+            &json!([{ "name": "watermelon" }]),
+            &json!({ "max_width": 4 }),
+        );
+        assert!(narrow.contains("| wat… |"), "{}", narrow);
+        assert_eq!(table::render(&json!([]), &json!({})), "(no rows)");
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn html_xpath_selects_elements_and_attributes() {
+        let page = "<ul><li>a</li><li class='x'>b</li><li>c</li></ul><a href='/next'>more</a>";
+        assert_eq!(super::html::xpath(page, "//li[@class='x']"), ["b"]);
+        assert_eq!(super::html::xpath(page, "//li[last()]"), ["c"]);
+        assert_eq!(super::html::xpath(page, "//ul/li[2]/@class"), ["x"]);
+        assert_eq!(
+            super::html::xpath(page, "//a[contains(text(), 'mo')]/@href"),
+            ["/next"]
+        );
+        assert!(super::html::xpath(page, "//li[").is_empty());
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn html_article_extracts_metadata_and_text() {
+        let body = "Zinc is a small language for scraping. ".repeat(10);
+        let page = format!(
+            "<html><head><title>Zinc 1.1 release notes | Zinc Blog</title>\
+             <meta name='author' content='Ada'>\
+             <meta property='article:published_time' content='2024-01-02'></head>\
+             <body><nav>Home About</nav><article><p>{}</p></article></body></html>",
+            body
+        );
+        let article = super::html::article(&page);
+        assert_eq!(article["title"], json!("Zinc 1.1 release notes"));
+        assert_eq!(article["byline"], json!("Ada"));
+        assert_eq!(article["published"], json!("2024-01-02"));
+        let content = article["content"].as_str().unwrap();
+        assert!(
+            content.starts_with("Zinc is a small language"),
+            "{}",
+            content
+        );
+        assert!(!content.contains("Home About"), "{}", content);
+    }
+}
+
+pub struct Defer<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> Drop for Defer<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.take() {
+            f();
+        }
+    }
+}
+
+pub fn defer<F: FnOnce()>(f: F) -> Defer<F> {
+    Defer(Some(f))
+}
+
+/// A number of seconds as zinc passes it: an integer or float literal
+/// (`i64`/`f64`), or a JSON number.
+pub trait Seconds {
+    fn seconds(&self) -> f64;
+}
+
+macro_rules! seconds {
+    ($($t:ty),*) => {$(
+        impl Seconds for $t {
+            fn seconds(&self) -> f64 {
+                *self as f64
+            }
+        }
+    )*};
+}
+
+seconds!(i32, i64, u32, u64, usize, f32, f64);
+
+impl Seconds for serde_json::Value {
+    fn seconds(&self) -> f64 {
+        self.as_f64().unwrap_or(f64::NAN)
+    }
+}
+
+/// Runs `f` with a deadline. Returns its result, or
+/// `{"error": {"kind": "timeout", ...}}` if it takes longer than `seconds`.
+/// The work itself cannot be interrupted and finishes in the background.
+pub fn timeout<S, T, F>(seconds: S, f: F) -> serde_json::Value
+where
+    S: Seconds,
+    T: Into<serde_json::Value> + Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let seconds = seconds.seconds();
+    let deadline = match std::time::Duration::try_from_secs_f64(seconds) {
+        Ok(deadline) => deadline,
+        Err(e) => {
+            summary::count_error();
+            let message = format!("timeout of {} seconds: {}", seconds, e);
+            return serde_json::json!({ "error": { "kind": "invalid", "message": message } });
+        }
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f().into());
+    });
+    match rx.recv_timeout(deadline) {
+        Ok(value) => value,
+        Err(_) => {
+            summary::count_error();
+            serde_json::json!({ "error": { "kind": "timeout", "seconds": seconds } })
+        }
+    }
+}
+
+/// Waits for a task started by a zinc `parallel` block, re-raising its panic.
+pub fn join<T>(task: std::thread::ScopedJoinHandle<'_, T>) -> T {
+    task.join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Calls `f` up to `times` times, sleeping `delay` seconds between attempts,
+/// until it returns something other than an `{"error": ...}` document
+/// (as a value or as JSON text). Returns the last result.
+pub fn retry<N, S, T, F>(times: N, delay: S, mut f: F) -> T
+where
+    N: Into<i64>,
+    S: Into<f64>,
+    T: serde::Serialize,
+    F: FnMut() -> T,
+{
+    let attempts = times.into().max(1);
+    let delay = std::time::Duration::from_secs_f64(delay.into().max(0.0));
+    let mut attempt = 1;
+    loop {
+        let result = f();
+        if attempt >= attempts || !is_error(&result) {
+            return result;
+        }
+        eprintln!("Attempt {}/{} failed; retrying", attempt, attempts);
+        attempt += 1;
+        std::thread::sleep(delay);
+    }
+}
+
+fn is_error<T: serde::Serialize>(result: &T) -> bool {
+    let value = match serde_json::to_value(result) {
+        Ok(serde_json::Value::String(text)) => serde_json::from_str(&text).unwrap_or_default(),
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    value.get("error").is_some()
+}
+
+static DRY_RUN: OnceLock<bool> = OnceLock::new();
+
+/// True under `zn run --dry-run`, where writes to files, databases and S3
+/// are logged to stderr instead of being carried out.
+pub fn dry_run() -> bool {
+    *DRY_RUN.get_or_init(|| std::env::var_os("ZINC_DRY_RUN").is_some())
+}
+
+// Logs the action and returns true if it should be skipped.
+pub(crate) fn skip_for_dry_run(action: std::fmt::Arguments) -> bool {
+    if dry_run() {
+        eprintln!("[dry-run] would {}", action);
+    }
+    dry_run()
+}
+
+/// What a zinc `catch` block receives. Inside `try`, the transpiler calls the
+/// `try_*` variants of std functions and converts their errors into this.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Error {
+    kind: String,
+    message: String,
+}
+
+impl Error {
+    pub fn new(kind: &str, message: impl ToString) -> Self {
+        Error {
+            kind: kind.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} error: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(feature = "http")]
+impl From<spider::HttpError> for Error {
+    fn from(err: spider::HttpError) -> Self {
+        Error::new(&err.kind, &err)
+    }
+}
+
+#[cfg(feature = "html")]
+impl From<html::SelectorError> for Error {
+    fn from(err: html::SelectorError) -> Self {
+        Error::new("selector", err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::new("io", err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::new("json", err)
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        Error::new("error", err)
+    }
+}
+
+type ExitHook = Box<dyn FnOnce() + Send>;
+
+static EXIT_HOOKS: Mutex<Vec<ExitHook>> = Mutex::new(Vec::new());
+static SIGNAL_WATCH: std::sync::Once = std::sync::Once::new();
+
+/// Registers cleanup to run when the program finishes, is interrupted with
+/// SIGINT/SIGTERM, or hits a `limits` guardrail. Hooks run once, newest first.
+pub fn on_exit<T, F: FnOnce() -> T + Send + 'static>(hook: F) {
+    EXIT_HOOKS.lock().unwrap().push(Box::new(move || {
+        hook();
+    }));
+    SIGNAL_WATCH.call_once(|| {
+        runtime().spawn(async {
+            let code = shutdown_signal().await;
+            run_exit_hooks();
+            std::process::exit(code);
+        });
+    });
+}
+
+pub fn run_exit_hooks() {
+    loop {
+        // Popped one at a time so a hook may register another.
+        let hook = EXIT_HOOKS.lock().unwrap().pop();
+        match hook {
+            Some(hook) => hook(),
+            None => break,
+        }
+    }
+}
+
+// Resolves to the conventional exit status for the signal received.
+async fn shutdown_signal() -> i32 {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            return tokio::select! {
+                _ = tokio::signal::ctrl_c() => 130,
+                _ = term.recv() => 143,
+            };
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+    130
+}
+
+#[cfg(feature = "http")]
+pub mod auth {
+    use serde_json::Value;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    // Refresh this long before the server-side expiry so a token never goes
+    // stale mid-request.
+    const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+    pub struct OAuth2 {
+        token_url: String,
+        client_id: String,
+        secret: String,
+        scopes: Vec<String>,
+        token: Mutex<Option<Token>>,
+    }
+
+    struct Token {
+        access: String,
+        refresh: Option<String>,
+        expires_at: Option<Instant>,
+    }
+
+    /// A client-credentials OAuth2 client. Tokens are fetched lazily and
+    /// refreshed (via the refresh token when the server issued one) once
+    /// they are about to expire.
+    pub fn oauth2<S: AsRef<str>>(
+        token_url: &str,
+        client_id: &str,
+        secret: &str,
+        scopes: &[S],
+    ) -> OAuth2 {
+        OAuth2 {
+            token_url: token_url.to_string(),
+            client_id: client_id.to_string(),
+            secret: secret.to_string(),
+            scopes: scopes.iter().map(|s| s.as_ref().to_string()).collect(),
+            token: Mutex::new(None),
+        }
+    }
+
+    impl OAuth2 {
+        /// The current access token, or an empty string if the token
+        /// endpoint could not be reached.
+        pub fn token(&self) -> String {
+            let mut slot = self.token.lock().unwrap();
+            let fresh = slot.as_ref().map_or(false, |t| {
+                t.expires_at.map_or(true, |at| Instant::now() < at)
+            });
+            if !fresh {
+                let refresh = slot.as_ref().and_then(|t| t.refresh.clone());
+                *slot = self.request(refresh.as_deref()).or_else(|| {
+                    // A rejected refresh token falls back to a new grant.
+                    refresh.as_ref().and_then(|_| self.request(None))
+                });
+            }
+            slot.as_ref().map(|t| t.access.clone()).unwrap_or_default()
+        }
+
+        /// The `Authorization` header value for spider requests.
+        pub fn header(&self) -> String {
+            format!("Bearer {}", self.token())
+        }
+
+        fn request(&self, refresh: Option<&str>) -> Option<Token> {
+            let scope = self.scopes.join(" ");
+            let mut form = vec![
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.secret.as_str()),
+            ];
+            match refresh {
+                Some(token) => {
+                    form.push(("grant_type", "refresh_token"));
+                    form.push(("refresh_token", token));
+                }
+                None => form.push(("grant_type", "client_credentials")),
+            }
+            if !scope.is_empty() {
+                form.push(("scope", scope.as_str()));
+            }
+            let body = crate::spider::post_form(&self.token_url, &form).ok()?;
+            let json: Value = serde_json::from_str(&body).ok()?;
+            let access = json.get("access_token")?.as_str()?.to_string();
+            let expires_at = json.get("expires_in").and_then(|v| v.as_u64()).map(|secs| {
+                Instant::now() + Duration::from_secs(secs).saturating_sub(EXPIRY_MARGIN)
+            });
+            let refresh = json
+                .get("refresh_token")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+                .or_else(|| refresh.map(|r| r.to_string()));
+            Some(Token {
+                access,
+                refresh,
+                expires_at,
+            })
+        }
+    }
+}
+
+/// Flags for the script itself, passed after `--` in `zn run script.zn -- ...`.
+///
+/// A spec maps each flag to its default (`{"pages": 5}`) or to a map with
+/// `type` (`int`, `float`, `bool` or `string`), `default`, `required` and
+/// `help`. Dashes and underscores are interchangeable in flag names, and
+/// positional arguments end up in `_args`.
+pub mod cli {
+    use serde_json::{Map, Value};
+
+    pub fn flags(spec: &Value) -> Value {
+        let args = std::env::args().skip(1).collect::<Vec<_>>();
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            println!("{}", help(spec));
+            std::process::exit(0);
+        }
+        match parse(spec, &args) {
+            Ok(flags) => flags,
+            Err(message) => {
+                eprintln!("{}\n\n{}", message, help(spec));
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn parse(spec: &Value, args: &[String]) -> Result<Value, String> {
+        let options = options(spec);
+        let mut flags = Map::new();
+        let mut positional = Vec::new();
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            let flag = match arg.strip_prefix("--") {
+                Some(flag) if !flag.is_empty() => flag,
+                _ => {
+                    positional.push(Value::String(arg.clone()));
+                    continue;
+                }
+            };
+            let (name, inline) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (flag, None),
+            };
+            let name = name.replace('-', "_");
+            if let Some(option) = name
+                .strip_prefix("no_")
+                .and_then(|base| options.iter().find(|o| o.name == base))
+                .filter(|o| o.kind == "bool" && inline.is_none())
+            {
+                flags.insert(option.name.clone(), Value::Bool(false));
+                continue;
+            }
+            let option = options
+                .iter()
+                .find(|o| o.name == name)
+                .ok_or_else(|| format!("Unknown flag --{}", flag_name(&name)))?;
+            let raw = match (inline, option.kind.as_str()) {
+                (Some(raw), _) => raw,
+                (None, "bool") => "true".to_string(),
+                (None, _) => rest
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("--{} expects a value", flag_name(&name)))?,
+            };
+            flags.insert(option.name.clone(), convert(option, &raw)?);
+        }
+
+        for option in &options {
+            if flags.contains_key(&option.name) {
+                continue;
+            }
+            if option.required {
+                return Err(format!(
+                    "Missing required flag --{}",
+                    flag_name(&option.name)
+                ));
+            }
+            flags.insert(option.name.clone(), option.default.clone());
+        }
+        flags.insert("_args".to_string(), Value::Array(positional));
+        Ok(Value::Object(flags))
+    }
+
+    pub fn help(spec: &Value) -> String {
+        let script = std::env::var("ZINC_SCRIPT").unwrap_or_else(|_| "script.zn".to_string());
+        let mut lines = vec![
+            format!("Usage: zn run {} -- [flags] [args...]", script),
+            String::new(),
+            "Flags:".to_string(),
+        ];
+        let options = options(spec);
+        let usages = options
+            .iter()
+            .map(|o| match o.kind.as_str() {
+                "bool" => format!("--{}", flag_name(&o.name)),
+                kind => format!("--{} <{}>", flag_name(&o.name), kind),
+            })
+            .collect::<Vec<_>>();
+        let width = usages.iter().map(String::len).max().unwrap_or(0);
+        for (option, usage) in options.iter().zip(&usages) {
+            let mut line = format!("  {:<width$}  {}", usage, option.help);
+            if option.required {
+                line.push_str(" (required)");
+            } else if !option.default.is_null() && option.kind != "bool" {
+                line.push_str(&format!(" (default: {})", option.default));
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines.push(format!("  {:<width$}  Show this help", "--help"));
+        lines.join("\n")
+    }
+
+    struct Flag {
+        name: String,
+        kind: String,
+        default: Value,
+        required: bool,
+        help: String,
+    }
+
+    fn options(spec: &Value) -> Vec<Flag> {
+        let entries = match spec {
+            Value::Object(entries) => entries,
+            _ => return Vec::new(),
+        };
+        entries
+            .iter()
+            .map(|(name, entry)| {
+                let detailed = entry.as_object().filter(|e| {
+                    ["type", "default", "required", "help"]
+                        .iter()
+                        .any(|k| e.contains_key(*k))
+                });
+                let default = match detailed {
+                    Some(e) => e.get("default").cloned().unwrap_or(Value::Null),
+                    None => entry.clone(),
+                };
+                let kind = detailed
+                    .and_then(|e| e.get("type"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| kind_of(&default).to_string());
+                let default = match (&default, kind.as_str()) {
+                    (Value::Null, "bool") => Value::Bool(false),
+                    _ => default,
+                };
+                Flag {
+                    name: name.replace('-', "_"),
+                    kind,
+                    default,
+                    required: detailed
+                        .and_then(|e| e.get("required"))
+                        .and_then(Value::as_bool)
+                        .unwrap_or(false),
+                    help: detailed
+                        .and_then(|e| e.get("help"))
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                }
+            })
+            .collect()
+    }
+
+    fn kind_of(default: &Value) -> &'static str {
+        match default {
+            Value::Bool(_) => "bool",
+            Value::Number(n) if n.is_f64() => "float",
+            Value::Number(_) => "int",
+            _ => "string",
+        }
+    }
+
+    fn convert(option: &Flag, raw: &str) -> Result<Value, String> {
+        let invalid = || {
+            format!(
+                "--{} expects {}, got `{}`",
+                flag_name(&option.name),
+                option.kind,
+                raw
+            )
+        };
+        match option.kind.as_str() {
+            "int" => raw.parse::<i64>().map(Value::from).map_err(|_| invalid()),
+            "float" => raw.parse::<f64>().map(Value::from).map_err(|_| invalid()),
+            "bool" => match raw {
+                "true" | "yes" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "0" => Ok(Value::Bool(false)),
+                _ => Err(invalid()),
+            },
+            _ => Ok(Value::String(raw.to_string())),
+        }
+    }
+
+    fn flag_name(name: &str) -> String {
+        name.replace('_', "-")
+    }
+}
+
+#[cfg(feature = "clipboard")]
+pub mod clipboard {
+    use arboard::Clipboard;
+    use std::sync::Mutex;
+
+    // Kept open for the whole run: on X11 the copied text is only served
+    // while its owner is alive.
+    static CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+    fn with_clipboard<T>(
+        f: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>,
+    ) -> Result<T, arboard::Error> {
+        let mut slot = CLIPBOARD.lock().unwrap();
+        if slot.is_none() {
+            *slot = Some(Clipboard::new()?);
+        }
+        f(slot.as_mut().expect("clipboard was just opened"))
+    }
+
+    pub fn get() -> String {
+        with_clipboard(|c| c.get_text()).unwrap_or_else(|e| {
+            eprintln!("Clipboard error: {}", e);
+            String::new()
+        })
+    }
+
+    pub fn set(text: &str) -> bool {
+        with_clipboard(|c| c.set_text(text))
+            .map_err(|e| eprintln!("Clipboard error: {}", e))
+            .is_ok()
+    }
+}
+
+/// Project settings from `zinc.toml`, found by walking up from the script's
+/// directory, or from the working directory when no script is running.
+pub mod config {
+    use serde_json::Value;
+    use std::path::{Path, PathBuf};
+    use std::sync::OnceLock;
+
+    static CONFIG: OnceLock<Value> = OnceLock::new();
+
+    pub fn path() -> Option<PathBuf> {
+        find(
+            std::env::var_os("ZINC_SCRIPT")
+                .map(PathBuf::from)
+                .as_deref(),
+        )
+    }
+
+    /// The `zinc.toml` that applies to `script`.
+    pub fn find(script: Option<&Path>) -> Option<PathBuf> {
+        let script_dir = script
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .filter(|dir| !dir.as_os_str().is_empty());
+        let start = std::fs::canonicalize(script_dir.unwrap_or_else(|| PathBuf::from("."))).ok()?;
+        start
+            .ancestors()
+            .map(|dir| dir.join("zinc.toml"))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// The whole file as JSON, or `Null` without one. A file that does not
+    /// parse is reported once and otherwise ignored.
+    pub fn load() -> &'static Value {
+        CONFIG.get_or_init(|| {
+            let Some(path) = path() else {
+                return Value::Null;
+            };
+            read(&path).unwrap_or_else(|err| {
+                eprintln!("Ignoring {}: {}", path.display(), err);
+                Value::Null
+            })
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Value, String> {
+        std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| toml::from_str::<Value>(&text).map_err(|e| e.to_string()))
+    }
+}
+
+#[cfg(feature = "db")]
 pub mod db {
     use anyhow::Result;
     use serde_json::{json, Map, Value};
@@ -32,11 +994,21 @@ pub mod db {
     use sqlx::{Column, Row};
 
     pub fn query(url: &str, sql: &str) -> String {
-        query_inner(url, sql).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e))
+        try_query(url, sql).unwrap_or_else(|e| {
+            crate::summary::count_error();
+            format!("{{\"error\":\"{}\"}}", e)
+        })
     }
 
-    fn query_inner(url: &str, sql: &str) -> Result<String> {
-        tokio::runtime::Runtime::new().unwrap().block_on(async {
+    pub fn try_query(url: &str, sql: &str) -> Result<String> {
+        let _span = tracing::info_span!("db.query", db.statement = sql).entered();
+        if let Some(rows) = crate::mock::db_rows(sql) {
+            return Ok(rows.to_string());
+        }
+        if !is_read_only(sql) && crate::skip_for_dry_run(format_args!("run on {}: {}", url, sql)) {
+            return Ok("[]".to_string());
+        }
+        crate::block_on(async {
             sqlx::any::install_default_drivers();
             let pool = AnyPoolOptions::new().max_connections(5).connect(url).await?;
             let rows = sqlx::query(sql).fetch_all(&pool).await?;
@@ -45,7 +1017,26 @@ pub mod db {
         })
     }
 
-    fn rows_to_json(rows: &[AnyRow]) -> Value {
+    // Statements that can run for real under `--dry-run`.
+    pub(crate) fn is_read_only(sql: &str) -> bool {
+        let lower = sql.to_ascii_lowercase();
+        let mut words = lower
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .filter(|word| !word.is_empty());
+        match words.next().unwrap_or_default() {
+            "select" | "show" | "explain" | "describe" => true,
+            // `WITH ... DELETE`, or a CTE that writes: when in doubt, a write.
+            "with" => !words.any(|word| {
+                matches!(
+                    word,
+                    "insert" | "update" | "delete" | "merge" | "replace" | "upsert" | "into"
+                )
+            }),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn rows_to_json(rows: &[AnyRow]) -> Value {
         let mut out = Vec::new();
         for row in rows {
             let mut map = Map::new();
@@ -79,97 +1070,6218 @@ pub mod db {
     }
 }
 
-pub mod fs {
-    pub fn read(path: &str) -> String {
-        std::fs::read_to_string(path).unwrap_or_default()
+pub mod diff {
+    use serde_json::{json, Value};
+
+    /// Line diff of two texts:
+    /// `{"changed", "added", "removed", "changes": [{"op", "line", "text"}]}`.
+    /// `line` is 1-based in the old text for removals and in the new text
+    /// for additions.
+    pub fn text(old: &str, new: &str) -> Value {
+        let a = old.lines().collect::<Vec<_>>();
+        let b = new.lines().collect::<Vec<_>>();
+        let mut changes = Vec::new();
+        let (mut added, mut removed) = (0, 0);
+        for edit in myers(&a, &b) {
+            match edit {
+                Edit::Keep => {}
+                Edit::Delete(i) => {
+                    removed += 1;
+                    changes.push(json!({ "op": "removed", "line": i + 1, "text": a[i] }));
+                }
+                Edit::Insert(j) => {
+                    added += 1;
+                    changes.push(json!({ "op": "added", "line": j + 1, "text": b[j] }));
+                }
+            }
+        }
+        json!({
+            "changed": !changes.is_empty(),
+            "added": added,
+            "removed": removed,
+            "changes": changes,
+        })
     }
 
-    pub fn write(path: &str, content: &str) {
-        let _ = std::fs::write(path, content);
+    /// Structural diff of two JSON values:
+    /// `{"changed", "changes": [{"path", "op", "old", "new"}]}` with paths
+    /// like `items[2].price`. Arrays are compared index by index.
+    pub fn json(old: &Value, new: &Value) -> Value {
+        let mut changes = Vec::new();
+        diff_values("", old, new, &mut changes);
+        json!({
+            "changed": !changes.is_empty(),
+            "changes": changes,
+        })
     }
-}
 
-pub mod html {
-    use scraper::{Html, Selector};
+    fn diff_values(path: &str, old: &Value, new: &Value, out: &mut Vec<Value>) {
+        match (old, new) {
+            (Value::Object(a), Value::Object(b)) => {
+                for (key, old_val) in a {
+                    let child = join_key(path, key);
+                    match b.get(key) {
+                        Some(new_val) => diff_values(&child, old_val, new_val, out),
+                        None => out.push(json!({ "path": child, "op": "removed", "old": old_val })),
+                    }
+                }
+                for (key, new_val) in b {
+                    if !a.contains_key(key) {
+                        let child = join_key(path, key);
+                        out.push(json!({ "path": child, "op": "added", "new": new_val }));
+                    }
+                }
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                for i in 0..a.len().max(b.len()) {
+                    let child = format!("{}[{}]", path, i);
+                    match (a.get(i), b.get(i)) {
+                        (Some(x), Some(y)) => diff_values(&child, x, y, out),
+                        (Some(x), None) => {
+                            out.push(json!({ "path": child, "op": "removed", "old": x }))
+                        }
+                        (None, Some(y)) => {
+                            out.push(json!({ "path": child, "op": "added", "new": y }))
+                        }
+                        (None, None) => {}
+                    }
+                }
+            }
+            _ if old != new => {
+                out.push(json!({ "path": path, "op": "changed", "old": old, "new": new }));
+            }
+            _ => {}
+        }
+    }
 
-    pub fn select_text(html: &str, selector: &str) -> Vec<String> {
-        let mut out = Vec::new();
-        let doc = Html::parse_document(html);
-        let sel = match Selector::parse(selector) {
-            Ok(s) => s,
-            Err(_) => return out,
+    fn join_key(path: &str, key: &str) -> String {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    }
+
+    enum Edit {
+        Keep,
+        Delete(usize),
+        Insert(usize),
+    }
+
+    // Myers' O(ND) shortest edit script, recovered by walking the saved
+    // frontier of each round backwards.
+    fn myers(a: &[&str], b: &[&str]) -> Vec<Edit> {
+        let (n, m) = (a.len() as isize, b.len() as isize);
+        let max = n + m;
+        let offset = max + 1;
+        let mut v = vec![0isize; 2 * max as usize + 3];
+        let mut trace = Vec::new();
+        'search: for d in 0..=max {
+            trace.push(v.clone());
+            for k in (-d..=d).step_by(2) {
+                let idx = (k + offset) as usize;
+                let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                    v[idx + 1]
+                } else {
+                    v[idx - 1] + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                v[idx] = x;
+                if x >= n && y >= m {
+                    break 'search;
+                }
+            }
+        }
+
+        let mut edits = Vec::new();
+        let (mut x, mut y) = (n, m);
+        for (d, v) in trace.iter().enumerate().rev() {
+            let d = d as isize;
+            let k = x - y;
+            let idx = (k + offset) as usize;
+            let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = v[(prev_k + offset) as usize];
+            let prev_y = prev_x - prev_k;
+            while x > prev_x && y > prev_y {
+                edits.push(Edit::Keep);
+                x -= 1;
+                y -= 1;
+            }
+            if d > 0 {
+                if x == prev_x {
+                    edits.push(Edit::Insert(y as usize - 1));
+                } else {
+                    edits.push(Edit::Delete(x as usize - 1));
+                }
+            }
+            x = prev_x;
+            y = prev_y;
+        }
+        edits.reverse();
+        edits
+    }
+}
+
+pub mod encoding {
+    use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+
+    /// Best guess at the encoding of `bytes`, e.g. "Shift_JIS" or "UTF-8".
+    pub fn detect<B: AsRef<[u8]>>(bytes: B) -> String {
+        guess(bytes.as_ref()).name().to_string()
+    }
+
+    /// Re-encodes `bytes` from one encoding to another. `from` may be "auto".
+    pub fn convert<B: AsRef<[u8]>>(bytes: B, from: &str, to: &str) -> Vec<u8> {
+        let bytes = bytes.as_ref();
+        let source = if from.trim().is_empty() || from.eq_ignore_ascii_case("auto") {
+            Some(guess(bytes))
+        } else {
+            Encoding::for_label(from.trim().as_bytes())
+        };
+        let (Some(source), Some(target)) = (source, Encoding::for_label(to.trim().as_bytes()))
+        else {
+            eprintln!("Unknown encoding in convert(\"{}\", \"{}\")", from, to);
+            return Vec::new();
+        };
+        let (text, _) = source.decode_with_bom_removal(bytes);
+        // encoding_rs only decodes UTF-16; encoding to it has to be done by hand.
+        if target == UTF_16LE {
+            return text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        }
+        if target == UTF_16BE {
+            return text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        }
+        target.encode(&text).0.into_owned()
+    }
+
+    fn guess(bytes: &[u8]) -> &'static Encoding {
+        if let Some((enc, _)) = Encoding::for_bom(bytes) {
+            return enc;
+        }
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(bytes, true);
+        detector.guess(None, true)
+    }
+}
+
+#[cfg(feature = "feed")]
+pub mod feed {
+    use serde_json::{json, Value};
+
+    /// Parses RSS 0.9x/1.0/2.0, Atom or JSON Feed into an array of
+    /// `{"title", "link", "date", "content"}` entries. `date` is RFC 3339
+    /// (published, else updated); `content` falls back to the summary.
+    /// Malformed feeds give `{"error": ...}`, like `db::query`.
+    pub fn parse(xml: &str) -> Value {
+        let feed = match feed_rs::parser::parse(xml.as_bytes()) {
+            Ok(feed) => feed,
+            Err(err) => return json!({ "error": err.to_string() }),
+        };
+        let entries = feed
+            .entries
+            .into_iter()
+            .map(|entry| {
+                let content = entry
+                    .content
+                    .and_then(|c| c.body)
+                    .or_else(|| entry.summary.map(|s| s.content));
+                json!({
+                    "id": entry.id,
+                    "title": entry.title.map(|t| t.content),
+                    "link": entry.links.first().map(|l| l.href.clone()),
+                    "date": entry.published.or(entry.updated).map(|d| d.to_rfc3339()),
+                    "content": content,
+                })
+            })
+            .collect();
+        Value::Array(entries)
+    }
+}
+
+pub mod fmt {
+    use serde_json::Value;
+
+    /// What `print` shows: text as it is, anything else as compact JSON, so
+    /// that a number in a `json::Value` prints as `3` rather than `Number(3)`.
+    pub fn show<T: serde::Serialize + ?Sized>(value: &T) -> String {
+        match serde_json::to_value(value) {
+            Ok(Value::String(text)) => text,
+            Ok(value) => value.to_string(),
+            Err(e) => format!("<{}>", e),
+        }
+    }
+
+    pub enum Arg {
+        Int(i64),
+        Float(f64),
+        Text(String),
+    }
+
+    pub trait ToArg {
+        fn to_arg(&self) -> Arg;
+    }
+
+    pub fn arg<T: ToArg + ?Sized>(val: &T) -> Arg {
+        val.to_arg()
+    }
+
+    impl<T: ToArg + ?Sized> ToArg for &T {
+        fn to_arg(&self) -> Arg {
+            (**self).to_arg()
+        }
+    }
+
+    impl ToArg for str {
+        fn to_arg(&self) -> Arg {
+            Arg::Text(self.to_string())
+        }
+    }
+
+    impl ToArg for String {
+        fn to_arg(&self) -> Arg {
+            Arg::Text(self.clone())
+        }
+    }
+
+    impl ToArg for bool {
+        fn to_arg(&self) -> Arg {
+            Arg::Text(self.to_string())
+        }
+    }
+
+    impl ToArg for i32 {
+        fn to_arg(&self) -> Arg {
+            Arg::Int(*self as i64)
+        }
+    }
+
+    impl ToArg for i64 {
+        fn to_arg(&self) -> Arg {
+            Arg::Int(*self)
+        }
+    }
+
+    impl ToArg for usize {
+        fn to_arg(&self) -> Arg {
+            Arg::Int(*self as i64)
+        }
+    }
+
+    impl ToArg for f64 {
+        fn to_arg(&self) -> Arg {
+            Arg::Float(*self)
+        }
+    }
+
+    impl ToArg for Value {
+        fn to_arg(&self) -> Arg {
+            match self {
+                Value::Number(n) if n.is_i64() => Arg::Int(n.as_i64().unwrap_or_default()),
+                Value::Number(n) => Arg::Float(n.as_f64().unwrap_or_default()),
+                Value::String(s) => Arg::Text(s.clone()),
+                other => Arg::Text(other.to_string()),
+            }
+        }
+    }
+
+    impl<T: ToArg> ToArg for Vec<T> {
+        fn to_arg(&self) -> Arg {
+            let parts = self
+                .iter()
+                .map(|item| apply(&item.to_arg(), ""))
+                .collect::<Vec<_>>();
+            Arg::Text(format!("[{}]", parts.join(", ")))
+        }
+    }
+
+    /// Formats `template` with Rust-style placeholders: `{}`, `{:>8}`, `{:<8}`,
+    /// `{:^8}`, `{:*^8}`, `{:08.2}` and `{:.2}`. `{{` and `}}` escape braces.
+    pub fn format(template: &str, args: &[Arg]) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut chars = template.chars().peekable();
+        let mut next = 0;
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    out.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    out.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                        spec.push(c);
+                    }
+                    if let Some(arg) = args.get(next) {
+                        out.push_str(&apply(arg, spec.strip_prefix(':').unwrap_or(&spec)));
+                    }
+                    next += 1;
+                }
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+
+    fn apply(arg: &Arg, spec: &str) -> String {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut idx = 0;
+        let mut fill = ' ';
+        let mut align = None;
+        if chars.len() >= 2 && matches!(chars[1], '<' | '>' | '^') {
+            fill = chars[0];
+            align = Some(chars[1]);
+            idx = 2;
+        } else if !chars.is_empty() && matches!(chars[0], '<' | '>' | '^') {
+            align = Some(chars[0]);
+            idx = 1;
+        }
+
+        let mut zero = false;
+        if chars.get(idx) == Some(&'0') {
+            zero = true;
+            idx += 1;
+        }
+
+        let mut width = 0;
+        while let Some(d) = chars.get(idx).and_then(|c| c.to_digit(10)) {
+            width = width * 10 + d as usize;
+            idx += 1;
+        }
+
+        let mut precision = None;
+        if chars.get(idx) == Some(&'.') {
+            idx += 1;
+            let mut p = 0;
+            while let Some(d) = chars.get(idx).and_then(|c| c.to_digit(10)) {
+                p = p * 10 + d as usize;
+                idx += 1;
+            }
+            precision = Some(p);
+        }
+
+        let (body, numeric) = match arg {
+            Arg::Int(v) => match precision {
+                Some(p) => (format!("{:.*}", p, *v as f64), true),
+                None => (v.to_string(), true),
+            },
+            Arg::Float(v) => match precision {
+                Some(p) => (format!("{:.*}", p, v), true),
+                None => (v.to_string(), true),
+            },
+            Arg::Text(s) => match precision {
+                Some(p) => (s.chars().take(p).collect(), false),
+                None => (s.clone(), false),
+            },
         };
+
+        let len = body.chars().count();
+        if len >= width {
+            return body;
+        }
+        let pad = width - len;
+
+        if zero && numeric && align.is_none() {
+            let (sign, digits) = match body.strip_prefix('-') {
+                Some(rest) => ("-", rest.to_string()),
+                None => ("", body.clone()),
+            };
+            return format!("{}{}{}", sign, "0".repeat(pad), digits);
+        }
+
+        let align = align.unwrap_or(if numeric { '>' } else { '<' });
+        let fill_str = |n: usize| fill.to_string().repeat(n);
+        match align {
+            '>' => format!("{}{}", fill_str(pad), body),
+            '^' => format!("{}{}{}", fill_str(pad / 2), body, fill_str(pad - pad / 2)),
+            _ => format!("{}{}", body, fill_str(pad)),
+        }
+    }
+}
+
+pub mod fs {
+    pub fn read(path: &str) -> String {
+        try_read(path).unwrap_or_default()
+    }
+
+    pub fn try_read(path: &str) -> std::io::Result<String> {
+        let _span = tracing::info_span!("fs.read", file.path = path).entered();
+        if let Some(content) = crate::mock::fs_content(path) {
+            return Ok(content);
+        }
+        std::fs::read_to_string(path)
+    }
+
+    pub fn read_bytes(path: &str) -> Vec<u8> {
+        std::fs::read(path).unwrap_or_default()
+    }
+
+    pub fn write(path: &str, content: &str) {
+        let _ = try_write(path, content);
+    }
+
+    pub fn try_write(path: &str, content: &str) -> std::io::Result<()> {
+        let _span = tracing::info_span!("fs.write", file.path = path).entered();
+        if crate::skip_for_dry_run(format_args!("write {} bytes to {}", content.len(), path)) {
+            return Ok(());
+        }
+        std::fs::write(path, content)
+    }
+
+    pub fn write_bytes(path: &str, content: &[u8]) {
+        if crate::skip_for_dry_run(format_args!("write {} bytes to {}", content.len(), path)) {
+            return;
+        }
+        let _ = std::fs::write(path, content);
+    }
+
+    pub fn remove(path: &str) {
+        if crate::skip_for_dry_run(format_args!("remove {}", path)) {
+            return;
+        }
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+pub mod fuzzy {
+    use serde_json::{json, Value};
+
+    /// Similarity from 0 to 100, ignoring case and runs of whitespace.
+    pub fn ratio(a: &str, b: &str) -> i64 {
+        (strsim::normalized_levenshtein(&normalize(a), &normalize(b)) * 100.0).round() as i64
+    }
+
+    /// The closest candidate as `{"match", "index", "score"}`, or null when
+    /// there are none. Jaro-Winkler breaks ties in favour of shared prefixes.
+    pub fn best_match<H>(needle: &str, haystack: H) -> Value
+    where
+        H: crate::iter::Items,
+        H::Item: Into<Value>,
+    {
+        let needle = normalize(needle);
+        let mut best: Option<(i64, f64, usize, String)> = None;
+        for (index, candidate) in haystack.items().enumerate() {
+            let text = match candidate.into() {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            let normalized = normalize(&text);
+            let score =
+                (strsim::normalized_levenshtein(&needle, &normalized) * 100.0).round() as i64;
+            let tiebreak = strsim::jaro_winkler(&needle, &normalized);
+            let better = match &best {
+                None => true,
+                Some((s, t, _, _)) => score > *s || (score == *s && tiebreak > *t),
+            };
+            if better {
+                best = Some((score, tiebreak, index, text));
+            }
+        }
+        match best {
+            Some((score, _, index, text)) => {
+                json!({ "match": text, "index": index, "score": score })
+            }
+            None => Value::Null,
+        }
+    }
+
+    fn normalize(s: &str) -> String {
+        s.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+}
+
+#[cfg(feature = "html")]
+pub mod html {
+    use ego_tree::NodeRef;
+    use scraper::{ElementRef, Html, Node, Selector};
+    use serde_json::{json, Map, Value};
+    use std::collections::{HashMap, HashSet};
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+
+    const SELECTOR_CACHE_SIZE: usize = 256;
+
+    // Least-recently-used cache of parsed selectors; each entry remembers the
+    // tick it was last handed out on.
+    struct SelectorCache {
+        entries: HashMap<String, (Arc<Selector>, u64)>,
+        tick: u64,
+    }
+
+    static SELECTORS: Mutex<Option<SelectorCache>> = Mutex::new(None);
+
+    #[derive(Debug, Clone)]
+    pub struct SelectorError {
+        pub selector: String,
+        pub message: String,
+    }
+
+    impl fmt::Display for SelectorError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Invalid CSS selector `{}`: {}",
+                self.selector, self.message
+            )
+        }
+    }
+
+    impl std::error::Error for SelectorError {}
+
+    pub fn parse_selector(raw: &str) -> Result<Arc<Selector>, SelectorError> {
+        let mut guard = SELECTORS.lock().unwrap();
+        let cache = guard.get_or_insert_with(|| SelectorCache {
+            entries: HashMap::new(),
+            tick: 0,
+        });
+        cache.tick += 1;
+        let tick = cache.tick;
+        if let Some((sel, used)) = cache.entries.get_mut(raw) {
+            *used = tick;
+            return Ok(sel.clone());
+        }
+        let sel = Selector::parse(raw).map_err(|e| SelectorError {
+            selector: raw.to_string(),
+            message: e.to_string(),
+        })?;
+        if cache.entries.len() >= SELECTOR_CACHE_SIZE {
+            let oldest = cache
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                cache.entries.remove(&key);
+            }
+        }
+        let sel = Arc::new(sel);
+        cache.entries.insert(raw.to_string(), (sel.clone(), tick));
+        Ok(sel)
+    }
+
+    // An invalid selector is reported rather than quietly matching nothing;
+    // use `try_select_text` (what `try` blocks call) to get the error.
+    pub fn select_text(html: &str, selector: &str) -> Vec<String> {
+        try_select_text(html, selector).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            Vec::new()
+        })
+    }
+
+    pub fn try_select_text(html: &str, selector: &str) -> Result<Vec<String>, SelectorError> {
+        let sel = parse_selector(selector)?;
+        let doc = Html::parse_document(html);
+        let mut out = Vec::new();
         for el in doc.select(&sel) {
             let text = el.text().collect::<String>().trim().to_string();
             if !text.is_empty() {
                 out.push(text);
             }
         }
-        out
+        Ok(out)
     }
-}
 
-pub mod json {
-    use serde_json::Value;
+    const SKIPPED_TAGS: &[&str] = &["script", "style", "noscript", "template", "head"];
+    const BLOCK_TAGS: &[&str] = &[
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "dd",
+        "div",
+        "dl",
+        "dt",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hr",
+        "li",
+        "main",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "table",
+        "tr",
+        "ul",
+    ];
 
-    pub fn parse(s: &str) -> Value {
-        serde_json::from_str(s).unwrap_or(Value::Null)
+    /// Readable plain text: scripts and styles dropped, block elements and
+    /// `<br>` start new lines, runs of whitespace collapse, and at most one
+    /// blank line separates paragraphs.
+    pub fn to_text(html: &str) -> String {
+        let doc = Html::parse_document(html);
+        let mut raw = String::new();
+        render_text(doc.tree.root(), &mut raw);
+        let mut out = String::new();
+        let mut blank = false;
+        for line in raw.lines() {
+            let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+            if line.is_empty() {
+                blank = !out.is_empty();
+                continue;
+            }
+            if !out.is_empty() {
+                out.push_str(if blank { "\n\n" } else { "\n" });
+            }
+            out.push_str(&line);
+            blank = false;
+        }
+        out
     }
 
-    pub fn get(val: &Value, key: &str) -> Value {
-        val.get(key).cloned().unwrap_or(Value::Null)
+    fn render_text(node: NodeRef<'_, Node>, out: &mut String) {
+        match node.value() {
+            Node::Text(text) => out.push_str(&text.replace('\n', " ")),
+            Node::Element(el) => {
+                let name = el.name();
+                if SKIPPED_TAGS.contains(&name) {
+                    return;
+                }
+                if name == "br" {
+                    out.push('\n');
+                    return;
+                }
+                let block = BLOCK_TAGS.contains(&name);
+                let paragraph = matches!(name, "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6");
+                if block {
+                    out.push_str(if paragraph { "\n\n" } else { "\n" });
+                }
+                for child in node.children() {
+                    render_text(child, out);
+                    if name == "tr" && child.value().is_element() {
+                        out.push('\t');
+                    }
+                }
+                if block {
+                    out.push_str(if paragraph { "\n\n" } else { "\n" });
+                }
+            }
+            _ => {
+                for child in node.children() {
+                    render_text(child, out);
+                }
+            }
+        }
     }
 
-    pub fn at(val: &Value, idx: usize) -> Value {
-        match val {
-            Value::Array(items) => items.get(idx).cloned().unwrap_or(Value::Null),
-            _ => Value::Null,
+    /// Removes all markup and keeps the text exactly as written, minus the
+    /// contents of `<script>` and `<style>`.
+    pub fn strip_tags(html: &str) -> String {
+        let doc = Html::parse_fragment(html);
+        let mut out = String::new();
+        for node in doc.tree.root().descendants() {
+            if let Node::Text(text) = node.value() {
+                let hidden = node.ancestors().any(|a| {
+                    a.value()
+                        .as_element()
+                        .is_some_and(|el| matches!(el.name(), "script" | "style"))
+                });
+                if !hidden {
+                    out.push_str(text);
+                }
+            }
         }
+        out
     }
 
-    pub fn to_string(val: &Value) -> String {
-        serde_json::to_string(val).unwrap_or_else(|_| "null".to_string())
+    /// Readability-style extraction. Returns
+    /// `{"title", "byline", "published", "content", "length"}`; fields that
+    /// can't be found are `null`, and `content` is plain text.
+    pub fn article(html: &str) -> Value {
+        let doc = Html::parse_document(html);
+        let title = meta_content(
+            &doc,
+            &["meta[property='og:title']", "meta[name='twitter:title']"],
+        )
+        .or_else(|| first_text(&doc, "title").map(|t| strip_site_suffix(&t)))
+        .or_else(|| first_text(&doc, "h1"));
+        let byline = meta_content(
+            &doc,
+            &["meta[name='author']", "meta[property='article:author']"],
+        )
+        .or_else(|| {
+            first_text(
+                &doc,
+                "[rel='author'], [itemprop='author'], .byline, .author",
+            )
+        });
+        let published = meta_content(
+            &doc,
+            &[
+                "meta[property='article:published_time']",
+                "meta[itemprop='datePublished']",
+                "meta[name='date']",
+                "meta[name='pubdate']",
+            ],
+        )
+        .or_else(|| {
+            let sel = parse_selector("time[datetime]").ok()?;
+            doc.select(&sel)
+                .next()
+                .and_then(|t| t.value().attr("datetime"))
+                .map(str::to_string)
+        });
+        let content = best_candidate(&doc).map(|el| to_text(&el.html()));
+        json!({
+            "title": title,
+            "byline": byline,
+            "published": published,
+            "length": content.as_ref().map_or(0, |c| c.chars().count()),
+            "content": content,
+        })
     }
-}
 
-pub mod python {
-    use pyo3::prelude::*;
-    use std::ffi::CString;
+    const POSITIVE_HINTS: &[&str] = &[
+        "article", "body", "content", "entry", "main", "page", "post", "story", "text",
+    ];
+    const NEGATIVE_HINTS: &[&str] = &[
+        "comment", "footer", "sidebar", "nav", "menu", "share", "related", "promo", "widget",
+        "banner", "ad-", "sponsor",
+    ];
 
-    pub fn eval(code: &str) -> String {
-        let code_c = CString::new(code).unwrap();
-        Python::with_gil(|py| {
-            let result = py.eval(&code_c, None, None);
-            match result {
-                Ok(value) => value.to_string(),
-                Err(err) => err.to_string(),
+    // Paragraphs vote for their parent (and half for the grandparent); the
+    // container with the best score, discounted by link density, wins.
+    fn best_candidate(doc: &Html) -> Option<ElementRef<'_>> {
+        let paragraphs = parse_selector("p, pre, td").ok()?;
+        let mut scores: Vec<(ElementRef<'_>, f64)> = Vec::new();
+        for p in doc.select(&paragraphs) {
+            let text = p.text().collect::<String>();
+            let text = text.trim();
+            if text.chars().count() < 25 {
+                continue;
+            }
+            let score = 1.0 + text.matches(',').count() as f64 + (text.len() / 100).min(3) as f64;
+            let parent = p.parent().and_then(ElementRef::wrap);
+            let grandparent = parent.and_then(|e| e.parent()).and_then(ElementRef::wrap);
+            for (el, share) in [(parent, 1.0), (grandparent, 0.5)] {
+                let Some(el) = el else { continue };
+                match scores.iter_mut().find(|(e, _)| e.id() == el.id()) {
+                    Some((_, total)) => *total += score * share,
+                    None => scores.push((el, class_weight(el) + score * share)),
+                }
             }
+        }
+        scores
+            .into_iter()
+            .map(|(el, score)| (el, score * (1.0 - link_density(el))))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(el, _)| el)
+    }
+
+    fn class_weight(el: ElementRef<'_>) -> f64 {
+        let hints = format!(
+            "{} {}",
+            el.value().attr("class").unwrap_or(""),
+            el.value().attr("id").unwrap_or("")
+        )
+        .to_ascii_lowercase();
+        let mut weight = 0.0;
+        if POSITIVE_HINTS.iter().any(|h| hints.contains(h)) {
+            weight += 25.0;
+        }
+        if NEGATIVE_HINTS.iter().any(|h| hints.contains(h)) {
+            weight -= 25.0;
+        }
+        if matches!(el.value().name(), "article" | "main") {
+            weight += 10.0;
+        }
+        weight
+    }
+
+    fn link_density(el: ElementRef<'_>) -> f64 {
+        let total = el.text().map(|t| t.trim().len()).sum::<usize>();
+        if total == 0 {
+            return 0.0;
+        }
+        let links = parse_selector("a").expect("static selector");
+        let linked = el
+            .select(&links)
+            .flat_map(|a| a.text())
+            .map(|t| t.trim().len())
+            .sum::<usize>();
+        linked as f64 / total as f64
+    }
+
+    fn meta_content(doc: &Html, selectors: &[&str]) -> Option<String> {
+        selectors.iter().find_map(|raw| {
+            let sel = parse_selector(raw).ok()?;
+            doc.select(&sel)
+                .filter_map(|m| m.value().attr("content"))
+                .map(|c| c.trim().to_string())
+                .find(|c| !c.is_empty())
         })
     }
-}
 
-pub mod spider {
-    use wreq::Client;
-    use wreq_util::Emulation;
+    fn first_text(doc: &Html, selector: &str) -> Option<String> {
+        let sel = parse_selector(selector).ok()?;
+        doc.select(&sel)
+            .map(|el| el.text().collect::<Vec<_>>().join(" "))
+            .map(|t| t.split_whitespace().collect::<Vec<_>>().join(" "))
+            .find(|t| !t.is_empty())
+    }
 
-    pub fn get(url: &str, profile: Option<&str>) -> String {
-        get_with_proxy(url, profile, None)
+    // "Story headline | Example News" -> "Story headline"
+    fn strip_site_suffix(title: &str) -> String {
+        for sep in [" | ", " - ", " — ", " :: "] {
+            if let Some((head, _)) = title.rsplit_once(sep) {
+                if head.split_whitespace().count() >= 3 {
+                    return head.trim().to_string();
+                }
+            }
+        }
+        title.trim().to_string()
     }
 
-    pub fn get_with_proxy(url: &str, profile: Option<&str>, proxy: Option<&str>) -> String {
-        tokio::runtime::Runtime::new().unwrap().block_on(async {
-            let emu = match profile.unwrap_or("chrome") {
-                "safari" => Emulation::Safari26,
-                _ => Emulation::Chrome124,
+    /// Structured data in one object: `{"opengraph": {...}, "twitter": {...},
+    /// "jsonld": [...], "microdata": [...]}`. OpenGraph and Twitter keys
+    /// drop their `og:` / `twitter:` prefix; unparseable JSON-LD is skipped.
+    pub fn metadata(html: &str) -> Value {
+        let doc = Html::parse_document(html);
+        let mut opengraph = Map::new();
+        let mut twitter = Map::new();
+        let metas = parse_selector("meta").expect("static selector");
+        for meta in doc.select(&metas) {
+            let el = meta.value();
+            let key = el
+                .attr("property")
+                .or_else(|| el.attr("name"))
+                .unwrap_or("");
+            let Some(content) = el.attr("content") else {
+                continue;
             };
+            let (target, name) = if let Some(name) = key.strip_prefix("og:") {
+                (&mut opengraph, name)
+            } else if let Some(name) = key.strip_prefix("twitter:") {
+                (&mut twitter, name)
+            } else {
+                continue;
+            };
+            // Repeated tags (og:image, ...) become arrays.
+            let value = Value::String(content.to_string());
+            match target.get_mut(name) {
+                Some(Value::Array(items)) => items.push(value),
+                Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+                None => {
+                    target.insert(name.to_string(), value);
+                }
+            }
+        }
 
-            let mut builder = Client::builder().emulation(emu);
-            if let Some(proxy_url) = proxy {
-                builder = builder.proxy(wreq::Proxy::all(proxy_url).unwrap());
+        let scripts =
+            parse_selector("script[type='application/ld+json']").expect("static selector");
+        let mut jsonld = Vec::new();
+        for script in doc.select(&scripts) {
+            let raw = script.text().collect::<String>();
+            match serde_json::from_str::<Value>(raw.trim()) {
+                Ok(Value::Array(items)) => jsonld.extend(items),
+                Ok(item) => jsonld.push(item),
+                Err(_) => {}
             }
+        }
 
-            let client = builder.build().unwrap();
-            client.get(url).send().await.unwrap().text().await.unwrap()
+        let scopes = parse_selector("[itemscope]:not([itemprop])").expect("static selector");
+        let microdata = doc.select(&scopes).map(microdata_item).collect::<Vec<_>>();
+
+        json!({
+            "opengraph": opengraph,
+            "twitter": twitter,
+            "jsonld": jsonld,
+            "microdata": microdata,
+        })
+    }
+
+    fn microdata_item(scope: ElementRef<'_>) -> Value {
+        let mut properties = Map::new();
+        collect_microdata(*scope, &mut properties);
+        json!({
+            "type": scope.value().attr("itemtype"),
+            "properties": properties,
         })
     }
+
+    fn collect_microdata(node: NodeRef<'_, Node>, properties: &mut Map<String, Value>) {
+        for child in node.children() {
+            let Some(el) = ElementRef::wrap(child) else {
+                continue;
+            };
+            let nested = el.value().attr("itemscope").is_some();
+            if let Some(names) = el.value().attr("itemprop") {
+                let value = if nested {
+                    microdata_item(el)
+                } else {
+                    Value::String(microdata_value(el))
+                };
+                for name in names.split_whitespace() {
+                    match properties.get_mut(name) {
+                        Some(Value::Array(items)) => items.push(value.clone()),
+                        Some(existing) => {
+                            *existing = Value::Array(vec![existing.take(), value.clone()])
+                        }
+                        None => {
+                            properties.insert(name.to_string(), value.clone());
+                        }
+                    }
+                }
+            }
+            // A nested item owns everything below it.
+            if !nested {
+                collect_microdata(child, properties);
+            }
+        }
+    }
+
+    fn microdata_value(el: ElementRef<'_>) -> String {
+        let attr = match el.value().name() {
+            "meta" => "content",
+            "a" | "link" | "area" => "href",
+            "img" | "audio" | "video" | "source" | "iframe" | "embed" => "src",
+            "time" => "datetime",
+            "data" | "meter" => "value",
+            "object" => "data",
+            _ => "",
+        };
+        match el.value().attr(attr) {
+            Some(value) => value.to_string(),
+            None => el
+                .text()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// Evaluates an XPath 1.0 subset: location paths (relative ones start at
+    /// the document), the child, descendant(-or-self), parent, self, ancestor
+    /// and sibling axes, `@attr`, `text()`, `node()`, `*`, and the predicates
+    /// `[n]`, `[last()]`, `[@a]`, `[name]`, `[x='v']`, `[contains(x, 'v')]`
+    /// and `[starts-with(x, 'v')]` where `x` is `@a`, `text()` or `.`.
+    /// Elements yield their trimmed text; attributes and text nodes their value.
+    pub fn xpath(html: &str, expr: &str) -> Vec<String> {
+        let steps = match parse_xpath(expr) {
+            Some(steps) => steps,
+            None => return Vec::new(),
+        };
+        let doc = Html::parse_document(html);
+        let mut context = vec![doc.tree.root()];
+        let mut out = Vec::new();
+        for (i, step) in steps.iter().enumerate() {
+            if step.axis == Axis::Attribute {
+                // Attributes have no children, so nothing may follow them.
+                if i + 1 != steps.len() {
+                    return Vec::new();
+                }
+                for el in context.iter().filter_map(|n| n.value().as_element()) {
+                    match &step.test {
+                        NodeTest::Name(name) => out.extend(el.attr(name).map(str::to_string)),
+                        _ => out.extend(el.attrs().map(|(_, v)| v.to_string())),
+                    }
+                }
+                return out;
+            }
+            let mut seen = HashSet::new();
+            let mut next = Vec::new();
+            for node in &context {
+                let candidates = axis_nodes(*node, step.axis)
+                    .into_iter()
+                    .filter(|n| matches_test(*n, &step.test))
+                    .collect::<Vec<_>>();
+                for n in apply_predicates(candidates, &step.predicates) {
+                    if seen.insert(n.id()) {
+                        next.push(n);
+                    }
+                }
+            }
+            context = next;
+        }
+        for node in context {
+            let text = string_value(node).trim().to_string();
+            if !text.is_empty() {
+                out.push(text);
+            }
+        }
+        out
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Axis {
+        Child,
+        Descendant,
+        DescendantOrSelf,
+        Parent,
+        SelfNode,
+        Ancestor,
+        FollowingSibling,
+        PrecedingSibling,
+        Attribute,
+    }
+
+    enum NodeTest {
+        Name(String),
+        Any,
+        Text,
+        Node,
+    }
+
+    enum Operand {
+        Attr(String),
+        Text,
+        Dot,
+    }
+
+    enum Predicate {
+        Index(usize),
+        Last,
+        HasAttr(String),
+        HasChild(String),
+        Equals(Operand, String),
+        Contains(Operand, String),
+        StartsWith(Operand, String),
+    }
+
+    struct Step {
+        axis: Axis,
+        test: NodeTest,
+        predicates: Vec<Predicate>,
+    }
+
+    impl Step {
+        fn descendant_or_self() -> Self {
+            Step {
+                axis: Axis::DescendantOrSelf,
+                test: NodeTest::Node,
+                predicates: Vec::new(),
+            }
+        }
+    }
+
+    fn axis_nodes(node: NodeRef<'_, Node>, axis: Axis) -> Vec<NodeRef<'_, Node>> {
+        match axis {
+            Axis::Child => node.children().collect(),
+            Axis::Descendant => node.descendants().skip(1).collect(),
+            Axis::DescendantOrSelf => node.descendants().collect(),
+            Axis::Parent => node.parent().into_iter().collect(),
+            Axis::SelfNode => vec![node],
+            Axis::Ancestor => node.ancestors().collect(),
+            Axis::FollowingSibling => node.next_siblings().collect(),
+            Axis::PrecedingSibling => node.prev_siblings().collect(),
+            Axis::Attribute => Vec::new(),
+        }
+    }
+
+    fn matches_test(node: NodeRef<'_, Node>, test: &NodeTest) -> bool {
+        match test {
+            NodeTest::Node => true,
+            NodeTest::Text => node.value().is_text(),
+            NodeTest::Any => node.value().is_element(),
+            NodeTest::Name(name) => node
+                .value()
+                .as_element()
+                .is_some_and(|el| el.name().eq_ignore_ascii_case(name)),
+        }
+    }
+
+    fn apply_predicates<'a>(
+        mut nodes: Vec<NodeRef<'a, Node>>,
+        predicates: &[Predicate],
+    ) -> Vec<NodeRef<'a, Node>> {
+        for predicate in predicates {
+            let size = nodes.len();
+            nodes = nodes
+                .into_iter()
+                .enumerate()
+                .filter(|(i, n)| predicate_matches(predicate, *n, i + 1, size))
+                .map(|(_, n)| n)
+                .collect();
+        }
+        nodes
+    }
+
+    // Comparisons ignore surrounding whitespace, which HTML is full of.
+    fn predicate_matches(
+        predicate: &Predicate,
+        node: NodeRef<'_, Node>,
+        pos: usize,
+        size: usize,
+    ) -> bool {
+        match predicate {
+            Predicate::Index(n) => pos == *n,
+            Predicate::Last => pos == size,
+            Predicate::HasAttr(name) => operand_value(&Operand::Attr(name.clone()), node).is_some(),
+            Predicate::HasChild(name) => node
+                .children()
+                .any(|c| matches_test(c, &NodeTest::Name(name.clone()))),
+            Predicate::Equals(op, v) => operand_value(op, node).is_some_and(|s| s.trim() == v),
+            Predicate::Contains(op, v) => {
+                operand_value(op, node).is_some_and(|s| s.contains(v.as_str()))
+            }
+            Predicate::StartsWith(op, v) => {
+                operand_value(op, node).is_some_and(|s| s.trim_start().starts_with(v.as_str()))
+            }
+        }
+    }
+
+    fn operand_value(op: &Operand, node: NodeRef<'_, Node>) -> Option<String> {
+        match op {
+            Operand::Attr(name) => node
+                .value()
+                .as_element()
+                .and_then(|el| el.attr(name))
+                .map(str::to_string),
+            Operand::Text => Some(
+                node.children()
+                    .filter_map(|c| c.value().as_text().map(|t| String::from(&**t)))
+                    .collect(),
+            ),
+            Operand::Dot => Some(string_value(node)),
+        }
+    }
+
+    fn string_value(node: NodeRef<'_, Node>) -> String {
+        node.descendants()
+            .filter_map(|n| n.value().as_text().map(|t| String::from(&**t)))
+            .collect()
+    }
+
+    fn parse_xpath(expr: &str) -> Option<Vec<Step>> {
+        let mut rest = expr.trim();
+        if rest.is_empty() {
+            return None;
+        }
+        let mut steps = Vec::new();
+        if let Some(r) = rest.strip_prefix("//") {
+            steps.push(Step::descendant_or_self());
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('/') {
+            rest = r;
+        }
+        loop {
+            let (raw, tail) = match split_top_level(rest, '/') {
+                Some((raw, tail)) => (raw, Some(tail)),
+                None => (rest, None),
+            };
+            steps.push(parse_step(raw.trim())?);
+            match tail {
+                None => break,
+                Some(tail) => match tail.strip_prefix('/') {
+                    Some(t) => {
+                        steps.push(Step::descendant_or_self());
+                        rest = t;
+                    }
+                    None => rest = tail,
+                },
+            }
+        }
+        Some(steps)
+    }
+
+    fn parse_step(raw: &str) -> Option<Step> {
+        let (head, mut preds) = match split_top_level(raw, '[') {
+            Some((head, _)) => (head.trim(), &raw[head.len()..]),
+            None => (raw, ""),
+        };
+        let (axis, test) = match head {
+            "." => (Axis::SelfNode, NodeTest::Node),
+            ".." => (Axis::Parent, NodeTest::Node),
+            _ => {
+                if let Some(attr) = head.strip_prefix('@') {
+                    (Axis::Attribute, parse_node_test(attr)?)
+                } else if let Some((axis, test)) = head.split_once("::") {
+                    (parse_axis(axis.trim())?, parse_node_test(test.trim())?)
+                } else {
+                    (Axis::Child, parse_node_test(head)?)
+                }
+            }
+        };
+        let mut predicates = Vec::new();
+        while !preds.trim().is_empty() {
+            let body = preds.trim_start().strip_prefix('[')?;
+            let (inner, tail) = split_top_level(body, ']')?;
+            predicates.push(parse_predicate(inner.trim())?);
+            preds = tail;
+        }
+        Some(Step {
+            axis,
+            test,
+            predicates,
+        })
+    }
+
+    fn parse_axis(name: &str) -> Option<Axis> {
+        Some(match name {
+            "child" => Axis::Child,
+            "descendant" => Axis::Descendant,
+            "descendant-or-self" => Axis::DescendantOrSelf,
+            "parent" => Axis::Parent,
+            "self" => Axis::SelfNode,
+            "ancestor" => Axis::Ancestor,
+            "following-sibling" => Axis::FollowingSibling,
+            "preceding-sibling" => Axis::PrecedingSibling,
+            "attribute" => Axis::Attribute,
+            _ => return None,
+        })
+    }
+
+    fn parse_node_test(raw: &str) -> Option<NodeTest> {
+        match raw {
+            "*" => Some(NodeTest::Any),
+            "text()" => Some(NodeTest::Text),
+            "node()" => Some(NodeTest::Node),
+            name if is_name(name) => Some(NodeTest::Name(name.to_ascii_lowercase())),
+            _ => None,
+        }
+    }
+
+    fn parse_predicate(raw: &str) -> Option<Predicate> {
+        if let Ok(n) = raw.parse::<usize>() {
+            return Some(Predicate::Index(n));
+        }
+        if raw == "last()" {
+            return Some(Predicate::Last);
+        }
+        if let Some(args) = raw
+            .strip_prefix("contains(")
+            .and_then(|r| r.strip_suffix(')'))
+        {
+            let (lhs, rhs) = split_top_level(args, ',')?;
+            return Some(Predicate::Contains(
+                parse_operand(lhs)?,
+                parse_literal(rhs)?,
+            ));
+        }
+        if let Some(args) = raw
+            .strip_prefix("starts-with(")
+            .and_then(|r| r.strip_suffix(')'))
+        {
+            let (lhs, rhs) = split_top_level(args, ',')?;
+            return Some(Predicate::StartsWith(
+                parse_operand(lhs)?,
+                parse_literal(rhs)?,
+            ));
+        }
+        if let Some((lhs, rhs)) = split_top_level(raw, '=') {
+            return Some(Predicate::Equals(parse_operand(lhs)?, parse_literal(rhs)?));
+        }
+        if let Some(attr) = raw.strip_prefix('@') {
+            return is_name(attr).then(|| Predicate::HasAttr(attr.to_string()));
+        }
+        is_name(raw).then(|| Predicate::HasChild(raw.to_ascii_lowercase()))
+    }
+
+    fn parse_operand(raw: &str) -> Option<Operand> {
+        match raw.trim() {
+            "." => Some(Operand::Dot),
+            "text()" => Some(Operand::Text),
+            other => other
+                .strip_prefix('@')
+                .filter(|a| is_name(a))
+                .map(|a| Operand::Attr(a.to_string())),
+        }
+    }
+
+    fn parse_literal(raw: &str) -> Option<String> {
+        let raw = raw.trim();
+        let quote = raw.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+        raw[1..].strip_suffix(quote).map(|s| s.to_string())
+    }
+
+    fn is_name(raw: &str) -> bool {
+        !raw.is_empty()
+            && raw
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':')
+    }
+
+    // Splits at the first `sep` outside quotes and brackets.
+    fn split_top_level(raw: &str, sep: char) -> Option<(&str, &str)> {
+        let mut depth = 0usize;
+        let mut quote = None;
+        for (i, c) in raw.char_indices() {
+            match (quote, c) {
+                (Some(q), _) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                (None, _) if c == sep && depth == 0 => {
+                    return Some((&raw[..i], &raw[i + c.len_utf8()..]))
+                }
+                (None, '[' | '(') => depth += 1,
+                (None, ']' | ')') => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Tree view of a value with types and sizes, for `inspect(value)`. Long
+/// strings, long arrays and deep nesting are cut short; on a terminal, output
+/// taller than the screen goes through `$PAGER` (default `less -R`).
+pub mod inspect {
+    use serde_json::Value;
+    use std::io::{IsTerminal, Write};
+
+    const MAX_DEPTH: usize = 6;
+    const MAX_ITEMS: usize = 20;
+    const MAX_STRING: usize = 80;
+
+    pub fn print<T: serde::Serialize>(value: &T) {
+        let text = render(value);
+        let height = std::env::var("LINES")
+            .ok()
+            .and_then(|lines| lines.parse::<usize>().ok())
+            .unwrap_or(40);
+        if !std::io::stdout().is_terminal() || text.lines().count() < height || !page(&text) {
+            println!("{}", text);
+        }
+    }
+
+    pub fn render<T: serde::Serialize>(value: &T) -> String {
+        let value = match serde_json::to_value(value).unwrap_or_default() {
+            // db.query and friends return JSON text; show what it encodes.
+            Value::String(text) => match serde_json::from_str::<Value>(&text) {
+                Ok(parsed @ (Value::Array(_) | Value::Object(_))) => parsed,
+                _ => Value::String(text),
+            },
+            value => value,
+        };
+        let mut lines = Vec::new();
+        node(&mut lines, "", &value, 0);
+        lines.join("\n")
+    }
+
+    fn node(lines: &mut Vec<String>, label: &str, value: &Value, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let summary = match value {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => format!("bool {}", b),
+            Value::Number(n) => format!("number {}", n),
+            Value::String(text) => format!("string({}) {}", text.chars().count(), quoted(text)),
+            Value::Array(items) => format!("array[{}]", items.len()),
+            Value::Object(map) => format!("object{{{}}}", map.len()),
+        };
+        lines.push(format!("{}{}{}", indent, label, summary));
+
+        let children: Vec<(String, &Value)> = match value {
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| (format!("[{}]: ", i), item))
+                .collect(),
+            Value::Object(map) => map.iter().map(|(k, v)| (format!("{}: ", k), v)).collect(),
+            _ => return,
+        };
+        if children.is_empty() {
+            return;
+        }
+        if depth + 1 >= MAX_DEPTH {
+            lines.push(format!("{}  …", indent));
+            return;
+        }
+        let total = children.len();
+        for (label, child) in children.into_iter().take(MAX_ITEMS) {
+            node(lines, &label, child, depth + 1);
+        }
+        if total > MAX_ITEMS {
+            lines.push(format!("{}  … {} more", indent, total - MAX_ITEMS));
+        }
+    }
+
+    fn quoted(text: &str) -> String {
+        let mut shown = text.chars().take(MAX_STRING).collect::<String>();
+        if text.chars().count() > MAX_STRING {
+            shown.push('…');
+        }
+        Value::String(shown).to_string()
+    }
+
+    // False if no pager could be started, so the caller prints instead.
+    fn page(text: &str) -> bool {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+        let mut parts = pager.split_whitespace();
+        let program = match parts.next() {
+            Some(program) => program,
+            None => return false,
+        };
+        let child = std::process::Command::new(program)
+            .args(parts)
+            .stdin(std::process::Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(_) => return false,
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = writeln!(stdin, "{}", text);
+        }
+        let _ = child.wait();
+        true
+    }
+}
+
+pub mod iter {
+    use serde_json::Value;
+    use std::ops::{Range, RangeInclusive};
+
+    pub trait Items {
+        type Item;
+        type IntoIter: Iterator<Item = Self::Item>;
+        fn items(self) -> Self::IntoIter;
+    }
+
+    pub trait Entries {
+        type Key;
+        type Value;
+        fn entries(self) -> std::vec::IntoIter<(Self::Key, Self::Value)>;
+    }
+
+    pub fn items<T: Items>(value: T) -> T::IntoIter {
+        value.items()
+    }
+
+    pub fn entries<T: Entries>(value: T) -> std::vec::IntoIter<(T::Key, T::Value)> {
+        value.entries()
+    }
+
+    impl<T> Items for Vec<T> {
+        type Item = T;
+        type IntoIter = std::vec::IntoIter<T>;
+        fn items(self) -> Self::IntoIter {
+            self.into_iter()
+        }
+    }
+
+    impl<'a, T: Clone> Items for &'a Vec<T> {
+        type Item = T;
+        type IntoIter = std::iter::Cloned<std::slice::Iter<'a, T>>;
+        fn items(self) -> Self::IntoIter {
+            self.iter().cloned()
+        }
+    }
+
+    impl<A> Items for Range<A>
+    where
+        Range<A>: Iterator,
+    {
+        type Item = <Range<A> as Iterator>::Item;
+        type IntoIter = Range<A>;
+        fn items(self) -> Self::IntoIter {
+            self
+        }
+    }
+
+    impl<A: Clone> Items for &Range<A>
+    where
+        Range<A>: Iterator,
+    {
+        type Item = <Range<A> as Iterator>::Item;
+        type IntoIter = Range<A>;
+        fn items(self) -> Self::IntoIter {
+            self.clone()
+        }
+    }
+
+    impl<A> Items for RangeInclusive<A>
+    where
+        RangeInclusive<A>: Iterator,
+    {
+        type Item = <RangeInclusive<A> as Iterator>::Item;
+        type IntoIter = RangeInclusive<A>;
+        fn items(self) -> Self::IntoIter {
+            self
+        }
+    }
+
+    impl<A: Clone> Items for &RangeInclusive<A>
+    where
+        RangeInclusive<A>: Iterator,
+    {
+        type Item = <RangeInclusive<A> as Iterator>::Item;
+        type IntoIter = RangeInclusive<A>;
+        fn items(self) -> Self::IntoIter {
+            self.clone()
+        }
+    }
+
+    impl Items for Value {
+        type Item = Value;
+        type IntoIter = std::vec::IntoIter<Value>;
+        fn items(self) -> Self::IntoIter {
+            match self {
+                Value::Array(items) => items.into_iter(),
+                Value::Object(map) => map
+                    .into_iter()
+                    .map(|(k, _)| Value::String(k))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+                _ => Vec::new().into_iter(),
+            }
+        }
+    }
+
+    impl Items for &Value {
+        type Item = Value;
+        type IntoIter = std::vec::IntoIter<Value>;
+        fn items(self) -> Self::IntoIter {
+            self.clone().items()
+        }
+    }
+
+    // db.query and friends hand back JSON text; iterate the rows it encodes.
+    impl Items for String {
+        type Item = Value;
+        type IntoIter = std::vec::IntoIter<Value>;
+        fn items(self) -> Self::IntoIter {
+            self.as_str().items()
+        }
+    }
+
+    impl Items for &String {
+        type Item = Value;
+        type IntoIter = std::vec::IntoIter<Value>;
+        fn items(self) -> Self::IntoIter {
+            self.as_str().items()
+        }
+    }
+
+    impl Items for &str {
+        type Item = Value;
+        type IntoIter = std::vec::IntoIter<Value>;
+        fn items(self) -> Self::IntoIter {
+            match serde_json::from_str::<Value>(self) {
+                Ok(Value::Array(rows)) => rows.into_iter(),
+                Ok(Value::Object(map)) if map.contains_key("error") => {
+                    eprintln!("Cannot iterate over error: {}", map["error"]);
+                    Vec::new().into_iter()
+                }
+                _ => Vec::new().into_iter(),
+            }
+        }
+    }
+
+    impl<T> Entries for Vec<T> {
+        type Key = usize;
+        type Value = T;
+        fn entries(self) -> std::vec::IntoIter<(usize, T)> {
+            self.into_iter().enumerate().collect::<Vec<_>>().into_iter()
+        }
+    }
+
+    impl<T: Clone> Entries for &Vec<T> {
+        type Key = usize;
+        type Value = T;
+        fn entries(self) -> std::vec::IntoIter<(usize, T)> {
+            self.clone().entries()
+        }
+    }
+
+    impl Entries for Value {
+        type Key = String;
+        type Value = Value;
+        fn entries(self) -> std::vec::IntoIter<(String, Value)> {
+            match self {
+                Value::Object(map) => map.into_iter().collect::<Vec<_>>().into_iter(),
+                Value::Array(items) => items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, v)| (i.to_string(), v))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+                _ => Vec::new().into_iter(),
+            }
+        }
+    }
+
+    impl Entries for &Value {
+        type Key = String;
+        type Value = Value;
+        fn entries(self) -> std::vec::IntoIter<(String, Value)> {
+            self.clone().entries()
+        }
+    }
+}
+
+pub mod json {
+    use serde_json::Map;
+    pub use serde_json::Value;
+    // For generated structs, which may be linked against zinc_std alone.
+    pub use serde::{self, Serialize};
+
+    pub fn parse(s: &str) -> Value {
+        try_parse(s).unwrap_or(Value::Null)
+    }
+
+    pub fn try_parse(s: &str) -> serde_json::Result<Value> {
+        serde_json::from_str(s)
+    }
+
+    pub fn get(val: &Value, key: &str) -> Value {
+        val.get(key).cloned().unwrap_or(Value::Null)
+    }
+
+    pub fn field(val: &Value, key: &str) -> Option<Value> {
+        match val.get(key) {
+            None | Some(Value::Null) => None,
+            Some(v) => Some(v.clone()),
+        }
+    }
+
+    pub fn at(val: &Value, idx: usize) -> Value {
+        match val {
+            Value::Array(items) => items.get(idx).cloned().unwrap_or(Value::Null),
+            _ => Value::Null,
+        }
+    }
+
+    pub fn to_string(val: &Value) -> String {
+        serde_json::to_string(val).unwrap_or_else(|_| "null".to_string())
+    }
+
+    /// A string value as text for std functions that take `&str`; any other
+    /// value is empty.
+    pub fn as_str(val: &Value) -> &str {
+        val.as_str().unwrap_or_default()
+    }
+
+    pub fn from<T: Into<Value>>(val: T) -> Value {
+        val.into()
+    }
+
+    pub fn to_value<T: Serialize>(val: &T) -> Value {
+        serde_json::to_value(val).unwrap_or(Value::Null)
+    }
+
+    /// One step of a `path`: `.name` / `["name"]`, or `[0]`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Key {
+        Name(String),
+        Index(usize),
+    }
+
+    impl From<&str> for Key {
+        fn from(name: &str) -> Self {
+            Key::Name(name.to_string())
+        }
+    }
+
+    impl From<String> for Key {
+        fn from(name: String) -> Self {
+            Key::Name(name)
+        }
+    }
+
+    impl From<&String> for Key {
+        fn from(name: &String) -> Self {
+            Key::Name(name.clone())
+        }
+    }
+
+    macro_rules! index_key {
+        ($($t:ty),*) => {$(
+            impl From<$t> for Key {
+                // Negative indexes match nothing.
+                fn from(index: $t) -> Self {
+                    Key::Index(usize::try_from(index).unwrap_or(usize::MAX))
+                }
+            }
+        )*};
+    }
+    index_key!(i32, i64, u32, u64, usize);
+
+    // Results of `/` and `%`, which are JSON numbers.
+    impl From<Value> for Key {
+        fn from(key: Value) -> Self {
+            match key {
+                Value::String(name) => Key::Name(name),
+                other => Key::Index(
+                    other
+                        .as_u64()
+                        .and_then(|i| usize::try_from(i).ok())
+                        .unwrap_or(usize::MAX),
+                ),
+            }
+        }
+    }
+
+    /// What `.field` and `[...]` reach into: JSON values, JSON text and the
+    /// structs zinc programs declare.
+    pub trait Fields {
+        fn field(&self, name: &str) -> Value;
+        fn field_mut(&mut self, name: &str) -> Option<&mut Value>;
+
+        fn item(&self, _index: usize) -> Value {
+            Value::Null
+        }
+
+        fn item_mut(&mut self, _index: usize) -> Option<&mut Value> {
+            None
+        }
+    }
+
+    impl Fields for Value {
+        fn field(&self, name: &str) -> Value {
+            get(self, name)
+        }
+
+        // Assigning into null starts a new object.
+        fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+            if self.is_null() {
+                *self = Value::Object(Map::new());
+            }
+            match self {
+                Value::Object(map) => Some(map.entry(name).or_insert(Value::Null)),
+                _ => None,
+            }
+        }
+
+        fn item(&self, index: usize) -> Value {
+            at(self, index)
+        }
+
+        // Assigning past the end pads the array with nulls.
+        fn item_mut(&mut self, index: usize) -> Option<&mut Value> {
+            if index == usize::MAX {
+                return None;
+            }
+            if self.is_null() {
+                *self = Value::Array(Vec::new());
+            }
+            match self {
+                Value::Array(items) => {
+                    if index >= items.len() {
+                        items.resize(index + 1, Value::Null);
+                    }
+                    items.get_mut(index)
+                }
+                _ => None,
+            }
+        }
+    }
+
+    // db.query and friends return JSON text; it can be read but not assigned.
+    impl Fields for String {
+        fn field(&self, name: &str) -> Value {
+            parse(self).field(name)
+        }
+
+        fn field_mut(&mut self, _name: &str) -> Option<&mut Value> {
+            None
+        }
+
+        fn item(&self, index: usize) -> Value {
+            parse(self).item(index)
+        }
+    }
+
+    fn step<T: Fields + ?Sized>(val: &T, key: &Key) -> Value {
+        match key {
+            Key::Name(name) => val.field(name),
+            Key::Index(index) => val.item(*index),
+        }
+    }
+
+    fn step_mut<'a, T: Fields + ?Sized>(val: &'a mut T, key: &Key) -> Option<&'a mut Value> {
+        match key {
+            Key::Name(name) => val.field_mut(name),
+            Key::Index(index) => val.item_mut(*index),
+        }
+    }
+
+    /// `obj.a.b[0]`: null as soon as a step is missing.
+    pub fn path<T: Fields + ?Sized>(val: &T, keys: Vec<Key>) -> Value {
+        let mut keys = keys.iter();
+        let first = match keys.next() {
+            Some(key) => step(val, key),
+            None => return Value::Null,
+        };
+        keys.fold(first, |current, key| step(&current, key))
+    }
+
+    /// `obj.a.b[0] = value`, creating objects and arrays on the way. Does
+    /// nothing if a step lands inside a string, number or bool.
+    pub fn set<T: Fields + ?Sized>(target: &mut T, keys: Vec<Key>, value: Value) {
+        let mut keys = keys.iter();
+        let mut slot = match keys.next().and_then(|key| step_mut(target, key)) {
+            Some(slot) => slot,
+            None => return,
+        };
+        for key in keys {
+            slot = match step_mut(slot, key) {
+                Some(next) => next,
+                None => return,
+            };
+        }
+        *slot = value;
+    }
+
+    pub fn object(fields: Vec<(&str, Value)>) -> Value {
+        let mut map = Map::new();
+        for (key, val) in fields {
+            map.insert(key.to_string(), val);
+        }
+        Value::Object(map)
+    }
+
+    pub fn insert<T: Into<Value>>(target: &mut Value, key: &str, val: T) {
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        if let Value::Object(map) = target {
+            map.insert(key.to_string(), val.into());
+        }
+    }
+
+    pub fn merge(target: &mut Value, source: &Value) {
+        if let (Value::Object(dst), Value::Object(src)) = (target, source) {
+            for (key, val) in src {
+                dst.insert(key.clone(), val.clone());
+            }
+        }
+    }
+}
+
+/// Guardrails for long-running scripts. Limits come from `ZINC_MAX_MEMORY_MB`,
+/// `ZINC_MAX_REQUESTS` and `ZINC_MAX_RUNTIME_SECS`, or from `limits.set` in
+/// the script. Crossing one prints a `{"error": {"kind": "limit", ...}}`
+/// document to stderr and exits with status 3.
+pub mod limits {
+    use serde_json::{json, Value};
+    use std::io::Write;
+    #[cfg(feature = "http")]
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Mutex, Once, OnceLock};
+    use std::time::{Duration, Instant};
+
+    pub const EXIT_CODE: i32 = 3;
+
+    #[derive(Clone, Copy, Default)]
+    struct Limits {
+        max_memory_mb: Option<u64>,
+        max_requests: Option<u64>,
+        max_runtime_secs: Option<u64>,
+    }
+
+    static LIMITS: Mutex<Limits> = Mutex::new(Limits {
+        max_memory_mb: None,
+        max_requests: None,
+        max_runtime_secs: None,
+    });
+    #[cfg(feature = "http")]
+    static REQUESTS: AtomicU64 = AtomicU64::new(0);
+    static STARTED: OnceLock<Instant> = OnceLock::new();
+    static WATCHDOG: Once = Once::new();
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Called at the top of every generated `main`.
+    pub fn start() {
+        STARTED.get_or_init(Instant::now);
+        {
+            let mut limits = LIMITS.lock().unwrap();
+            limits.max_memory_mb = env_limit("ZINC_MAX_MEMORY_MB").or(limits.max_memory_mb);
+            limits.max_requests = env_limit("ZINC_MAX_REQUESTS").or(limits.max_requests);
+            limits.max_runtime_secs =
+                env_limit("ZINC_MAX_RUNTIME_SECS").or(limits.max_runtime_secs);
+        }
+        watch();
+    }
+
+    /// Accepts `max_memory_mb`, `max_requests` and `max_runtime_secs`; a
+    /// `null` value removes that limit. Returns false for unknown keys or
+    /// values that aren't non-negative integers.
+    pub fn set(options: &Value) -> bool {
+        let Some(options) = options.as_object() else {
+            eprintln!("limits.set expects a map");
+            return false;
+        };
+        let mut next = *LIMITS.lock().unwrap();
+        for (key, value) in options {
+            let slot = match key.as_str() {
+                "max_memory_mb" => &mut next.max_memory_mb,
+                "max_requests" => &mut next.max_requests,
+                "max_runtime_secs" => &mut next.max_runtime_secs,
+                _ => {
+                    eprintln!("limits.set: unknown limit {}", key);
+                    return false;
+                }
+            };
+            *slot = match value {
+                Value::Null => None,
+                _ => match value.as_u64() {
+                    Some(n) => Some(n),
+                    None => {
+                        eprintln!("limits.set: {} must be a non-negative integer", key);
+                        return false;
+                    }
+                },
+            };
+        }
+        *LIMITS.lock().unwrap() = next;
+        STARTED.get_or_init(Instant::now);
+        watch();
+        true
+    }
+
+    /// Counts one outgoing HTTP request, aborting if it is over the budget.
+    #[cfg(feature = "http")]
+    pub(crate) fn count_request(url: &str) {
+        let used = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+        let max = LIMITS.lock().unwrap().max_requests;
+        if let Some(max) = max.filter(|max| used > *max) {
+            abort(json!({
+                "limit": "max_requests",
+                "max": max,
+                "used": used,
+                "url": url,
+            }));
+        }
+    }
+
+    /// Time since `start`, or zero if it was never called.
+    pub fn elapsed() -> Duration {
+        STARTED.get().map(|s| s.elapsed()).unwrap_or_default()
+    }
+
+    fn watch() {
+        WATCHDOG.call_once(|| {
+            std::thread::spawn(|| loop {
+                check();
+                std::thread::sleep(POLL_INTERVAL);
+            });
+        });
+    }
+
+    fn check() {
+        let limits = *LIMITS.lock().unwrap();
+        if let (Some(max), Some(started)) = (limits.max_runtime_secs, STARTED.get()) {
+            let used = started.elapsed().as_secs();
+            if used >= max {
+                abort(json!({ "limit": "max_runtime_secs", "max": max, "used": used }));
+            }
+        }
+        if let (Some(max), Some(used)) = (limits.max_memory_mb, resident_mb()) {
+            if used > max {
+                abort(json!({ "limit": "max_memory_mb", "max": max, "used": used }));
+            }
+        }
+    }
+
+    fn env_limit(name: &str) -> Option<u64> {
+        let raw = std::env::var(name).ok()?;
+        match raw.trim().parse() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                eprintln!("Ignoring {}={}: expected a whole number", name, raw);
+                None
+            }
+        }
+    }
+
+    // Resident set size. Only Linux exposes this without extra dependencies,
+    // so the memory limit is not enforced elsewhere.
+    fn resident_mb() -> Option<u64> {
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        Some(pages * 4096 / (1024 * 1024))
+    }
+
+    fn abort(mut detail: Value) -> ! {
+        detail["kind"] = json!("limit");
+        let _ = std::io::stdout().flush();
+        eprintln!("{}", json!({ "error": detail }));
+        crate::run_exit_hooks();
+        crate::summary::emit();
+        std::process::exit(EXIT_CODE);
+    }
+}
+
+/// Array methods (`xs.len()`, `xs.push(v)`, `xs.sort()`, ...) that work the
+/// same on literal vecs and on JSON arrays from `json.parse` or `db.query`.
+pub mod list {
+    use serde_json::Value;
+    use std::cmp::Ordering;
+
+    pub trait Len {
+        fn length(&self) -> usize;
+    }
+
+    impl<T> Len for Vec<T> {
+        fn length(&self) -> usize {
+            self.len()
+        }
+    }
+
+    // Counted in characters, not bytes.
+    impl Len for str {
+        fn length(&self) -> usize {
+            self.chars().count()
+        }
+    }
+
+    impl Len for String {
+        fn length(&self) -> usize {
+            self.as_str().length()
+        }
+    }
+
+    impl Len for &str {
+        fn length(&self) -> usize {
+            (*self).length()
+        }
+    }
+
+    impl Len for Value {
+        fn length(&self) -> usize {
+            match self {
+                Value::Array(items) => items.len(),
+                Value::Object(map) => map.len(),
+                Value::String(text) => text.length(),
+                _ => 0,
+            }
+        }
+    }
+
+    pub trait Push<V> {
+        fn push_item(&mut self, item: V);
+    }
+
+    impl<T> Push<T> for Vec<T> {
+        fn push_item(&mut self, item: T) {
+            self.push(item);
+        }
+    }
+
+    impl<V: Into<Value>> Push<V> for Value {
+        fn push_item(&mut self, item: V) {
+            match self {
+                Value::Array(items) => items.push(item.into()),
+                Value::Null => *self = Value::Array(vec![item.into()]),
+                _ => {}
+            }
+        }
+    }
+
+    pub trait Contains<V> {
+        fn contains_item(&self, item: &V) -> bool;
+    }
+
+    impl<T: PartialEq<V>, V> Contains<V> for Vec<T> {
+        fn contains_item(&self, item: &V) -> bool {
+            self.iter().any(|x| x == item)
+        }
+    }
+
+    impl<V: Clone + Into<Value>> Contains<V> for Value {
+        fn contains_item(&self, item: &V) -> bool {
+            let item = item.clone().into();
+            match (self, &item) {
+                (Value::Array(items), _) => items.contains(&item),
+                (Value::Object(map), Value::String(key)) => map.contains_key(key),
+                (Value::String(text), Value::String(part)) => text.contains(part.as_str()),
+                _ => false,
+            }
+        }
+    }
+
+    impl<V: AsRef<str>> Contains<V> for String {
+        fn contains_item(&self, item: &V) -> bool {
+            self.contains(item.as_ref())
+        }
+    }
+
+    impl<V: AsRef<str>> Contains<V> for &str {
+        fn contains_item(&self, item: &V) -> bool {
+            self.contains(item.as_ref())
+        }
+    }
+
+    pub trait List {
+        fn values(&self) -> Vec<Value>;
+        fn sort_items(&mut self);
+        fn reverse_items(&mut self);
+    }
+
+    impl<T: Clone + Into<Value>> List for Vec<T> {
+        fn values(&self) -> Vec<Value> {
+            self.iter().cloned().map(Into::into).collect()
+        }
+
+        fn sort_items(&mut self) {
+            self.sort_by(|a, b| compare(&a.clone().into(), &b.clone().into()));
+        }
+
+        fn reverse_items(&mut self) {
+            self.reverse();
+        }
+    }
+
+    impl List for Value {
+        fn values(&self) -> Vec<Value> {
+            match self {
+                Value::Array(items) => items.clone(),
+                _ => Vec::new(),
+            }
+        }
+
+        fn sort_items(&mut self) {
+            if let Value::Array(items) = self {
+                items.sort_by(compare);
+            }
+        }
+
+        fn reverse_items(&mut self) {
+            if let Value::Array(items) = self {
+                items.reverse();
+            }
+        }
+    }
+
+    pub trait Slice {
+        type Output;
+        fn slice_len(&self) -> usize;
+        fn slice_range(&self, start: usize, end: usize) -> Self::Output;
+    }
+
+    impl<T: Clone> Slice for Vec<T> {
+        type Output = Vec<T>;
+        fn slice_len(&self) -> usize {
+            self.len()
+        }
+        fn slice_range(&self, start: usize, end: usize) -> Vec<T> {
+            self[start..end].to_vec()
+        }
+    }
+
+    // Strings slice by character.
+    impl Slice for str {
+        type Output = String;
+        fn slice_len(&self) -> usize {
+            self.chars().count()
+        }
+        fn slice_range(&self, start: usize, end: usize) -> String {
+            self.chars().skip(start).take(end - start).collect()
+        }
+    }
+
+    impl Slice for String {
+        type Output = String;
+        fn slice_len(&self) -> usize {
+            self.as_str().slice_len()
+        }
+        fn slice_range(&self, start: usize, end: usize) -> String {
+            self.as_str().slice_range(start, end)
+        }
+    }
+
+    impl Slice for &str {
+        type Output = String;
+        fn slice_len(&self) -> usize {
+            (*self).slice_len()
+        }
+        fn slice_range(&self, start: usize, end: usize) -> String {
+            (*self).slice_range(start, end)
+        }
+    }
+
+    impl Slice for Value {
+        type Output = Value;
+        fn slice_len(&self) -> usize {
+            match self {
+                Value::Array(items) => items.len(),
+                Value::String(text) => text.slice_len(),
+                _ => 0,
+            }
+        }
+        fn slice_range(&self, start: usize, end: usize) -> Value {
+            match self {
+                Value::Array(items) => Value::Array(items[start..end].to_vec()),
+                Value::String(text) => Value::String(text.slice_range(start, end)),
+                _ => Value::Null,
+            }
+        }
+    }
+
+    pub fn len<T: Len + ?Sized>(xs: &T) -> usize {
+        xs.length()
+    }
+
+    pub fn push<T: Push<V>, V>(xs: &mut T, item: V) {
+        xs.push_item(item);
+    }
+
+    pub fn contains<T: Contains<V>, V>(xs: &T, item: &V) -> bool {
+        xs.contains_item(item)
+    }
+
+    /// `xs[start:end]`. Negative bounds count from the end, and bounds out of
+    /// range are clamped, so the worst case is an empty slice.
+    pub fn slice<T: Slice + ?Sized>(xs: &T, start: Option<i64>, end: Option<i64>) -> T::Output {
+        let len = xs.slice_len() as i64;
+        let clamp = |i: i64| (if i < 0 { len + i } else { i }).clamp(0, len) as usize;
+        let start = start.map_or(0, clamp);
+        let end = end.map_or(len as usize, clamp).max(start);
+        xs.slice_range(start, end)
+    }
+
+    // Strings join as-is; other values as their JSON text.
+    pub fn join<T: List>(xs: &T, sep: &str) -> String {
+        xs.values()
+            .iter()
+            .map(|v| match v {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(sep)
+    }
+
+    pub fn sort<T: List>(xs: &mut T) {
+        xs.sort_items();
+    }
+
+    pub fn reverse<T: List>(xs: &mut T) {
+        xs.reverse_items();
+    }
+
+    // Numbers by value, strings alphabetically; mixed types group by kind.
+    fn compare(a: &Value, b: &Value) -> Ordering {
+        fn rank(v: &Value) -> u8 {
+            match v {
+                Value::Null => 0,
+                Value::Bool(_) => 1,
+                Value::Number(_) => 2,
+                Value::String(_) => 3,
+                Value::Array(_) => 4,
+                Value::Object(_) => 5,
+            }
+        }
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => x
+                .as_f64()
+                .partial_cmp(&y.as_f64())
+                .unwrap_or(Ordering::Equal),
+            (Value::String(x), Value::String(y)) => x.cmp(y),
+            (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+            _ => rank(a).cmp(&rank(b)),
+        }
+    }
+}
+
+/// Log and metric lines on stderr, either human-readable text (the
+/// default) or one JSON object per line for log pipelines.
+pub mod log {
+    use chrono::SecondsFormat;
+    use serde_json::{json, Map, Value};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static JSON: AtomicBool = AtomicBool::new(false);
+
+    /// "text" or "json".
+    pub fn set_format(format: &str) -> bool {
+        match format {
+            "text" => JSON.store(false, Ordering::Relaxed),
+            "json" => JSON.store(true, Ordering::Relaxed),
+            _ => {
+                eprintln!(
+                    "Unknown log format {}; expected \"text\" or \"json\"",
+                    format
+                );
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn info(msg: &str, fields: &Value) {
+        emit("info", msg, fields);
+    }
+
+    pub fn warn(msg: &str, fields: &Value) {
+        emit("warn", msg, fields);
+    }
+
+    pub fn error(msg: &str, fields: &Value) {
+        emit("error", msg, fields);
+    }
+
+    pub fn metric<V: Into<Value>>(name: &str, value: V) {
+        emit("metric", name, &json!({ "value": value.into() }));
+    }
+
+    fn emit(level: &str, msg: &str, fields: &Value) {
+        let ts = chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let fields = match fields {
+            Value::Object(map) => map.clone(),
+            Value::Null => Map::new(),
+            other => Map::from_iter([("value".to_string(), other.clone())]),
+        };
+        if JSON.load(Ordering::Relaxed) {
+            let mut line = Map::new();
+            line.insert("ts".to_string(), json!(ts));
+            line.insert("level".to_string(), json!(level));
+            line.insert("msg".to_string(), json!(msg));
+            line.extend(fields);
+            eprintln!("{}", Value::Object(line));
+        } else {
+            let mut line = format!("{} {:<6} {}", ts, level.to_uppercase(), msg);
+            for (key, value) in &fields {
+                match value {
+                    Value::String(s) => line.push_str(&format!(" {}={}", key, s)),
+                    other => line.push_str(&format!(" {}={}", key, other)),
+                }
+            }
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Canned responses for tests. While a mock is registered, the matching
+/// spider fetch, `fs.read` or `db.query` returns it without touching the
+/// network, disk or database.
+pub mod mock {
+    use serde::Serialize;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Mocks {
+        spider: HashMap<String, String>,
+        fs: HashMap<String, String>,
+        db: HashMap<String, Value>,
+    }
+
+    static MOCKS: Mutex<Option<Mocks>> = Mutex::new(None);
+
+    fn with_mocks<T>(f: impl FnOnce(&mut Mocks) -> T) -> T {
+        f(MOCKS.lock().unwrap().get_or_insert_with(Mocks::default))
+    }
+
+    pub fn spider(url: &str, body: &str) {
+        with_mocks(|m| m.spider.insert(url.to_string(), body.to_string()));
+    }
+
+    pub fn fs(path: &str, content: &str) {
+        with_mocks(|m| m.fs.insert(path.to_string(), content.to_string()));
+    }
+
+    // Matched on the SQL text with surrounding whitespace ignored.
+    pub fn db<R: Serialize + ?Sized>(sql: &str, rows: &R) {
+        let rows = serde_json::to_value(rows).unwrap_or(Value::Null);
+        with_mocks(|m| m.db.insert(sql.trim().to_string(), rows));
+    }
+
+    pub fn clear() {
+        *MOCKS.lock().unwrap() = None;
+    }
+
+    #[cfg(feature = "http")]
+    pub(crate) fn spider_body(url: &str) -> Option<String> {
+        MOCKS.lock().unwrap().as_ref()?.spider.get(url).cloned()
+    }
+
+    pub(crate) fn fs_content(path: &str) -> Option<String> {
+        MOCKS.lock().unwrap().as_ref()?.fs.get(path).cloned()
+    }
+
+    #[cfg(feature = "db")]
+    pub(crate) fn db_rows(sql: &str) -> Option<Value> {
+        MOCKS.lock().unwrap().as_ref()?.db.get(sql.trim()).cloned()
+    }
+}
+
+#[cfg(feature = "http")]
+pub mod notify {
+    use serde_json::json;
+
+    // Discord rejects messages longer than this.
+    const DISCORD_MAX_CHARS: usize = 2000;
+
+    pub fn slack(webhook_url: &str, text: &str) -> bool {
+        send(webhook_url, json!({ "text": text }))
+    }
+
+    pub fn discord(webhook_url: &str, text: &str) -> bool {
+        let content: String = text.chars().take(DISCORD_MAX_CHARS).collect();
+        send(webhook_url, json!({ "content": content }))
+    }
+
+    fn send(webhook_url: &str, payload: serde_json::Value) -> bool {
+        if crate::skip_for_dry_run(format_args!("post to {}: {}", webhook_url, payload)) {
+            return true;
+        }
+        match crate::spider::post_json(webhook_url, &payload) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("Notification failed: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Arithmetic behind zinc's operators. `+` adds numbers and concatenates
+/// anything else; `-`, `*`, `/` and `%` are numeric only. `json::Value`
+/// operands are inspected at runtime.
+pub mod ops {
+    use serde_json::Value;
+    use std::cmp::Ordering;
+
+    pub trait Add<Rhs> {
+        type Output;
+        fn add(self, rhs: Rhs) -> Self::Output;
+    }
+
+    pub trait Sub<Rhs> {
+        type Output;
+        fn sub(self, rhs: Rhs) -> Self::Output;
+    }
+
+    pub trait Mul<Rhs> {
+        type Output;
+        fn mul(self, rhs: Rhs) -> Self::Output;
+    }
+
+    pub trait Div<Rhs> {
+        type Output;
+        fn div(self, rhs: Rhs) -> Self::Output;
+    }
+
+    pub trait Rem<Rhs> {
+        type Output;
+        fn rem(self, rhs: Rhs) -> Self::Output;
+    }
+
+    pub fn add<A: Add<B>, B>(a: A, b: B) -> A::Output {
+        a.add(b)
+    }
+
+    pub fn sub<A: Sub<B>, B>(a: A, b: B) -> A::Output {
+        a.sub(b)
+    }
+
+    pub fn mul<A: Mul<B>, B>(a: A, b: B) -> A::Output {
+        a.mul(b)
+    }
+
+    pub fn div<A: Div<B>, B>(a: A, b: B) -> A::Output {
+        a.div(b)
+    }
+
+    pub fn rem<A: Rem<B>, B>(a: A, b: B) -> A::Output {
+        a.rem(b)
+    }
+
+    /// Ordering for `<`, `>`, `<=` and `>=`, which also has to cover JSON
+    /// values since `json::Value` has none of its own.
+    pub trait Compare<Rhs: ?Sized> {
+        fn compare(&self, rhs: &Rhs) -> Option<Ordering>;
+    }
+
+    pub fn lt<A: Compare<B> + ?Sized, B: ?Sized>(a: &A, b: &B) -> bool {
+        a.compare(b) == Some(Ordering::Less)
+    }
+
+    pub fn gt<A: Compare<B> + ?Sized, B: ?Sized>(a: &A, b: &B) -> bool {
+        a.compare(b) == Some(Ordering::Greater)
+    }
+
+    pub fn le<A: Compare<B> + ?Sized, B: ?Sized>(a: &A, b: &B) -> bool {
+        matches!(a.compare(b), Some(Ordering::Less | Ordering::Equal))
+    }
+
+    pub fn ge<A: Compare<B> + ?Sized, B: ?Sized>(a: &A, b: &B) -> bool {
+        matches!(a.compare(b), Some(Ordering::Greater | Ordering::Equal))
+    }
+
+    /// `a ?? b`: `b` when `a` is null, otherwise `a`.
+    pub fn coalesce<A: Into<Value>, B: Into<Value>>(a: A, b: B) -> Value {
+        match a.into() {
+            Value::Null => b.into(),
+            value => value,
+        }
+    }
+
+    macro_rules! numeric {
+        ($($t:ty),*) => {$(
+            impl Add<$t> for $t {
+                type Output = $t;
+                fn add(self, rhs: $t) -> $t {
+                    self + rhs
+                }
+            }
+
+            impl Sub<$t> for $t {
+                type Output = $t;
+                fn sub(self, rhs: $t) -> $t {
+                    self - rhs
+                }
+            }
+
+            impl Mul<$t> for $t {
+                type Output = $t;
+                fn mul(self, rhs: $t) -> $t {
+                    self * rhs
+                }
+            }
+
+            impl Add<Value> for $t {
+                type Output = Value;
+                fn add(self, rhs: Value) -> Value {
+                    add_values(Value::from(self), rhs)
+                }
+            }
+
+            impl Sub<Value> for $t {
+                type Output = Value;
+                fn sub(self, rhs: Value) -> Value {
+                    arith(Value::from(self), rhs, Op::Sub)
+                }
+            }
+
+            impl Mul<Value> for $t {
+                type Output = Value;
+                fn mul(self, rhs: Value) -> Value {
+                    arith(Value::from(self), rhs, Op::Mul)
+                }
+            }
+
+            impl Div<Value> for $t {
+                type Output = Value;
+                fn div(self, rhs: Value) -> Value {
+                    arith(Value::from(self), rhs, Op::Div)
+                }
+            }
+
+            impl Rem<Value> for $t {
+                type Output = Value;
+                fn rem(self, rhs: Value) -> Value {
+                    arith(Value::from(self), rhs, Op::Rem)
+                }
+            }
+        )*};
+    }
+
+    numeric!(i32, i64, u32, u64, usize, f32, f64);
+
+    impl<T: Compare<U> + ?Sized, U: ?Sized> Compare<U> for &T {
+        fn compare(&self, rhs: &U) -> Option<Ordering> {
+            (**self).compare(rhs)
+        }
+    }
+
+    impl<R: Into<Value> + Clone> Compare<R> for Value {
+        fn compare(&self, rhs: &R) -> Option<Ordering> {
+            compare_values(self, &rhs.clone().into())
+        }
+    }
+
+    macro_rules! ordered {
+        ($($t:ty),*) => {$(
+            impl Compare<$t> for $t {
+                fn compare(&self, rhs: &$t) -> Option<Ordering> {
+                    self.partial_cmp(rhs)
+                }
+            }
+        )*};
+    }
+
+    ordered!(i32, i64, u32, u64, usize, f32, f64, bool, char, String);
+
+    macro_rules! ordered_with_value {
+        ($($t:ty),*) => {$(
+            impl Compare<Value> for $t {
+                fn compare(&self, rhs: &Value) -> Option<Ordering> {
+                    compare_values(&Value::from(self.clone()), rhs)
+                }
+            }
+        )*};
+    }
+
+    ordered_with_value!(i32, i64, u32, u64, usize, f32, f64, String);
+
+    impl Compare<&str> for str {
+        fn compare(&self, rhs: &&str) -> Option<Ordering> {
+            self.partial_cmp(*rhs)
+        }
+    }
+
+    impl Compare<String> for str {
+        fn compare(&self, rhs: &String) -> Option<Ordering> {
+            self.partial_cmp(rhs.as_str())
+        }
+    }
+
+    impl Compare<&str> for String {
+        fn compare(&self, rhs: &&str) -> Option<Ordering> {
+            self.as_str().partial_cmp(*rhs)
+        }
+    }
+
+    impl Compare<Value> for str {
+        fn compare(&self, rhs: &Value) -> Option<Ordering> {
+            compare_values(&Value::from(self), rhs)
+        }
+    }
+
+    // Numbers compare as numbers and strings alphabetically; any other pair
+    // is unordered, so every comparison on it is false.
+    fn compare_values(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+        match (lhs, rhs) {
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            _ => lhs.as_f64()?.partial_cmp(&rhs.as_f64()?),
+        }
+    }
+
+    // Same as for JSON numbers: exact quotients stay integers, inexact ones
+    // become floats and dividing by zero gives null.
+    macro_rules! int_division {
+        ($($t:ty),*) => {$(
+            impl Div<$t> for $t {
+                type Output = Value;
+                fn div(self, rhs: $t) -> Value {
+                    arith(Value::from(self), Value::from(rhs), Op::Div)
+                }
+            }
+
+            impl Rem<$t> for $t {
+                type Output = Value;
+                fn rem(self, rhs: $t) -> Value {
+                    arith(Value::from(self), Value::from(rhs), Op::Rem)
+                }
+            }
+        )*};
+    }
+
+    int_division!(i32, i64, u32, u64, usize);
+
+    macro_rules! float_division {
+        ($($t:ty),*) => {$(
+            impl Div<$t> for $t {
+                type Output = $t;
+                fn div(self, rhs: $t) -> $t {
+                    self / rhs
+                }
+            }
+
+            impl Rem<$t> for $t {
+                type Output = $t;
+                fn rem(self, rhs: $t) -> $t {
+                    self % rhs
+                }
+            }
+        )*};
+    }
+
+    float_division!(f32, f64);
+
+    // Integers meeting a float literal are widened, so `n * 1.5` works.
+    macro_rules! mixed {
+        ($($t:ty),*) => {$(
+            impl Add<f64> for $t {
+                type Output = f64;
+                fn add(self, rhs: f64) -> f64 {
+                    self as f64 + rhs
+                }
+            }
+
+            impl Add<$t> for f64 {
+                type Output = f64;
+                fn add(self, rhs: $t) -> f64 {
+                    self + rhs as f64
+                }
+            }
+
+            impl Sub<f64> for $t {
+                type Output = f64;
+                fn sub(self, rhs: f64) -> f64 {
+                    self as f64 - rhs
+                }
+            }
+
+            impl Sub<$t> for f64 {
+                type Output = f64;
+                fn sub(self, rhs: $t) -> f64 {
+                    self - rhs as f64
+                }
+            }
+
+            impl Mul<f64> for $t {
+                type Output = f64;
+                fn mul(self, rhs: f64) -> f64 {
+                    self as f64 * rhs
+                }
+            }
+
+            impl Mul<$t> for f64 {
+                type Output = f64;
+                fn mul(self, rhs: $t) -> f64 {
+                    self * rhs as f64
+                }
+            }
+
+            impl Div<f64> for $t {
+                type Output = f64;
+                fn div(self, rhs: f64) -> f64 {
+                    self as f64 / rhs
+                }
+            }
+
+            impl Div<$t> for f64 {
+                type Output = f64;
+                fn div(self, rhs: $t) -> f64 {
+                    self / rhs as f64
+                }
+            }
+
+            impl Rem<f64> for $t {
+                type Output = f64;
+                fn rem(self, rhs: f64) -> f64 {
+                    self as f64 % rhs
+                }
+            }
+
+            impl Rem<$t> for f64 {
+                type Output = f64;
+                fn rem(self, rhs: $t) -> f64 {
+                    self % rhs as f64
+                }
+            }
+
+            impl Compare<f64> for $t {
+                fn compare(&self, rhs: &f64) -> Option<Ordering> {
+                    (*self as f64).partial_cmp(rhs)
+                }
+            }
+
+            impl Compare<$t> for f64 {
+                fn compare(&self, rhs: &$t) -> Option<Ordering> {
+                    self.partial_cmp(&(*rhs as f64))
+                }
+            }
+        )*};
+    }
+
+    mixed!(i32, i64, u32, u64, usize);
+
+    /// How a value reads when added to a string: JSON strings go in without
+    /// their quotes.
+    pub trait Text {
+        fn text(&self) -> String;
+    }
+
+    impl<T: Text + ?Sized> Text for &T {
+        fn text(&self) -> String {
+            (**self).text()
+        }
+    }
+
+    impl Text for Value {
+        fn text(&self) -> String {
+            text(self)
+        }
+    }
+
+    macro_rules! display_text {
+        ($($t:ty),*) => {$(
+            impl Text for $t {
+                fn text(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*};
+    }
+
+    display_text!(str, String, char, bool, i32, i64, u32, u64, usize, f32, f64);
+
+    impl<R: Text> Add<R> for String {
+        type Output = String;
+        fn add(self, rhs: R) -> String {
+            format!("{}{}", self, rhs.text())
+        }
+    }
+
+    impl<R: Text> Add<R> for &String {
+        type Output = String;
+        fn add(self, rhs: R) -> String {
+            format!("{}{}", self, rhs.text())
+        }
+    }
+
+    impl<R: Text> Add<R> for &str {
+        type Output = String;
+        fn add(self, rhs: R) -> String {
+            format!("{}{}", self, rhs.text())
+        }
+    }
+
+    impl<R: Into<Value>> Add<R> for Value {
+        type Output = Value;
+        fn add(self, rhs: R) -> Value {
+            add_values(self, rhs.into())
+        }
+    }
+
+    impl<R: Into<Value>> Sub<R> for Value {
+        type Output = Value;
+        fn sub(self, rhs: R) -> Value {
+            arith(self, rhs.into(), Op::Sub)
+        }
+    }
+
+    impl<R: Into<Value>> Mul<R> for Value {
+        type Output = Value;
+        fn mul(self, rhs: R) -> Value {
+            arith(self, rhs.into(), Op::Mul)
+        }
+    }
+
+    impl<R: Into<Value>> Div<R> for Value {
+        type Output = Value;
+        fn div(self, rhs: R) -> Value {
+            arith(self, rhs.into(), Op::Div)
+        }
+    }
+
+    impl<R: Into<Value>> Rem<R> for Value {
+        type Output = Value;
+        fn rem(self, rhs: R) -> Value {
+            arith(self, rhs.into(), Op::Rem)
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Op {
+        Add,
+        Sub,
+        Mul,
+        Div,
+        Rem,
+    }
+
+    fn add_values(lhs: Value, rhs: Value) -> Value {
+        if lhs.is_number() && rhs.is_number() {
+            return arith(lhs, rhs, Op::Add);
+        }
+        Value::String(format!("{}{}", text(&lhs), text(&rhs)))
+    }
+
+    fn text(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    // Integers stay integers unless dividing would truncate; anything that
+    // isn't a number yields null.
+    fn arith(lhs: Value, rhs: Value, op: Op) -> Value {
+        if let (Some(a), Some(b)) = (lhs.as_i64(), rhs.as_i64()) {
+            let exact = match op {
+                Op::Add => a.checked_add(b),
+                Op::Sub => a.checked_sub(b),
+                Op::Mul => a.checked_mul(b),
+                Op::Div if b != 0 && a % b == 0 => Some(a / b),
+                Op::Div => None,
+                Op::Rem => a.checked_rem(b),
+            };
+            if let Some(n) = exact {
+                return Value::from(n);
+            }
+        }
+        let (Some(a), Some(b)) = (lhs.as_f64(), rhs.as_f64()) else {
+            return Value::Null;
+        };
+        let result = match op {
+            Op::Add => a + b,
+            Op::Sub => a - b,
+            Op::Mul => a * b,
+            Op::Div => a / b,
+            Op::Rem => a % b,
+        };
+        serde_json::Number::from_f64(result)
+            .map(Value::Number)
+            .unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub mod parquet {
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use ::parquet::arrow::ArrowWriter;
+    use anyhow::{anyhow, Result};
+    use arrow::array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::json::ArrayWriter;
+    use arrow::record_batch::RecordBatch;
+    use serde_json::{json, Value};
+    use std::fs::File;
+    use std::sync::Arc;
+
+    /// Writes an array of JSON objects as a Parquet file. Columns are the
+    /// union of keys in first-seen order; a column is int64, float64 or
+    /// boolean when every non-null value fits, otherwise string (nested
+    /// values as JSON text). Returns `{"rows": n}` or `{"error": ...}`.
+    pub fn write<R>(path: &str, rows: R) -> String
+    where
+        R: crate::iter::Items,
+        R::Item: Into<Value>,
+    {
+        let rows = rows.items().map(Into::into).collect::<Vec<Value>>();
+        if crate::skip_for_dry_run(format_args!("write {} rows to {}", rows.len(), path)) {
+            return json!({ "rows": rows.len(), "dry_run": true }).to_string();
+        }
+        match write_inner(path, &rows) {
+            Ok(count) => json!({ "rows": count }).to_string(),
+            Err(e) => json!({ "error": e.to_string() }).to_string(),
+        }
+    }
+
+    /// Reads a Parquet file back as an array of JSON objects.
+    pub fn read(path: &str) -> Value {
+        read_inner(path).unwrap_or_else(|e| json!({ "error": e.to_string() }))
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Kind {
+        Int,
+        Float,
+        Bool,
+        Text,
+    }
+
+    fn kind_of(value: &Value) -> Option<Kind> {
+        match value {
+            Value::Null => None,
+            Value::Bool(_) => Some(Kind::Bool),
+            Value::Number(n) if n.is_i64() => Some(Kind::Int),
+            Value::Number(_) => Some(Kind::Float),
+            _ => Some(Kind::Text),
+        }
+    }
+
+    fn merge(a: Option<Kind>, b: Option<Kind>) -> Option<Kind> {
+        match (a, b) {
+            (None, k) | (k, None) => k,
+            (Some(x), Some(y)) if x == y => Some(x),
+            (Some(Kind::Int), Some(Kind::Float)) | (Some(Kind::Float), Some(Kind::Int)) => {
+                Some(Kind::Float)
+            }
+            _ => Some(Kind::Text),
+        }
+    }
+
+    fn write_inner(path: &str, rows: &[Value]) -> Result<usize> {
+        let mut columns: Vec<(String, Option<Kind>)> = Vec::new();
+        for row in rows {
+            let fields = row
+                .as_object()
+                .ok_or_else(|| anyhow!("parquet.write expects an array of objects"))?;
+            for (key, value) in fields {
+                match columns.iter_mut().find(|(name, _)| name == key) {
+                    Some((_, kind)) => *kind = merge(*kind, kind_of(value)),
+                    None => columns.push((key.clone(), kind_of(value))),
+                }
+            }
+        }
+
+        let mut fields = Vec::with_capacity(columns.len());
+        let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len());
+        for (name, kind) in &columns {
+            let cells = rows.iter().map(|row| row.get(name).unwrap_or(&Value::Null));
+            let (data_type, array): (DataType, ArrayRef) = match kind.unwrap_or(Kind::Text) {
+                Kind::Int => {
+                    let mut b = Int64Builder::new();
+                    cells.for_each(|v| b.append_option(v.as_i64()));
+                    (DataType::Int64, Arc::new(b.finish()))
+                }
+                Kind::Float => {
+                    let mut b = Float64Builder::new();
+                    cells.for_each(|v| b.append_option(v.as_f64()));
+                    (DataType::Float64, Arc::new(b.finish()))
+                }
+                Kind::Bool => {
+                    let mut b = BooleanBuilder::new();
+                    cells.for_each(|v| b.append_option(v.as_bool()));
+                    (DataType::Boolean, Arc::new(b.finish()))
+                }
+                Kind::Text => {
+                    let mut b = StringBuilder::new();
+                    for v in cells {
+                        match v {
+                            Value::Null => b.append_null(),
+                            Value::String(s) => b.append_value(s),
+                            other => b.append_value(other.to_string()),
+                        }
+                    }
+                    (DataType::Utf8, Arc::new(b.finish()))
+                }
+            };
+            fields.push(Field::new(name, data_type, true));
+            arrays.push(array);
+        }
+
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+        let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(rows.len())
+    }
+
+    fn read_inner(path: &str) -> Result<Value> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+        let mut writer = ArrayWriter::new(Vec::new());
+        for batch in reader {
+            writer.write(&batch?)?;
+        }
+        writer.finish()?;
+        let bytes = writer.into_inner();
+        if bytes.is_empty() {
+            return Ok(Value::Array(Vec::new()));
+        }
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+pub mod parse {
+    use chrono::{
+        DateTime, Duration, Months, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc,
+    };
+    use serde_json::{json, Value};
+
+    const CURRENCY_SYMBOLS: &[(&str, &str)] = &[
+        ("US$", "USD"),
+        ("C$", "CAD"),
+        ("A$", "AUD"),
+        ("R$", "BRL"),
+        ("$", "USD"),
+        ("€", "EUR"),
+        ("£", "GBP"),
+        ("¥", "JPY"),
+        ("₹", "INR"),
+        ("₩", "KRW"),
+        ("₽", "RUB"),
+        ("₺", "TRY"),
+        ("zł", "PLN"),
+        ("kr", "SEK"),
+    ];
+
+    /// `"$1,299.99"` -> `{"amount": 1299.99, "currency": "USD"}`, or null.
+    pub fn price(s: &str) -> Value {
+        let Some((amount, _)) = leading_number(s) else {
+            return Value::Null;
+        };
+        json!({ "amount": to_json(amount), "currency": currency(s) })
+    }
+
+    /// `"1.2k"` -> 1200, `"3.5M"` -> 3500000, or null.
+    pub fn number(s: &str) -> Value {
+        let Some((value, rest)) = leading_number(s) else {
+            return Value::Null;
+        };
+        let scale = match rest.trim().to_lowercase().as_str() {
+            "" | "%" => 1.0,
+            "k" | "thousand" => 1e3,
+            "m" | "mn" | "mm" | "million" => 1e6,
+            "b" | "bn" | "billion" => 1e9,
+            "t" | "tn" | "trillion" => 1e12,
+            _ => return Value::Null,
+        };
+        to_json(value * scale)
+    }
+
+    /// Absolute or relative ("3 days ago", "yesterday") dates as RFC 3339 in
+    /// UTC, or null.
+    pub fn date(s: &str) -> Value {
+        let now = Utc::now();
+        relative_date(&s.trim().to_lowercase(), now)
+            .or_else(|| absolute_date(s.trim()))
+            .map(|dt| Value::String(dt.to_rfc3339_opts(SecondsFormat::Secs, true)))
+            .unwrap_or(Value::Null)
+    }
+
+    fn currency(s: &str) -> Value {
+        let code = s
+            .split(|c: char| !c.is_ascii_alphabetic())
+            .find(|word| word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase()));
+        if let Some(code) = code {
+            return Value::String(code.to_string());
+        }
+        CURRENCY_SYMBOLS
+            .iter()
+            .find(|(symbol, _)| s.contains(symbol))
+            .map(|(_, code)| Value::String(code.to_string()))
+            .unwrap_or(Value::Null)
+    }
+
+    // Splits off the leading number, honouring both 1,299.99 and 1.299,99.
+    fn leading_number(s: &str) -> Option<(f64, &str)> {
+        let start = s.find(|c: char| c.is_ascii_digit())?;
+        let negative = s[..start].trim_end().ends_with('-');
+        let chars = s[start..]
+            .char_indices()
+            .map(|(i, c)| (i + start, c))
+            .collect::<Vec<_>>();
+        let mut end = start;
+        let mut digits = String::new();
+        for (pos, &(i, c)) in chars.iter().enumerate() {
+            let next_is_digit = chars.get(pos + 1).is_some_and(|&(_, n)| n.is_ascii_digit());
+            match c {
+                '0'..='9' => digits.push(c),
+                ',' | '.' if next_is_digit => digits.push(c),
+                '\'' | ' ' | '\u{a0}' | '\u{202f}' if next_is_digit => {}
+                _ => break,
+            }
+            end = i + c.len_utf8();
+        }
+        let decimal = match (digits.rfind(','), digits.rfind('.')) {
+            (Some(comma), Some(dot)) => Some(if comma > dot { ',' } else { '.' }),
+            (Some(i), None) | (None, Some(i)) => {
+                let sep = digits.as_bytes()[i] as char;
+                // A lone separator with three digits after it groups thousands.
+                let lone = digits.matches(sep).count() == 1;
+                (lone && digits.len() - i - 1 != 3).then_some(sep)
+            }
+            (None, None) => None,
+        };
+        let normalized = digits
+            .chars()
+            .filter_map(|c| match c {
+                '0'..='9' => Some(c),
+                _ if Some(c) == decimal => Some('.'),
+                _ => None,
+            })
+            .collect::<String>();
+        let value: f64 = normalized.parse().ok()?;
+        Some((if negative { -value } else { value }, &s[end..]))
+    }
+
+    fn to_json(value: f64) -> Value {
+        let value = (value * 1e6).round() / 1e6;
+        if value.fract() == 0.0 && value.abs() < 9e15 {
+            json!(value as i64)
+        } else {
+            json!(value)
+        }
+    }
+
+    fn relative_date(s: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let midnight = now.date_naive().and_hms_opt(0, 0, 0)?.and_utc();
+        match s {
+            "now" | "just now" => return Some(now),
+            "today" => return Some(midnight),
+            "yesterday" => return Some(midnight - Duration::days(1)),
+            "tomorrow" => return Some(midnight + Duration::days(1)),
+            _ => {}
+        }
+        let (words, future) = match (s.strip_suffix(" ago"), s.strip_prefix("in ")) {
+            (Some(rest), _) => (rest, false),
+            (None, Some(rest)) => (rest, true),
+            (None, None) => return None,
+        };
+        let mut parts = words.split_whitespace();
+        let count: u32 = match parts.next()? {
+            "a" | "an" | "one" => 1,
+            n => n.parse().ok()?,
+        };
+        let unit = parts.next()?.trim_end_matches('s');
+        if parts.next().is_some() {
+            return None;
+        }
+        let delta = match unit {
+            "sec" | "second" => Duration::seconds(count.into()),
+            "min" | "minute" => Duration::minutes(count.into()),
+            "h" | "hr" | "hour" => Duration::hours(count.into()),
+            "day" => Duration::days(count.into()),
+            "week" => Duration::weeks(count.into()),
+            "month" | "year" => {
+                let months = Months::new(if unit == "year" { count * 12 } else { count });
+                return if future {
+                    now.checked_add_months(months)
+                } else {
+                    now.checked_sub_months(months)
+                };
+            }
+            _ => return None,
+        };
+        Some(if future { now + delta } else { now - delta })
+    }
+
+    fn absolute_date(s: &str) -> Option<DateTime<Utc>> {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Some(dt.with_timezone(&Utc));
+        }
+        if let Ok(dt) = DateTime::parse_from_rfc2822(s) {
+            return Some(dt.with_timezone(&Utc));
+        }
+        const DATE_TIMES: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"];
+        for format in DATE_TIMES {
+            if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+                return Some(Utc.from_utc_datetime(&dt));
+            }
+        }
+        const DATES: &[&str] = &[
+            "%Y-%m-%d",
+            "%Y/%m/%d",
+            "%B %d, %Y",
+            "%b %d, %Y",
+            "%B %d %Y",
+            "%b %d %Y",
+            "%d %B %Y",
+            "%d %b %Y",
+            "%m/%d/%Y",
+            "%d.%m.%Y",
+        ];
+        let cleaned = s
+            .split(' ')
+            .map(strip_ordinal)
+            .collect::<Vec<_>>()
+            .join(" ");
+        DATES.iter().find_map(|format| {
+            NaiveDate::parse_from_str(&cleaned, format)
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|dt| Utc.from_utc_datetime(&dt))
+        })
+    }
+
+    // "5th," -> "5," so that "March 5th, 2024" parses.
+    fn strip_ordinal(word: &str) -> String {
+        let (core, comma) = match word.strip_suffix(',') {
+            Some(core) => (core, ","),
+            None => (word, ""),
+        };
+        for ordinal in ["st", "nd", "rd", "th"] {
+            if let Some(num) = core.strip_suffix(ordinal) {
+                if !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()) {
+                    return format!("{}{}", num, comma);
+                }
+            }
+        }
+        word.to_string()
+    }
+}
+
+/// Progress reporting for long loops: a live bar on a terminal, plain
+/// `[progress]` lines every 10% (or 5 seconds) when output is redirected.
+pub mod progress {
+    use indicatif::{ProgressBar, ProgressStyle};
+    use std::io::IsTerminal;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    const LINE_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub struct Progress {
+        bar: Option<ProgressBar>,
+        total: u64,
+        done: AtomicU64,
+        started: Instant,
+        last_line: Mutex<(Instant, u64)>,
+    }
+
+    /// How many steps a bar has: an integer literal, a `len()`, or a JSON
+    /// number. Negative or missing totals count as zero.
+    pub trait Total {
+        fn total(&self) -> u64;
+    }
+
+    macro_rules! total {
+        ($($t:ty),*) => {$(
+            impl Total for $t {
+                fn total(&self) -> u64 {
+                    u64::try_from(*self).unwrap_or(0)
+                }
+            }
+        )*};
+    }
+
+    total!(i32, i64, u32, u64, usize);
+
+    impl Total for serde_json::Value {
+        fn total(&self) -> u64 {
+            self.as_u64().unwrap_or(0)
+        }
+    }
+
+    pub fn new<N: Total>(total: N) -> Progress {
+        let total = total.total();
+        let bar = std::io::stderr().is_terminal().then(|| {
+            let bar = ProgressBar::new(total);
+            if let Ok(style) = ProgressStyle::with_template(
+                "{bar:40.cyan/blue} {pos}/{len} ({percent}%) {elapsed_precise} eta {eta}",
+            ) {
+                bar.set_style(style.progress_chars("=> "));
+            }
+            bar
+        });
+        Progress {
+            bar,
+            total,
+            done: AtomicU64::new(0),
+            started: Instant::now(),
+            last_line: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    impl Progress {
+        pub fn tick(&self) {
+            let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(bar) = &self.bar {
+                bar.inc(1);
+                return;
+            }
+            let mut last = self.last_line.lock().unwrap();
+            let step = (self.total / 10).max(1);
+            if done / step > last.1 / step || last.0.elapsed() >= LINE_INTERVAL {
+                *last = (Instant::now(), done);
+                self.line(done);
+            }
+        }
+
+        pub fn finish(&self) {
+            let done = self.done.load(Ordering::Relaxed);
+            match &self.bar {
+                Some(bar) => bar.finish(),
+                None => self.line(done),
+            }
+        }
+
+        fn line(&self, done: u64) {
+            let percent = (done * 100).checked_div(self.total).unwrap_or(100);
+            eprintln!(
+                "[progress] {}/{} ({}%) {:.1}s",
+                done,
+                self.total,
+                percent,
+                self.started.elapsed().as_secs_f64()
+            );
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+pub mod python {
+    use pyo3::prelude::*;
+    use std::ffi::CString;
+
+    pub fn eval(code: &str) -> String {
+        let code_c = CString::new(code).unwrap();
+        Python::with_gil(|py| {
+            let result = py.eval(&code_c, None, None);
+            match result {
+                Ok(value) => value.to_string(),
+                Err(err) => err.to_string(),
+            }
+        })
+    }
+}
+
+/// Randomness for scripts. `zn run --seed N` (or `ZINC_SEED`) makes every
+/// value, shuffle, uuid and jitter delay repeat exactly from run to run.
+pub mod random {
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    static STATE: Mutex<Option<u64>> = Mutex::new(None);
+
+    fn initial_seed() -> u64 {
+        if let Ok(raw) = std::env::var("ZINC_SEED") {
+            match raw.trim().parse() {
+                Ok(seed) => return seed,
+                Err(_) => eprintln!("Ignoring ZINC_SEED={}: expected a whole number", raw),
+            }
+        }
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        nanos ^ ((std::process::id() as u64) << 32)
+    }
+
+    // SplitMix64: tiny, fast and good enough for scraping. Not for secrets.
+    fn next_u64() -> u64 {
+        let mut state = STATE.lock().unwrap();
+        let s = state.get_or_insert_with(initial_seed);
+        *s = s.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *s;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Restarts the sequence, as if the program had been run with `--seed`.
+    pub fn seed(seed: i64) {
+        *STATE.lock().unwrap() = Some(seed as u64);
+    }
+
+    /// A float in `[0, 1)`.
+    pub fn float() -> f64 {
+        (next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in `lo..=hi`.
+    pub fn int<A: Into<i64>, B: Into<i64>>(lo: A, hi: B) -> i64 {
+        let (lo, hi) = (lo.into(), hi.into());
+        if hi <= lo {
+            return lo;
+        }
+        let span = (hi - lo) as u64 + 1;
+        lo + (next_u64() % span) as i64
+    }
+
+    pub fn shuffle<T: Clone>(items: &[T]) -> Vec<T> {
+        let mut out = items.to_vec();
+        for i in (1..out.len()).rev() {
+            let j = (next_u64() % (i as u64 + 1)) as usize;
+            out.swap(i, j);
+        }
+        out
+    }
+
+    /// A version 4 UUID.
+    pub fn uuid() -> String {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&next_u64().to_le_bytes());
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex = bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+
+    /// Sleeps for a random time between `min_ms` and `max_ms` milliseconds.
+    pub fn jitter<A: Into<i64>, B: Into<i64>>(min_ms: A, max_ms: B) {
+        let ms = int(min_ms.into().max(0), max_ms.into().max(0));
+        std::thread::sleep(Duration::from_millis(ms as u64));
+    }
+}
+
+#[cfg(feature = "redis")]
+pub mod redis {
+    use ::redis::{Client, Commands, Connection, RedisResult};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    // One connection per run, opened lazily from REDIS_URL.
+    static CONNECTION: Mutex<Option<Connection>> = Mutex::new(None);
+
+    fn with_connection<T>(f: impl FnOnce(&mut Connection) -> RedisResult<T>) -> RedisResult<T> {
+        let mut slot = CONNECTION.lock().unwrap();
+        if slot.is_none() {
+            let url =
+                std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+            *slot = Some(Client::open(url)?.get_connection()?);
+        }
+        let result = f(slot.as_mut().expect("connection was just opened"));
+        if result
+            .as_ref()
+            .is_err_and(|e| e.is_connection_dropped() || e.is_io_error())
+        {
+            // Reconnect on the next call instead of failing forever.
+            *slot = None;
+        }
+        result
+    }
+
+    fn error_json(err: ::redis::RedisError) -> String {
+        json!({ "error": err.to_string() }).to_string()
+    }
+
+    /// The value at `key`, "" if it doesn't exist, or `{"error": ...}`.
+    pub fn get(key: &str) -> String {
+        with_connection(|con| con.get::<_, Option<String>>(key))
+            .map(|v| v.unwrap_or_default())
+            .unwrap_or_else(error_json)
+    }
+
+    pub fn set(key: &str, value: &str) -> bool {
+        if crate::skip_for_dry_run(format_args!("set redis key {} to {}", key, value)) {
+            return true;
+        }
+        with_connection(|con| con.set::<_, _, ()>(key, value))
+            .map_err(|e| eprintln!("Redis error: {}", e))
+            .is_ok()
+    }
+
+    /// Pushes onto the head of a list; returns the new length, or -1 on error.
+    pub fn lpush(key: &str, value: &str) -> i64 {
+        if crate::skip_for_dry_run(format_args!("push {} onto redis list {}", value, key)) {
+            return 0;
+        }
+        with_connection(|con| con.lpush::<_, _, i64>(key, value)).unwrap_or_else(|e| {
+            eprintln!("Redis error: {}", e);
+            -1
+        })
+    }
+
+    /// Pops from the tail of a list, "" when it is empty.
+    pub fn rpop(key: &str) -> String {
+        with_connection(|con| con.rpop::<_, Option<String>>(key, None))
+            .map(|v| v.unwrap_or_default())
+            .unwrap_or_else(error_json)
+    }
+}
+
+#[cfg(feature = "s3")]
+pub mod s3 {
+    use ::s3::creds::Credentials;
+    use ::s3::{Bucket, Region};
+    use anyhow::{anyhow, Result};
+    use serde_json::json;
+
+    // Credentials come from the usual AWS_ACCESS_KEY_ID /
+    // AWS_SECRET_ACCESS_KEY (/ AWS_SESSION_TOKEN) variables. AWS_REGION
+    // picks the region; S3_ENDPOINT switches to an S3-compatible service
+    // (MinIO, R2, ...) with path-style addressing.
+    fn bucket(name: &str) -> Result<Box<Bucket>> {
+        let credentials = Credentials::from_env()?;
+        let region_name = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let bucket = match std::env::var("S3_ENDPOINT") {
+            Ok(endpoint) => Bucket::new(
+                name,
+                Region::Custom {
+                    region: region_name,
+                    endpoint,
+                },
+                credentials,
+            )?
+            .with_path_style(),
+            Err(_) => Bucket::new(name, region_name.parse()?, credentials)?,
+        };
+        Ok(bucket)
+    }
+
+    /// Uploads `data` to `bucket/key`. Returns `{"status": code}` or
+    /// `{"error": ...}`.
+    pub fn put(bucket_name: &str, key: &str, data: &str) -> String {
+        if crate::skip_for_dry_run(format_args!(
+            "upload {} bytes to s3://{}/{}",
+            data.len(),
+            bucket_name,
+            key
+        )) {
+            return json!({ "dry_run": true }).to_string();
+        }
+        let result = crate::block_on(async {
+            let response = bucket(bucket_name)?
+                .put_object(key, data.as_bytes())
+                .await?;
+            match response.status_code() {
+                code @ 200..=299 => Ok(code),
+                code => Err(anyhow!("HTTP {}", code)),
+            }
+        });
+        match result {
+            Ok(code) => json!({ "status": code }).to_string(),
+            Err(e) => json!({ "error": e.to_string() }).to_string(),
+        }
+    }
+
+    /// Downloads `bucket/key` as text, or returns `{"error": ...}`.
+    pub fn get(bucket_name: &str, key: &str) -> String {
+        let result = crate::block_on(async {
+            let response = bucket(bucket_name)?.get_object(key).await?;
+            match response.status_code() {
+                200..=299 => Ok(String::from_utf8_lossy(response.bytes()).into_owned()),
+                code => Err(anyhow!("HTTP {}", code)),
+            }
+        });
+        result.unwrap_or_else(|e| json!({ "error": e.to_string() }).to_string())
+    }
+}
+
+#[cfg(feature = "http")]
+pub mod spider {
+    use encoding_rs::{Encoding, WINDOWS_1252};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::future::Future;
+    use std::net::{IpAddr, SocketAddr};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+    use tokio::sync::Semaphore;
+    use tracing::Instrument;
+    use wreq::Client;
+    use wreq_util::Emulation;
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct HttpError {
+        pub url: String,
+        pub kind: String,
+        pub status: Option<u16>,
+        pub message: String,
+    }
+
+    impl HttpError {
+        fn new(url: &str, kind: &str, message: impl ToString) -> Self {
+            HttpError {
+                url: url.to_string(),
+                kind: kind.to_string(),
+                status: None,
+                message: message.to_string(),
+            }
+        }
+
+        fn from_wreq(url: &str, err: wreq::Error) -> Self {
+            let message = err.to_string();
+            let kind = if err.is_timeout() {
+                "timeout"
+            } else if message.to_lowercase().contains("dns") {
+                "dns"
+            } else if err.is_connect() {
+                "connect"
+            } else if err.is_body() || err.is_decode() {
+                "body"
+            } else {
+                "request"
+            };
+            let mut out = HttpError::new(url, kind, message);
+            out.status = err.status().map(|s| s.as_u16());
+            out
+        }
+
+        pub fn to_json(&self) -> String {
+            crate::summary::count_error();
+            serde_json::to_string(&serde_json::json!({ "error": self }))
+                .unwrap_or_else(|_| "{\"error\":\"request failed\"}".to_string())
+        }
+    }
+
+    impl fmt::Display for HttpError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self.status {
+                Some(status) => write!(
+                    f,
+                    "{} error ({}) for {}: {}",
+                    self.kind, status, self.url, self.message
+                ),
+                None => write!(f, "{} error for {}: {}", self.kind, self.url, self.message),
+            }
+        }
+    }
+
+    impl std::error::Error for HttpError {}
+
+    struct Fetched {
+        status: u16,
+        body: String,
+    }
+
+    #[derive(Default, Clone)]
+    struct FetchOptions {
+        profile: Option<String>,
+        proxy: Option<String>,
+        encoding: Option<String>,
+        headers: Vec<(String, String)>,
+        body: Option<Vec<u8>>,
+        session: Option<String>,
+    }
+
+    impl FetchOptions {
+        fn profile(profile: Option<&str>) -> Self {
+            FetchOptions {
+                profile: profile.map(|p| p.to_string()),
+                ..Default::default()
+            }
+        }
+    }
+
+    static CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+    #[derive(Serialize, Deserialize)]
+    struct CacheEntry {
+        url: String,
+        status: u16,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        body: String,
+    }
+
+    /// Opts into an on-disk cache: responses carrying an `ETag` or
+    /// `Last-Modified` are stored under `dir` and revalidated on the next
+    /// request, so a `304 Not Modified` is served from disk.
+    pub fn enable_cache(dir: &str) {
+        let _ = std::fs::create_dir_all(dir);
+        *CACHE_DIR.lock().unwrap() = Some(PathBuf::from(dir));
+    }
+
+    pub fn disable_cache() {
+        *CACHE_DIR.lock().unwrap() = None;
+    }
+
+    fn cache_path(url: &str, profile: Option<&str>) -> Option<PathBuf> {
+        let dir = CACHE_DIR.lock().unwrap().clone()?;
+        // FNV-1a, so file names stay stable across toolchains.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in url.bytes().chain([0]).chain(profile.unwrap_or("").bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        Some(dir.join(format!("{:016x}.json", hash)))
+    }
+
+    fn load_cached(path: &PathBuf, url: &str) -> Option<CacheEntry> {
+        let raw = std::fs::read_to_string(path).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&raw).ok()?;
+        if entry.url == url {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    fn store_cached(path: &PathBuf, entry: &CacheEntry) {
+        if let Ok(raw) = serde_json::to_string(entry) {
+            let _ = std::fs::write(path, raw);
+        }
+    }
+
+    // Transport failures come back as a JSON `{"error": {...}}` document, the
+    // same convention `db::query` uses; use `try_get` to get a `Result`.
+    pub fn get(url: &str, profile: Option<&str>) -> String {
+        get_with_proxy(url, profile, None)
+    }
+
+    pub fn get_with_proxy(url: &str, profile: Option<&str>, proxy: Option<&str>) -> String {
+        let opts = FetchOptions {
+            proxy: proxy.map(|p| p.to_string()),
+            ..FetchOptions::profile(profile)
+        };
+        match crate::block_on(fetch(url.to_string(), opts)) {
+            Ok(f) => f.body,
+            Err(err) => err.to_json(),
+        }
+    }
+
+    // For servers that lie about their charset: `encoding` is a WHATWG label
+    // such as "shift_jis" or "windows-1251" and skips detection entirely.
+    pub fn get_with_encoding(url: &str, profile: Option<&str>, encoding: &str) -> String {
+        let opts = FetchOptions {
+            encoding: Some(encoding.to_string()),
+            ..FetchOptions::profile(profile)
+        };
+        match crate::block_on(fetch(url.to_string(), opts)) {
+            Ok(f) => f.body,
+            Err(err) => err.to_json(),
+        }
+    }
+
+    /// Like `get`, but keeps the status: `{"status": ..., "body": ...}`, or
+    /// the `{"error": ...}` document when the request fails.
+    pub fn get_response(url: &str, profile: Option<&str>) -> Value {
+        match crate::block_on(fetch(url.to_string(), FetchOptions::profile(profile))) {
+            Ok(f) => json!({ "status": f.status, "body": f.body }),
+            Err(err) => serde_json::from_str(&err.to_json()).unwrap_or(Value::Null),
+        }
+    }
+
+    pub fn try_get(url: &str, profile: Option<&str>) -> Result<String, HttpError> {
+        let fetched = crate::block_on(fetch(url.to_string(), FetchOptions::profile(profile)))?;
+        if fetched.status >= 400 {
+            let mut err = HttpError::new(url, "status", format!("HTTP {}", fetched.status));
+            err.status = Some(fetched.status);
+            return Err(err);
+        }
+        Ok(fetched.body)
+    }
+
+    pub fn get_async(
+        url: &str,
+        profile: Option<&str>,
+    ) -> impl Future<Output = String> + Send + 'static {
+        let fetched = fetch(url.to_string(), FetchOptions::profile(profile));
+        async move {
+            match fetched.await {
+                Ok(f) => f.body,
+                Err(err) => err.to_json(),
+            }
+        }
+    }
+
+    pub const DEFAULT_TOTAL_CONCURRENCY: usize = 64;
+    pub const DEFAULT_PER_HOST_CONCURRENCY: usize = 6;
+
+    pub fn get_all<S: AsRef<str>>(urls: &[S], profile: Option<&str>) -> Vec<String> {
+        let per_host = crawl_settings().map_or(DEFAULT_PER_HOST_CONCURRENCY, |c| c.per_host);
+        get_all_with_limits(urls, profile, per_host, DEFAULT_TOTAL_CONCURRENCY)
+    }
+
+    // Results come back in input order. A request waits for its host's slot
+    // before taking a global one, so a backlog on one site never starves
+    // the others.
+    pub fn get_all_with_limits<S: AsRef<str>>(
+        urls: &[S],
+        profile: Option<&str>,
+        per_host: usize,
+        total: usize,
+    ) -> Vec<String> {
+        let total = Arc::new(Semaphore::new(total.max(1)));
+        let mut hosts: HashMap<String, Arc<Semaphore>> = HashMap::new();
+        let handles = urls
+            .iter()
+            .map(|url| {
+                let host_slots = hosts
+                    .entry(host_of(url.as_ref()))
+                    .or_insert_with(|| Arc::new(Semaphore::new(per_host.max(1))))
+                    .clone();
+                let total = total.clone();
+                let request = get_async(url.as_ref(), profile);
+                crate::spawn(async move {
+                    let _host = host_slots.acquire_owned().await;
+                    let _slot = total.acquire_owned().await;
+                    request.await
+                })
+            })
+            .collect::<Vec<_>>();
+        crate::block_on(async move {
+            let mut out = Vec::with_capacity(handles.len());
+            for handle in handles {
+                out.push(handle.await);
+            }
+            out
+        })
+    }
+
+    fn host_of(url: &str) -> String {
+        let rest = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+        host.to_ascii_lowercase()
+    }
+
+    // The callback sees the body piece by piece as it arrives, split only on
+    // UTF-8 boundaries. Returns an empty string on success, otherwise the same
+    // JSON error document as `get`.
+    pub fn stream<T, F: FnMut(String) -> T>(
+        url: &str,
+        profile: Option<&str>,
+        mut on_chunk: F,
+    ) -> String {
+        let result = crate::block_on(async {
+            let mut resp = send_politely(url, &FetchOptions::profile(profile)).await?;
+            let mut pending = Vec::new();
+            while let Some(chunk) = resp
+                .chunk()
+                .await
+                .map_err(|e| HttpError::from_wreq(url, e))?
+            {
+                crate::summary::count_bytes(chunk.len());
+                pending.extend_from_slice(&chunk);
+                let valid = match std::str::from_utf8(&pending) {
+                    Ok(_) => pending.len(),
+                    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                    Err(_) => pending.len(),
+                };
+                if valid > 0 {
+                    let rest = pending.split_off(valid);
+                    on_chunk(String::from_utf8_lossy(&pending).into_owned());
+                    pending = rest;
+                }
+            }
+            if !pending.is_empty() {
+                on_chunk(String::from_utf8_lossy(&pending).into_owned());
+            }
+            Ok::<(), HttpError>(())
+        });
+        match result {
+            Ok(()) => String::new(),
+            Err(err) => err.to_json(),
+        }
+    }
+
+    /// Fetches `url` with extra request headers taken from a map, e.g.
+    /// `{"Authorization": auth.header(api)}`.
+    pub fn get_with_headers(url: &str, headers: &Value, profile: Option<&str>) -> String {
+        let opts = FetchOptions {
+            headers: header_pairs(headers),
+            ..FetchOptions::profile(profile)
+        };
+        match crate::block_on(fetch(url.to_string(), opts)) {
+            Ok(f) => f.body,
+            Err(err) => err.to_json(),
+        }
+    }
+
+    fn header_pairs(headers: &Value) -> Vec<(String, String)> {
+        headers
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    pub(crate) fn post_form(url: &str, form: &[(&str, &str)]) -> Result<String, HttpError> {
+        let body = form
+            .iter()
+            .map(|(k, v)| format!("{}={}", form_encode(k), form_encode(v)))
+            .collect::<Vec<_>>()
+            .join("&");
+        post(url, "application/x-www-form-urlencoded", body)
+    }
+
+    pub(crate) fn post_json(url: &str, payload: &Value) -> Result<String, HttpError> {
+        post(url, "application/json", payload.to_string())
+    }
+
+    fn post(url: &str, content_type: &str, body: String) -> Result<String, HttpError> {
+        let opts = FetchOptions {
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: Some(body.into_bytes()),
+            ..Default::default()
+        };
+        let fetched = crate::block_on(fetch(url.to_string(), opts))?;
+        if fetched.status >= 400 {
+            let mut err = HttpError::new(url, "status", format!("HTTP {}", fetched.status));
+            err.status = Some(fetched.status);
+            return Err(err);
+        }
+        Ok(fetched.body)
+    }
+
+    fn form_encode(value: &str) -> String {
+        let mut out = String::new();
+        for byte in value.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    out.push(byte as char)
+                }
+                b' ' => out.push('+'),
+                _ => out.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        out
+    }
+
+    /// Posts `fields` (a map of plain values) and `files` (a map of form name
+    /// to local path) as `multipart/form-data`.
+    pub fn upload(url: &str, fields: &Value, files: &Value) -> String {
+        if crate::skip_for_dry_run(format_args!("upload {} to {}", files, url)) {
+            return json!({ "dry_run": true }).to_string();
+        }
+        let boundary = format!(
+            "----zinc{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        );
+        let body = match multipart_body(&boundary, fields, files) {
+            Ok(body) => body,
+            Err(err) => return HttpError::new(url, "file", err).to_json(),
+        };
+        let opts = FetchOptions {
+            headers: vec![(
+                "Content-Type".to_string(),
+                format!("multipart/form-data; boundary={}", boundary),
+            )],
+            body: Some(body),
+            ..Default::default()
+        };
+        match crate::block_on(fetch(url.to_string(), opts)) {
+            Ok(f) => f.body,
+            Err(err) => err.to_json(),
+        }
+    }
+
+    fn multipart_body(boundary: &str, fields: &Value, files: &Value) -> std::io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        for (name, value) in fields.as_object().into_iter().flatten() {
+            let text = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            body.extend_from_slice(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                    boundary, name, text
+                )
+                .as_bytes(),
+            );
+        }
+        for (name, path) in files.as_object().into_iter().flatten() {
+            let path = path.as_str().unwrap_or_default();
+            let content = std::fs::read(path)?;
+            let filename = std::path::Path::new(path)
+                .file_name()
+                .and_then(|f| f.to_str())
+                .unwrap_or(path);
+            body.extend_from_slice(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                    boundary,
+                    name,
+                    filename,
+                    mime_for(filename)
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(&content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        Ok(body)
+    }
+
+    fn mime_for(filename: &str) -> &'static str {
+        let ext = filename
+            .rsplit('.')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => "application/json",
+            "csv" => "text/csv",
+            "txt" => "text/plain",
+            "html" | "htm" => "text/html",
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "pdf" => "application/pdf",
+            "zip" => "application/zip",
+            _ => "application/octet-stream",
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum CassetteMode {
+        // Replay what is on tape and record anything new.
+        Auto,
+        // Always go to the network and rewrite the tape.
+        Record,
+        // Never touch the network; unrecorded requests fail.
+        Replay,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Interaction {
+        method: String,
+        url: String,
+        request_body: Option<String>,
+        status: u16,
+        body: String,
+        #[serde(skip)]
+        played: bool,
+    }
+
+    struct Cassette {
+        path: PathBuf,
+        mode: CassetteMode,
+        interactions: Vec<Interaction>,
+    }
+
+    static CASSETTE: Mutex<Option<Cassette>> = Mutex::new(None);
+
+    /// Records responses to `path` on the first run and replays them on
+    /// later runs, so scraper tests are deterministic and work offline.
+    /// `ZINC_CASSETTE_MODE=record` forces fresh recordings and `replay`
+    /// fails any request that isn't on tape instead of fetching it.
+    pub fn cassette(path: &str) -> bool {
+        let mode = match std::env::var("ZINC_CASSETTE_MODE").as_deref() {
+            Ok("record") => CassetteMode::Record,
+            Ok("replay") => CassetteMode::Replay,
+            _ => CassetteMode::Auto,
+        };
+        let interactions = match std::fs::read_to_string(path) {
+            Ok(_) if mode == CassetteMode::Record => Vec::new(),
+            Ok(raw) => match serde_json::from_str(&raw) {
+                Ok(interactions) => interactions,
+                Err(e) => {
+                    eprintln!("Unreadable cassette {}: {}", path, e);
+                    return false;
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        *CASSETTE.lock().unwrap() = Some(Cassette {
+            path: PathBuf::from(path),
+            mode,
+            interactions,
+        });
+        true
+    }
+
+    // Method and request body; together with the URL they identify a recording.
+    type RequestKey = (String, Option<String>);
+
+    fn request_key(opts: &FetchOptions) -> RequestKey {
+        match &opts.body {
+            Some(body) => (
+                "POST".to_string(),
+                Some(String::from_utf8_lossy(body).into_owned()),
+            ),
+            None => ("GET".to_string(), None),
+        }
+    }
+
+    // Repeated requests replay their recordings in order; once those run out
+    // the last one keeps being served.
+    fn replay(url: &str, key: &RequestKey) -> Option<Result<Fetched, HttpError>> {
+        let mut slot = CASSETTE.lock().unwrap();
+        let cassette = slot.as_mut()?;
+        if cassette.mode == CassetteMode::Record {
+            return None;
+        }
+        let (method, request_body) = key;
+        let mut matches = cassette
+            .interactions
+            .iter_mut()
+            .filter(|i| &i.method == method && i.url == url && &i.request_body == request_body)
+            .collect::<Vec<_>>();
+        let hit = match matches.iter().position(|i| !i.played) {
+            Some(idx) => Some(matches.swap_remove(idx)),
+            None => matches.pop(),
+        };
+        match hit {
+            Some(hit) => {
+                hit.played = true;
+                Some(Ok(Fetched {
+                    status: hit.status,
+                    body: hit.body.clone(),
+                }))
+            }
+            None if cassette.mode == CassetteMode::Replay => {
+                Some(Err(HttpError::new(url, "cassette", "no recorded response")))
+            }
+            None => None,
+        }
+    }
+
+    fn record(url: &str, key: RequestKey, fetched: &Fetched) {
+        let mut slot = CASSETTE.lock().unwrap();
+        let Some(cassette) = slot.as_mut() else {
+            return;
+        };
+        let (method, request_body) = key;
+        cassette.interactions.push(Interaction {
+            method,
+            url: url.to_string(),
+            request_body,
+            status: fetched.status,
+            body: fetched.body.clone(),
+            played: true,
+        });
+        let written = serde_json::to_string_pretty(&cassette.interactions)
+            .map_err(|e| e.to_string())
+            .and_then(|raw| std::fs::write(&cassette.path, raw).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!(
+                "Failed to write cassette {}: {}",
+                cassette.path.display(),
+                e
+            );
+        }
+    }
+
+    async fn fetch(url: String, opts: FetchOptions) -> Result<Fetched, HttpError> {
+        let span = tracing::info_span!(
+            "spider.fetch",
+            http.method = if opts.body.is_some() { "POST" } else { "GET" },
+            url.full = %url,
+            http.status_code = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let result = fetch_recorded(url, opts).instrument(span.clone()).await;
+        match &result {
+            Ok(fetched) => span.record("http.status_code", fetched.status),
+            Err(err) => span.record("error", tracing::field::display(err)),
+        };
+        result
+    }
+
+    async fn fetch_recorded(url: String, opts: FetchOptions) -> Result<Fetched, HttpError> {
+        if let Some(body) = crate::mock::spider_body(&url) {
+            return Ok(Fetched { status: 200, body });
+        }
+        let key = request_key(&opts);
+        if let Some(replayed) = replay(&url, &key) {
+            return replayed;
+        }
+        let fetched = fetch_live(url.clone(), opts).await?;
+        record(&url, key, &fetched);
+        Ok(fetched)
+    }
+
+    async fn fetch_live(url: String, mut opts: FetchOptions) -> Result<Fetched, HttpError> {
+        let render = opts.body.is_none() && domain_config(&url).is_some_and(|d| d.render);
+        // Only plain GETs are cacheable. A rendered page is cached apart from
+        // the raw HTML at the same URL.
+        let cache_path = match opts.body {
+            Some(_) => None,
+            None if render => {
+                let key = format!("render:{}", opts.profile.as_deref().unwrap_or_default());
+                cache_path(&url, Some(&key))
+            }
+            None => cache_path(&url, opts.profile.as_deref()),
+        };
+        let cached = cache_path.as_ref().and_then(|p| load_cached(p, &url));
+        if render && cached.is_none() {
+            return render_politely(url, opts, cache_path).await;
+        }
+        let render_opts = render.then(|| opts.clone());
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                opts.headers
+                    .push(("If-None-Match".to_string(), etag.clone()));
+            }
+            if let Some(modified) = &entry.last_modified {
+                opts.headers
+                    .push(("If-Modified-Since".to_string(), modified.clone()));
+            }
+        }
+
+        let started = SystemTime::now();
+        let clock = Instant::now();
+        let resp = match send_politely(&url, &opts).await {
+            Ok(resp) => resp,
+            Err(err) => {
+                if har_enabled() {
+                    let response = json!({ "status": 0, "statusText": "", "_error": &err });
+                    har_record(
+                        &url,
+                        &opts,
+                        started,
+                        (clock.elapsed(), Duration::ZERO),
+                        response,
+                    );
+                }
+                return Err(err);
+            }
+        };
+        let wait = clock.elapsed();
+        let status = resp.status().as_u16();
+        let har = har_enabled().then(|| har_response(&resp));
+        if let (304, Some(entry)) = (status, cached) {
+            if let Some(response) = har {
+                har_record(&url, &opts, started, (wait, Duration::ZERO), response);
+            }
+            return Ok(Fetched {
+                status: entry.status,
+                body: entry.body,
+            });
+        }
+        // A plain request can revalidate a rendered page, but only Chrome
+        // can produce a new one.
+        if let Some(opts) = render_opts {
+            return render_politely(url, opts, cache_path).await;
+        }
+        let header = |name: &str| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let content_type = header("content-type");
+        let etag = header("etag");
+        let last_modified = header("last-modified");
+        let bytes = resp
+            .bytes()
+            .await
+            .map_err(|e| HttpError::from_wreq(&url, e))?;
+        crate::summary::count_bytes(bytes.len());
+        let body = decode_body(&bytes, content_type.as_deref(), opts.encoding.as_deref());
+        if let Some(mut response) = har {
+            response["content"] = har_content(&bytes, &body, content_type.as_deref());
+            response["bodySize"] = json!(bytes.len());
+            let timings = (wait, clock.elapsed().saturating_sub(wait));
+            har_record(&url, &opts, started, timings, response);
+        }
+
+        if let Some(path) = cache_path {
+            if status == 200 && (etag.is_some() || last_modified.is_some()) {
+                let entry = CacheEntry {
+                    url: url.clone(),
+                    status,
+                    etag,
+                    last_modified,
+                    body,
+                };
+                store_cached(&path, &entry);
+                return Ok(Fetched {
+                    status,
+                    body: entry.body,
+                });
+            }
+        }
+        Ok(Fetched { status, body })
+    }
+
+    static HAR: Mutex<Option<Vec<Value>>> = Mutex::new(None);
+
+    // Bodies beyond this are cut off in the log; `content.size` keeps the
+    // real length.
+    const HAR_BODY_LIMIT: usize = 64 * 1024;
+
+    /// Starts recording every request spider makes, for `har_save`.
+    pub fn har_start() {
+        *HAR.lock().unwrap() = Some(Vec::new());
+    }
+
+    /// Writes the recorded traffic as a HAR 1.2 file and stops recording.
+    pub fn har_save(path: &str) -> bool {
+        let entries = HAR.lock().unwrap().take().unwrap_or_default();
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "zinc", "version": env!("CARGO_PKG_VERSION") },
+                "entries": entries,
+            }
+        });
+        serde_json::to_string_pretty(&har)
+            .map(|raw| std::fs::write(path, raw).is_ok())
+            .unwrap_or(false)
+    }
+
+    fn har_enabled() -> bool {
+        HAR.lock().unwrap().is_some()
+    }
+
+    fn har_headers(headers: &[(String, String)]) -> Value {
+        headers
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect()
+    }
+
+    fn har_response(resp: &wreq::Response) -> Value {
+        let headers = resp
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.as_str().to_string(), value)
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "status": resp.status().as_u16(),
+            "statusText": resp.status().canonical_reason().unwrap_or(""),
+            "httpVersion": format!("{:?}", resp.version()),
+            "headers": har_headers(&headers),
+            "cookies": [],
+            "content": { "size": 0, "mimeType": "" },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": 0,
+        })
+    }
+
+    fn har_content(bytes: &[u8], body: &str, content_type: Option<&str>) -> Value {
+        let mut end = body.len().min(HAR_BODY_LIMIT);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        json!({
+            "size": bytes.len(),
+            "mimeType": content_type.unwrap_or(""),
+            "text": &body[..end],
+        })
+    }
+
+    fn har_record(
+        url: &str,
+        opts: &FetchOptions,
+        started: SystemTime,
+        (wait, receive): (Duration, Duration),
+        response: Value,
+    ) {
+        let request_body = opts.body.as_ref().map_or(0, |b| b.len());
+        let entry = json!({
+            "startedDateTime": iso8601(started),
+            "time": (wait + receive).as_secs_f64() * 1000.0,
+            "request": {
+                "method": if opts.body.is_some() { "POST" } else { "GET" },
+                "url": url,
+                "httpVersion": "HTTP/1.1",
+                "headers": har_headers(&opts.headers),
+                "queryString": [],
+                "cookies": [],
+                "headersSize": -1,
+                "bodySize": request_body,
+            },
+            "response": response,
+            "cache": {},
+            "timings": {
+                "send": 0,
+                "wait": wait.as_secs_f64() * 1000.0,
+                "receive": receive.as_secs_f64() * 1000.0,
+            },
+        });
+        if let Some(entries) = HAR.lock().unwrap().as_mut() {
+            entries.push(entry);
+        }
+    }
+
+    fn iso8601(time: SystemTime) -> String {
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since.as_secs() as i64;
+        let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+        // Civil-from-days (Howard Hinnant's algorithm).
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            rem / 3600,
+            rem % 3600 / 60,
+            rem % 60,
+            since.subsec_millis()
+        )
+    }
+
+    #[derive(Clone, Copy)]
+    enum Protocol {
+        Auto,
+        Http1,
+        Http2,
+    }
+
+    static PROTOCOL: Mutex<Protocol> = Mutex::new(Protocol::Auto);
+
+    /// Pins the HTTP version: "h1", "h2" or "auto" (ALPN negotiation, the
+    /// default). wreq has no HTTP/3 transport, so "h3" is rejected and the
+    /// setting is left unchanged; returns whether the choice was applied.
+    pub fn set_protocol(name: &str) -> bool {
+        let protocol = match name.to_ascii_lowercase().as_str() {
+            "auto" => Protocol::Auto,
+            "h1" | "http1" | "http/1.1" => Protocol::Http1,
+            "h2" | "http2" => Protocol::Http2,
+            _ => return false,
+        };
+        *PROTOCOL.lock().unwrap() = protocol;
+        true
+    }
+
+    /// Pacing, retries, user agent rotation and robots.txt handling, bundled
+    /// so that a script picks sensible defaults with one name.
+    #[derive(Clone, Copy)]
+    struct CrawlProfile {
+        // Minimum gap between requests to one host, plus up to `jitter`.
+        delay: Duration,
+        jitter: Duration,
+        per_host: usize,
+        retries: u32,
+        // Doubled after every failed attempt.
+        backoff: Duration,
+        rotate_agents: bool,
+        robots: bool,
+    }
+
+    fn crawl_preset(name: &str) -> Option<CrawlProfile> {
+        let profile = match name.to_ascii_lowercase().as_str() {
+            "aggressive" => CrawlProfile {
+                delay: Duration::ZERO,
+                jitter: Duration::ZERO,
+                per_host: 16,
+                retries: 1,
+                backoff: Duration::from_millis(250),
+                rotate_agents: false,
+                robots: false,
+            },
+            "polite" => CrawlProfile {
+                delay: Duration::from_secs(1),
+                jitter: Duration::from_millis(500),
+                per_host: 2,
+                retries: 3,
+                backoff: Duration::from_secs(2),
+                rotate_agents: false,
+                robots: true,
+            },
+            "stealth" => CrawlProfile {
+                delay: Duration::from_secs(3),
+                jitter: Duration::from_secs(4),
+                per_host: 1,
+                retries: 2,
+                backoff: Duration::from_secs(5),
+                rotate_agents: true,
+                robots: true,
+            },
+            _ => return None,
+        };
+        Some(profile)
+    }
+
+    // Outer `None` until first use, when `ZINC_CRAWL_PROFILE` and then
+    // `crawl_profile` under `[spider]` in zinc.toml are consulted.
+    static CRAWL: Mutex<Option<Option<CrawlProfile>>> = Mutex::new(None);
+
+    fn crawl_settings() -> Option<CrawlProfile> {
+        *CRAWL.lock().unwrap().get_or_insert_with(|| {
+            let name = std::env::var("ZINC_CRAWL_PROFILE").ok().or_else(|| {
+                let name = crate::config::load().pointer("/spider/crawl_profile")?;
+                name.as_str().map(str::to_string)
+            })?;
+            let profile = crawl_preset(&name);
+            if profile.is_none() {
+                eprintln!("Ignoring crawl profile {:?}: unknown profile", name);
+            }
+            profile
+        })
+    }
+
+    /// Site-specific settings from zinc.toml, e.g.
+    ///
+    /// ```toml
+    /// [spider.domains."example.com"]
+    /// headers = { "Accept-Language" = "de" }
+    /// proxy = "http://proxy:8080"
+    /// rate_limit = 2  # requests per second
+    /// render = true   # fetch through headless Chrome
+    /// ```
+    ///
+    /// A domain also covers its subdomains; the most specific entry wins.
+    #[derive(Clone, Default)]
+    struct DomainConfig {
+        headers: Vec<(String, String)>,
+        proxy: Option<String>,
+        rate_limit: Option<f64>,
+        render: bool,
+    }
+
+    impl DomainConfig {
+        // What the script sets itself takes precedence.
+        fn apply(&self, opts: &mut FetchOptions) {
+            for (name, value) in &self.headers {
+                if !opts
+                    .headers
+                    .iter()
+                    .any(|(n, _)| n.eq_ignore_ascii_case(name))
+                {
+                    opts.headers.push((name.clone(), value.clone()));
+                }
+            }
+            if opts.proxy.is_none() {
+                opts.proxy = self.proxy.clone();
+            }
+        }
+
+        fn min_gap(&self) -> Duration {
+            self.rate_limit
+                .filter(|rate| *rate > 0.0)
+                .map_or(Duration::ZERO, |rate| Duration::from_secs_f64(1.0 / rate))
+        }
+    }
+
+    static DOMAINS: std::sync::OnceLock<Vec<(String, DomainConfig)>> = std::sync::OnceLock::new();
+
+    fn domain_config(url: &str) -> Option<DomainConfig> {
+        let host = host_of(url);
+        let host = host.split(':').next().unwrap_or_default();
+        DOMAINS
+            .get_or_init(load_domains)
+            .iter()
+            .filter(|(domain, _)| host == domain || host.ends_with(&format!(".{}", domain)))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, config)| config.clone())
+    }
+
+    fn load_domains() -> Vec<(String, DomainConfig)> {
+        let domains = crate::config::load().pointer("/spider/domains");
+        domains
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(domain, settings)| {
+                let config = DomainConfig {
+                    headers: header_pairs(&settings["headers"]),
+                    proxy: settings["proxy"].as_str().map(str::to_string),
+                    rate_limit: settings["rate_limit"].as_f64(),
+                    render: settings["render"].as_bool().unwrap_or(false),
+                };
+                (domain.to_ascii_lowercase(), config)
+            })
+            .collect()
+    }
+
+    // `render` under the same policy as plain requests, caching the result
+    // when the page carries validators.
+    async fn render_politely(
+        url: String,
+        opts: FetchOptions,
+        cache_path: Option<PathBuf>,
+    ) -> Result<Fetched, HttpError> {
+        let entry = politely(&url, &opts, render).await?;
+        if let Some(path) = cache_path {
+            if entry.status == 200 && (entry.etag.is_some() || entry.last_modified.is_some()) {
+                store_cached(&path, &entry);
+            }
+        }
+        Ok(Fetched {
+            status: entry.status,
+            body: entry.body,
+        })
+    }
+
+    // The page's HTML once its scripts have run, for `render = true` domains,
+    // with the status and validators of the document's own response. `opts`
+    // already carries the domain's headers and proxy.
+    #[cfg(feature = "render")]
+    async fn render(url: String, opts: FetchOptions) -> Result<CacheEntry, HttpError> {
+        use headless_chrome::protocol::cdp::Network::ResourceType;
+        use headless_chrome::{Browser, LaunchOptions};
+
+        crate::limits::count_request(&url);
+        crate::summary::count_request();
+        let target = url.clone();
+        let rendered = tokio::task::spawn_blocking(move || -> anyhow::Result<(Value, String)> {
+            let browser = Browser::new(LaunchOptions {
+                proxy_server: opts.proxy.as_deref(),
+                ..LaunchOptions::default()
+            })?;
+            let tab = browser.new_tab()?;
+            let headers = opts
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            tab.set_extra_http_headers(headers)?;
+            let document = Arc::new(Mutex::new(None));
+            let seen = Arc::clone(&document);
+            tab.register_response_handling(
+                "zinc-document",
+                Box::new(move |event, _| {
+                    if event.Type == ResourceType::Document {
+                        let response = serde_json::to_value(&event.response).unwrap_or_default();
+                        seen.lock().unwrap().get_or_insert(response);
+                    }
+                }),
+            )?;
+            tab.navigate_to(&target)?.wait_until_navigated()?;
+            let body = tab.get_content()?;
+            let response = document.lock().unwrap().take().unwrap_or_default();
+            Ok((response, body))
+        })
+        .await;
+        let (response, body) = match rendered {
+            Ok(Ok(rendered)) => rendered,
+            Ok(Err(err)) => return Err(HttpError::new(&url, "render", err)),
+            Err(err) => return Err(HttpError::new(&url, "render", err)),
+        };
+        crate::summary::count_bytes(body.len());
+        let header = |name: &str| {
+            response["headers"]
+                .as_object()?
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, value)| value.as_str())
+                .map(str::to_string)
+        };
+        Ok(CacheEntry {
+            status: response["status"]
+                .as_u64()
+                .and_then(|status| u16::try_from(status).ok())
+                .unwrap_or(200),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+            url,
+            body,
+        })
+    }
+
+    #[cfg(not(feature = "render"))]
+    async fn render(url: String, _opts: FetchOptions) -> Result<CacheEntry, HttpError> {
+        let message = "zinc_std was built without the `render` feature";
+        Err(HttpError::new(&url, "render", message))
+    }
+
+    /// Applies a crawl profile to every later request: "aggressive",
+    /// "polite" or "stealth", or "none" to go back to plain requests. Unknown
+    /// names leave the setting unchanged; returns whether the choice applied.
+    pub fn set_crawl_profile(name: &str) -> bool {
+        let profile = match name.to_ascii_lowercase().as_str() {
+            "none" => None,
+            other => match crawl_preset(other) {
+                Some(profile) => Some(profile),
+                None => return false,
+            },
+        };
+        *CRAWL.lock().unwrap() = Some(profile);
+        true
+    }
+
+    async fn send_politely(url: &str, opts: &FetchOptions) -> Result<wreq::Response, HttpError> {
+        politely(
+            url,
+            opts,
+            |url, opts| async move { send(&url, &opts).await },
+        )
+        .await
+    }
+
+    // What a retry decision needs to know about a response.
+    trait StatusCode {
+        fn status_code(&self) -> u16;
+    }
+
+    impl StatusCode for wreq::Response {
+        fn status_code(&self) -> u16 {
+            self.status().as_u16()
+        }
+    }
+
+    impl StatusCode for CacheEntry {
+        fn status_code(&self) -> u16 {
+            self.status
+        }
+    }
+
+    // `request` with the host's zinc.toml settings and the active crawl
+    // profile, if any: robots.txt is checked first, then each attempt waits
+    // its turn for the host and failures, 429s and 5xx responses are retried
+    // with backoff.
+    async fn politely<T, F, Fut>(url: &str, opts: &FetchOptions, request: F) -> Result<T, HttpError>
+    where
+        T: StatusCode,
+        F: Fn(String, FetchOptions) -> Fut,
+        Fut: Future<Output = Result<T, HttpError>>,
+    {
+        let domain = domain_config(url).unwrap_or_default();
+        let mut opts = opts.clone();
+        domain.apply(&mut opts);
+        let Some(crawl) = crawl_settings() else {
+            pace(url, domain.min_gap()).await;
+            return request(url.to_string(), opts).await;
+        };
+        if crawl.robots && !robots_allowed(url).await {
+            return Err(HttpError::new(url, "robots", "disallowed by robots.txt"));
+        }
+        let mut attempt = 0;
+        loop {
+            let gap = crawl.delay + crawl.jitter.mul_f64(crate::random::float());
+            pace(url, gap.max(domain.min_gap())).await;
+            let mut opts = opts.clone();
+            if crawl.rotate_agents && opts.profile.is_none() {
+                opts.profile = Some(next_agent().to_string());
+            }
+            let result = request(url.to_string(), opts).await;
+            let retry = match &result {
+                Ok(resp) => matches!(resp.status_code(), 429 | 500..=599),
+                Err(err) => !matches!(err.kind.as_str(), "proxy" | "client" | "tls"),
+            };
+            if !retry || attempt >= crawl.retries {
+                return result;
+            }
+            tokio::time::sleep(crawl.backoff * 2u32.pow(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    static NEXT_SLOT: Mutex<Option<HashMap<String, Instant>>> = Mutex::new(None);
+
+    // Books the host's next free slot before sleeping, so concurrent
+    // requests to one host queue up instead of all firing after one delay.
+    async fn pace(url: &str, gap: Duration) {
+        if gap.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let start = {
+            let mut slots = NEXT_SLOT.lock().unwrap();
+            let next = slots
+                .get_or_insert_with(HashMap::new)
+                .entry(host_of(url))
+                .or_insert(now);
+            let start = (*next).max(now);
+            *next = start + gap;
+            start
+        };
+        tokio::time::sleep(start.saturating_duration_since(now)).await;
+    }
+
+    const AGENTS: &[&str] = &["chrome", "safari"];
+    static AGENT_TURN: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    fn next_agent() -> &'static str {
+        let turn = AGENT_TURN.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        AGENTS[turn % AGENTS.len()]
+    }
+
+    // Rules for `User-agent: *`, as (allow, path prefix), per origin.
+    static ROBOTS: Mutex<Option<HashMap<String, Arc<Vec<(bool, String)>>>>> = Mutex::new(None);
+
+    async fn robots_allowed(url: &str) -> bool {
+        let Some((origin, path)) = split_origin(url) else {
+            return true;
+        };
+        let cached = ROBOTS
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .get(origin)
+            .cloned();
+        let rules = match cached {
+            Some(rules) => rules,
+            None => {
+                // A missing or unreachable robots.txt allows everything.
+                let robots_url = format!("{}/robots.txt", origin);
+                let body = match send(&robots_url, &FetchOptions::default()).await {
+                    Ok(resp) if resp.status().is_success() => resp.text().await.unwrap_or_default(),
+                    _ => String::new(),
+                };
+                let rules = Arc::new(robots_rules(&body));
+                ROBOTS
+                    .lock()
+                    .unwrap()
+                    .get_or_insert_with(HashMap::new)
+                    .insert(origin.to_string(), rules.clone());
+                rules
+            }
+        };
+        // The longest matching rule decides, and `Allow` wins a tie.
+        rules
+            .iter()
+            .filter(|(_, prefix)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(allow, prefix)| (prefix.len(), *allow))
+            .map_or(true, |(allow, _)| *allow)
+    }
+
+    fn split_origin(url: &str) -> Option<(&str, &str)> {
+        let scheme_end = url.find("://")? + 3;
+        let path_start = url[scheme_end..]
+            .find(['/', '?', '#'])
+            .map_or(url.len(), |i| scheme_end + i);
+        let path = url[path_start..].split('#').next().unwrap_or_default();
+        Some((
+            &url[..path_start],
+            if path.starts_with('/') { path } else { "/" },
+        ))
+    }
+
+    // Wildcards are not supported. A Disallow rule is cut at its first `*` or
+    // `$`, which can only make it match more paths, never fewer; an Allow rule
+    // with one is dropped, since widening it would let through disallowed
+    // paths.
+    fn robots_rules(body: &str) -> Vec<(bool, String)> {
+        let mut rules = Vec::new();
+        let mut applies = false;
+        let mut in_agents = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+            match key.as_str() {
+                "user-agent" => {
+                    // Consecutive User-agent lines share one group.
+                    if !in_agents {
+                        applies = false;
+                    }
+                    in_agents = true;
+                    applies |= value == "*";
+                }
+                "allow" | "disallow" => {
+                    in_agents = false;
+                    let allow = key == "allow";
+                    let wildcard = value.contains(['*', '$']);
+                    let prefix = value.split(['*', '$']).next().unwrap_or_default();
+                    if applies && !value.is_empty() && !(allow && wildcard) {
+                        rules.push((allow, prefix.to_string()));
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+        rules
+    }
+
+    #[derive(Clone, Default)]
+    struct TlsConfig {
+        ca_file: Option<String>,
+        insecure: bool,
+    }
+
+    static TLS: Mutex<Option<TlsConfig>> = Mutex::new(None);
+
+    /// Sets TLS options for all later requests from a map:
+    /// `{"ca": "corp-root.pem", "insecure": true}`. `ca` is a PEM bundle of
+    /// extra roots; `insecure` turns off certificate verification entirely.
+    pub fn configure_tls(options: &Value) {
+        let config = TlsConfig {
+            ca_file: options
+                .get("ca")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string()),
+            insecure: options
+                .get("insecure")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+        };
+        if config.insecure {
+            eprintln!("Warning: TLS certificate verification is disabled.");
+        }
+        *TLS.lock().unwrap() = Some(config);
+    }
+
+    static DNS_OVERRIDES: Mutex<Option<HashMap<String, IpAddr>>> = Mutex::new(None);
+    static DOH_ENDPOINT: Mutex<Option<String>> = Mutex::new(None);
+
+    /// Pins `host` to a fixed address, like an `/etc/hosts` entry. Returns
+    /// false if `ip` is not a valid IPv4/IPv6 address.
+    pub fn resolve(host: &str, ip: &str) -> bool {
+        let Ok(ip) = ip.trim().parse::<IpAddr>() else {
+            return false;
+        };
+        DNS_OVERRIDES
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(host.to_ascii_lowercase(), ip);
+        true
+    }
+
+    /// Resolves hosts through a DNS-over-HTTPS JSON endpoint such as
+    /// `https://1.1.1.1/dns-query` instead of the system resolver. Use an
+    /// IP-literal endpoint so the lookup itself doesn't need DNS.
+    pub fn use_doh(endpoint: &str) {
+        *DOH_ENDPOINT.lock().unwrap() = Some(endpoint.to_string());
+    }
+
+    async fn resolve_override(host: &str) -> Option<IpAddr> {
+        if host.starts_with('[') || host.parse::<IpAddr>().is_ok() {
+            return None;
+        }
+        let pinned = DNS_OVERRIDES
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|m| m.get(host).copied());
+        if pinned.is_some() {
+            return pinned;
+        }
+        let endpoint = DOH_ENDPOINT.lock().unwrap().clone()?;
+        let ip = doh_lookup(&endpoint, host).await?;
+        // Answers are kept for the rest of the run, same as a pinned host.
+        DNS_OVERRIDES
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(host.to_string(), ip);
+        Some(ip)
+    }
+
+    async fn doh_lookup(endpoint: &str, host: &str) -> Option<IpAddr> {
+        let client = Client::builder().build().ok()?;
+        for record_type in ["A", "AAAA"] {
+            let body = client
+                .get(format!("{}?name={}&type={}", endpoint, host, record_type))
+                .header("Accept", "application/dns-json")
+                .send()
+                .await
+                .ok()?
+                .text()
+                .await
+                .ok()?;
+            let answer: Value = serde_json::from_str(&body).ok()?;
+            let ip = answer
+                .get("Answer")
+                .and_then(|a| a.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|record| record.get("data").and_then(|d| d.as_str()))
+                .find_map(|data| data.parse::<IpAddr>().ok());
+            if ip.is_some() {
+                return ip;
+            }
+        }
+        None
+    }
+
+    async fn send(url: &str, opts: &FetchOptions) -> Result<wreq::Response, HttpError> {
+        crate::limits::count_request(url);
+        crate::summary::count_request();
+        let emu = match opts.profile.as_deref().unwrap_or("chrome") {
+            "safari" => Emulation::Safari26,
+            _ => Emulation::Chrome124,
+        };
+
+        let mut builder = Client::builder().emulation(emu);
+        if let Some(proxy_url) = &opts.proxy {
+            let proxy = wreq::Proxy::all(proxy_url.as_str())
+                .map_err(|e| HttpError::new(url, "proxy", e))?;
+            builder = builder.proxy(proxy);
+        }
+        match *PROTOCOL.lock().unwrap() {
+            Protocol::Auto => {}
+            Protocol::Http1 => builder = builder.http1_only(),
+            Protocol::Http2 => builder = builder.http2_only(),
+        }
+        let tls = TLS.lock().unwrap().clone();
+        if let Some(tls) = tls {
+            if let Some(ca) = &tls.ca_file {
+                let pem = std::fs::read(ca).map_err(|e| HttpError::new(url, "tls", e))?;
+                let store = wreq::tls::CertStore::from_pem_stack(&pem)
+                    .map_err(|e| HttpError::new(url, "tls", e))?;
+                builder = builder.cert_store(store);
+            }
+            if tls.insecure {
+                builder = builder.cert_verification(false);
+            }
+        }
+        let host = host_of(url);
+        let host = host.split(':').next().unwrap_or_default();
+        if let Some(ip) = resolve_override(host).await {
+            // The port is taken from the URL; only the address is pinned.
+            builder = builder.resolve(host, SocketAddr::new(ip, 0));
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| HttpError::new(url, "client", e))?;
+        let mut request = match &opts.body {
+            Some(body) => client.post(url).body(body.clone()),
+            None => client.get(url),
+        };
+        for (name, value) in &opts.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(cookie) = opts.session.as_deref().and_then(|s| cookie_header(s, url)) {
+            request = request.header("Cookie", cookie);
+        }
+        let resp = request
+            .send()
+            .await
+            .map_err(|e| HttpError::from_wreq(url, e))?;
+        if let Some(session) = &opts.session {
+            let set_cookies = resp
+                .headers()
+                .get_all("set-cookie")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .filter_map(|v| parse_set_cookie(v, url))
+                .collect::<Vec<_>>();
+            store_cookies(session, set_cookies);
+        }
+        Ok(resp)
+    }
+
+    static SESSIONS: Mutex<Option<HashMap<String, Vec<Cookie>>>> = Mutex::new(None);
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Cookie {
+        pub name: String,
+        pub value: String,
+        pub domain: String,
+        pub path: String,
+        #[serde(default)]
+        pub secure: bool,
+        /// Unix seconds; `None` for session cookies.
+        #[serde(default)]
+        pub expires: Option<i64>,
+    }
+
+    impl Cookie {
+        fn matches(&self, url: &str, now: i64) -> bool {
+            let host = host_of(url);
+            let host = host.split(':').next().unwrap_or(&host);
+            let domain = self.domain.trim_start_matches('.');
+            let domain_ok = host == domain || host.ends_with(&format!(".{}", domain));
+            let path = url
+                .split_once("://")
+                .map(|(_, r)| r)
+                .and_then(|r| r.find('/').map(|i| &r[i..]))
+                .unwrap_or("/");
+            let secure_ok = !self.secure || url.starts_with("https://");
+            let live = self.expires.map_or(true, |t| t > now);
+            domain_ok && path.starts_with(&self.path) && secure_ok && live
+        }
+    }
+
+    fn unix_now() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default()
+    }
+
+    /// Fetches `url` with the cookies of the named session and records any
+    /// `Set-Cookie` the server sends back into it.
+    pub fn session_get(session: &str, url: &str, profile: Option<&str>) -> String {
+        let opts = FetchOptions {
+            session: Some(session.to_string()),
+            ..FetchOptions::profile(profile)
+        };
+        match crate::block_on(fetch(url.to_string(), opts)) {
+            Ok(f) => f.body,
+            Err(err) => err.to_json(),
+        }
+    }
+
+    fn cookie_header(session: &str, url: &str) -> Option<String> {
+        let now = unix_now();
+        let sessions = SESSIONS.lock().unwrap();
+        let pairs = sessions
+            .as_ref()?
+            .get(session)?
+            .iter()
+            .filter(|c| c.matches(url, now))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>();
+        if pairs.is_empty() {
+            None
+        } else {
+            Some(pairs.join("; "))
+        }
+    }
+
+    fn store_cookies(session: &str, cookies: Vec<Cookie>) {
+        let mut sessions = SESSIONS.lock().unwrap();
+        let jar = sessions
+            .get_or_insert_with(HashMap::new)
+            .entry(session.to_string())
+            .or_default();
+        for cookie in cookies {
+            jar.retain(|c| {
+                !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+            });
+            jar.push(cookie);
+        }
+    }
+
+    fn parse_set_cookie(header: &str, url: &str) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.trim().split_once('=')?;
+        let mut cookie = Cookie {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+            domain: host_of(url)
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            path: "/".to_string(),
+            secure: false,
+            expires: None,
+        };
+        for attr in parts {
+            let (key, val) = attr.trim().split_once('=').unwrap_or((attr.trim(), ""));
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !val.is_empty() => cookie.domain = val.to_ascii_lowercase(),
+                "path" if val.starts_with('/') => cookie.path = val.to_string(),
+                "secure" => cookie.secure = true,
+                "max-age" => {
+                    if let Ok(secs) = val.parse::<i64>() {
+                        cookie.expires = Some(unix_now() + secs);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(cookie)
+    }
+
+    /// Writes the session's cookies to `path`: JSON for `.json` files, the
+    /// Netscape `cookies.txt` format otherwise.
+    pub fn cookies_save(session: &str, path: &str) -> bool {
+        let cookies = SESSIONS
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|s| s.get(session).cloned())
+            .unwrap_or_default();
+        let content = if path.to_ascii_lowercase().ends_with(".json") {
+            serde_json::to_string_pretty(&cookies).unwrap_or_else(|_| "[]".to_string())
+        } else {
+            let mut out = String::from("# Netscape HTTP Cookie File\n");
+            for c in &cookies {
+                out.push_str(&format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    c.domain,
+                    if c.domain.starts_with('.') {
+                        "TRUE"
+                    } else {
+                        "FALSE"
+                    },
+                    c.path,
+                    if c.secure { "TRUE" } else { "FALSE" },
+                    c.expires.unwrap_or(0),
+                    c.name,
+                    c.value
+                ));
+            }
+            out
+        };
+        std::fs::write(path, content).is_ok()
+    }
+
+    /// Loads cookies into the session, accepting either format `cookies_save`
+    /// writes (and so browser `cookies.txt` exports). Returns how many were read.
+    pub fn cookies_load(session: &str, path: &str) -> usize {
+        let raw = match std::fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(_) => return 0,
+        };
+        let cookies = if raw.trim_start().starts_with('[') {
+            serde_json::from_str::<Vec<Cookie>>(&raw).unwrap_or_default()
+        } else {
+            raw.lines().filter_map(parse_netscape_line).collect()
+        };
+        let count = cookies.len();
+        store_cookies(session, cookies);
+        count
+    }
+
+    fn parse_netscape_line(line: &str) -> Option<Cookie> {
+        // curl marks HttpOnly cookies with this prefix instead of a column.
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.starts_with('#') || line.trim().is_empty() {
+            return None;
+        }
+        let fields = line.split('\t').collect::<Vec<_>>();
+        if fields.len() < 7 {
+            return None;
+        }
+        let expires = fields[4].parse::<i64>().ok().filter(|t| *t > 0);
+        Some(Cookie {
+            domain: fields[0].to_string(),
+            path: fields[2].to_string(),
+            secure: fields[3].eq_ignore_ascii_case("TRUE"),
+            expires,
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+        })
+    }
+
+    // (vendor, reason, markers): any one marker in the lowercased body is
+    // enough. Ordered so vendor-specific pages win over generic captchas.
+    const CHALLENGE_MARKERS: &[(&str, &str, &[&str])] = &[
+        (
+            "cloudflare",
+            "JavaScript challenge",
+            &[
+                "cf_chl_opt",
+                "cf-browser-verification",
+                "challenge-platform",
+                "<title>just a moment...</title>",
+            ],
+        ),
+        (
+            "cloudflare",
+            "Blocked by WAF",
+            &["attention required! | cloudflare", "cf-error-details"],
+        ),
+        (
+            "akamai",
+            "Access denied",
+            &["errors.edgesuite.net", "akamai reference #"],
+        ),
+        (
+            "perimeterx",
+            "Human verification",
+            &["_pxappid", "px-captcha", "_pxcaptcha", "perimeterx"],
+        ),
+        ("datadome", "Captcha", &["captcha-delivery.com", "datadome"]),
+        (
+            "unknown",
+            "Captcha",
+            &["g-recaptcha", "h-captcha", "cf-turnstile"],
+        ),
+    ];
+
+    /// Recognises anti-bot block pages, given a page body or the
+    /// `{"status": ..., "body": ...}` document from `get_response`. Returns
+    /// `null` for a normal page, otherwise `{"vendor": ..., "reason": ...}`
+    /// (plus `status` when it is known), so scripts can `match` on it.
+    pub fn is_challenge<R: Challenge + ?Sized>(response: &R) -> Value {
+        response.challenge()
+    }
+
+    pub trait Challenge {
+        fn challenge(&self) -> Value;
+    }
+
+    // JSON text is a transport error from `get` or a `get_response` document
+    // that went through a string.
+    impl Challenge for str {
+        fn challenge(&self) -> Value {
+            match serde_json::from_str::<Value>(self) {
+                Ok(doc @ Value::Object(_)) => doc.challenge(),
+                _ => challenge_page(None, self),
+            }
+        }
+    }
+
+    impl Challenge for String {
+        fn challenge(&self) -> Value {
+            self.as_str().challenge()
+        }
+    }
+
+    impl Challenge for Value {
+        fn challenge(&self) -> Value {
+            match self {
+                Value::String(body) => body.challenge(),
+                Value::Object(doc) => {
+                    let status = doc
+                        .get("status")
+                        .or_else(|| doc.get("error").and_then(|e| e.get("status")))
+                        .and_then(Value::as_u64);
+                    let body = doc.get("body").and_then(Value::as_str).unwrap_or_default();
+                    challenge_page(status, body)
+                }
+                _ => Value::Null,
+            }
+        }
+    }
+
+    fn challenge_page(status: Option<u64>, body: &str) -> Value {
+        let lower = body.to_ascii_lowercase();
+        // Akamai's denial page is tiny and only identifiable by its wording.
+        let akamai_denied = lower.contains("<title>access denied</title>")
+            && lower.contains("you don't have permission to access");
+        let found = if akamai_denied {
+            Some(("akamai", "Access denied".to_string()))
+        } else {
+            CHALLENGE_MARKERS
+                .iter()
+                .find(|(_, _, markers)| markers.iter().any(|m| lower.contains(m)))
+                .map(|(vendor, reason, _)| (*vendor, reason.to_string()))
+        };
+        let found = found.or_else(|| match status {
+            Some(status @ (403 | 429 | 503)) => Some(("unknown", format!("HTTP {}", status))),
+            _ => None,
+        });
+        let Some((vendor, reason)) = found else {
+            return Value::Null;
+        };
+        let mut out = json!({ "vendor": vendor, "reason": reason });
+        if let Some(status) = status {
+            out["status"] = json!(status);
+        }
+        out
+    }
+
+    /// Renders `url` in headless Chrome and saves a PNG to `path`. Options:
+    /// `{"full_page": bool, "viewport": {"width": w, "height": h}}`.
+    #[cfg(feature = "render")]
+    pub fn screenshot(url: &str, path: &str, options: &Value) -> bool {
+        match screenshot_inner(url, path, options) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Screenshot of {} failed: {}", url, e);
+                false
+            }
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn screenshot_inner(url: &str, path: &str, options: &Value) -> anyhow::Result<()> {
+        use headless_chrome::protocol::cdp::Page;
+        use headless_chrome::{Browser, LaunchOptions};
+
+        let dimension = |key: &str, default: u32| {
+            options
+                .get("viewport")
+                .and_then(|v| v.get(key))
+                .and_then(Value::as_u64)
+                .map_or(default, |n| n as u32)
+        };
+        let (width, height) = (dimension("width", 1280), dimension("height", 800));
+        let full_page = options
+            .get("full_page")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+
+        let launch = LaunchOptions::default_builder()
+            .window_size(Some((width, height)))
+            .build()?;
+        let browser = Browser::new(launch)?;
+        let tab = browser.new_tab()?;
+        tab.navigate_to(url)?.wait_until_navigated()?;
+
+        let clip = if full_page {
+            let metrics = tab.call_method(Page::GetLayoutMetrics(None))?;
+            Some(Page::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: metrics.css_content_size.width,
+                height: metrics.css_content_size.height,
+                scale: 1.0,
+            })
+        } else {
+            None
+        };
+        let png =
+            tab.capture_screenshot(Page::CaptureScreenshotFormatOption::Png, None, clip, true)?;
+        std::fs::write(path, png)?;
+        Ok(())
+    }
+
+    /// Decodes a response body to UTF-8. An explicit `encoding` label wins,
+    /// then a BOM, the `Content-Type` charset, a `<meta>` charset in the first
+    /// 1 KiB, and finally UTF-8 with a windows-1252 fallback.
+    pub fn decode_body(bytes: &[u8], content_type: Option<&str>, encoding: Option<&str>) -> String {
+        if let Some(enc) = encoding.and_then(|label| Encoding::for_label(label.trim().as_bytes())) {
+            return enc.decode_without_bom_handling(bytes).0.into_owned();
+        }
+        if let Some((enc, bom_len)) = Encoding::for_bom(bytes) {
+            return enc
+                .decode_without_bom_handling(&bytes[bom_len..])
+                .0
+                .into_owned();
+        }
+        let declared = content_type
+            .and_then(charset_param)
+            .or_else(|| meta_charset(&bytes[..bytes.len().min(1024)]))
+            .and_then(|label| Encoding::for_label(label.as_bytes()));
+        if let Some(enc) = declared {
+            return enc.decode_without_bom_handling(bytes).0.into_owned();
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => WINDOWS_1252
+                .decode_without_bom_handling(bytes)
+                .0
+                .into_owned(),
+        }
+    }
+
+    fn charset_param(value: &str) -> Option<String> {
+        let lower = value.to_ascii_lowercase();
+        let start = lower.find("charset=")? + "charset=".len();
+        let label = lower[start..]
+            .trim_start_matches(|c| c == '"' || c == '\'')
+            .split(|c: char| {
+                c == '"' || c == '\'' || c == ';' || c == '>' || c == '/' || c.is_whitespace()
+            })
+            .next()?;
+        if label.is_empty() {
+            None
+        } else {
+            Some(label.to_string())
+        }
+    }
+
+    // Covers both `<meta charset="...">` and the older
+    // `<meta http-equiv="Content-Type" content="text/html; charset=...">`.
+    fn meta_charset(head: &[u8]) -> Option<String> {
+        let text = String::from_utf8_lossy(head).to_ascii_lowercase();
+        text.split("<meta")
+            .skip(1)
+            .filter_map(|tag| charset_param(tag.split('>').next().unwrap_or(tag)))
+            .next()
+    }
+}
+
+/// Checkpoints for resumable runs. Files are JSON tagged with a format
+/// version and replaced atomically, so a crash mid-write leaves the previous
+/// checkpoint intact.
+pub mod state {
+    use serde::Serialize;
+    use serde_json::{json, Value};
+    use std::io::Write;
+    use std::path::Path;
+
+    pub const VERSION: u64 = 1;
+
+    pub fn save<T: Serialize + ?Sized>(path: &str, value: &T) -> bool {
+        if crate::skip_for_dry_run(format_args!("save state to {}", path)) {
+            return true;
+        }
+        let doc = json!({
+            "zinc_state": VERSION,
+            "saved_at": chrono::Utc::now().to_rfc3339(),
+            "value": value,
+        });
+        match write_atomic(Path::new(path), doc.to_string().as_bytes()) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Failed to save state to {}: {}", path, e);
+                false
+            }
+        }
+    }
+
+    /// The saved value, `null` if there is no checkpoint yet, or
+    /// `{"error": ...}` for an unreadable file or an unknown version.
+    pub fn load(path: &str) -> Value {
+        let raw = match std::fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Value::Null,
+            Err(e) => return json!({ "error": e.to_string() }),
+        };
+        let mut doc: Value = match serde_json::from_str(&raw) {
+            Ok(doc) => doc,
+            Err(e) => return json!({ "error": format!("corrupt state file: {}", e) }),
+        };
+        match doc.get("zinc_state").and_then(Value::as_u64) {
+            Some(VERSION) => doc["value"].take(),
+            Some(other) => json!({ "error": format!("unsupported state version {}", other) }),
+            None => json!({ "error": "not a zinc state file" }),
+        }
+    }
+
+    fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = Path::new(&tmp);
+        let mut file = std::fs::File::create(tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+#[cfg(feature = "db")]
+pub mod store {
+    use anyhow::{anyhow, Result};
+    use serde_json::{json, Value};
+    use sqlx::any::AnyPoolOptions;
+    use sqlx::{AnyPool, Row};
+    use std::sync::OnceLock;
+
+    // Relative to the working directory; `ZINC_STORE` points elsewhere.
+    const DEFAULT_PATH: &str = "zinc.db";
+
+    static POOL: OnceLock<AnyPool> = OnceLock::new();
+
+    fn url() -> String {
+        let path = std::env::var("ZINC_STORE").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+        format!("sqlite://{}?mode=rwc", path)
+    }
+
+    async fn pool() -> Result<AnyPool> {
+        if let Some(pool) = POOL.get() {
+            return Ok(pool.clone());
+        }
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect(&url())
+            .await?;
+        Ok(POOL.get_or_init(|| pool).clone())
+    }
+
+    /// Inserts a JSON object as a row, creating the table and any missing
+    /// columns on the fly. Returns `{"id": n}` or `{"error": ...}`.
+    pub fn save(table: &str, row: &Value) -> String {
+        match try_save(table, row) {
+            Ok(saved) => saved,
+            Err(e) => {
+                crate::summary::count_error();
+                json!({ "error": e.to_string() }).to_string()
+            }
+        }
+    }
+
+    /// Runs SQL against the local store; same result format as `db::query`.
+    pub fn query(sql: &str) -> String {
+        match try_query(sql) {
+            Ok(rows) => rows,
+            Err(e) => {
+                crate::summary::count_error();
+                json!({ "error": e.to_string() }).to_string()
+            }
+        }
+    }
+
+    pub fn try_query(sql: &str) -> Result<String> {
+        if !crate::db::is_read_only(sql)
+            && crate::skip_for_dry_run(format_args!("run on the store: {}", sql))
+        {
+            return Ok("[]".to_string());
+        }
+        let rows = crate::block_on(async {
+            let pool = pool().await?;
+            let rows = sqlx::query(sql).fetch_all(&pool).await?;
+            Ok::<_, anyhow::Error>(crate::db::rows_to_json(&rows))
+        })?;
+        Ok(rows.to_string())
+    }
+
+    pub fn try_save(table: &str, row: &Value) -> Result<String> {
+        if crate::skip_for_dry_run(format_args!("save to {}: {}", table, row)) {
+            return Ok(json!({ "id": null, "dry_run": true }).to_string());
+        }
+        let id = insert(table, row)?;
+        crate::summary::count_rows(1);
+        Ok(json!({ "id": id }).to_string())
+    }
+
+    fn insert(table: &str, row: &Value) -> Result<i64> {
+        let fields = row
+            .as_object()
+            .ok_or_else(|| anyhow!("store.save expects a JSON object"))?;
+        let table = quote_ident(table);
+        crate::block_on(async {
+            let pool = pool().await?;
+            let create = format!(
+                "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY AUTOINCREMENT)",
+                table
+            );
+            sqlx::query(&create).execute(&pool).await?;
+            let existing = sqlx::query(&format!("PRAGMA table_info({})", table))
+                .fetch_all(&pool)
+                .await?
+                .iter()
+                .filter_map(|r| r.try_get::<String, _>("name").ok())
+                .collect::<Vec<_>>();
+            for (key, value) in fields {
+                if !existing.contains(key) {
+                    let alter = format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}",
+                        table,
+                        quote_ident(key),
+                        column_type(value)
+                    );
+                    sqlx::query(&alter).execute(&pool).await?;
+                }
+            }
+
+            let sql = if fields.is_empty() {
+                format!("INSERT INTO {} DEFAULT VALUES", table)
+            } else {
+                let columns = fields.keys().map(|k| quote_ident(k)).collect::<Vec<_>>();
+                format!(
+                    "INSERT INTO {} ({}) VALUES ({})",
+                    table,
+                    columns.join(", "),
+                    vec!["?"; columns.len()].join(", ")
+                )
+            };
+            let mut insert = sqlx::query(&sql);
+            for value in fields.values() {
+                insert = match value {
+                    Value::Null => insert.bind(None::<String>),
+                    Value::Bool(b) => insert.bind(*b),
+                    Value::Number(n) => match n.as_i64() {
+                        Some(i) => insert.bind(i),
+                        None => insert.bind(n.as_f64().unwrap_or_default()),
+                    },
+                    Value::String(text) => insert.bind(text.clone()),
+                    // Nested objects and arrays are kept as JSON text.
+                    other => insert.bind(other.to_string()),
+                };
+            }
+            let result = insert.execute(&pool).await?;
+            Ok(result.last_insert_id().unwrap_or_default())
+        })
+    }
+
+    fn column_type(value: &Value) -> &'static str {
+        match value {
+            Value::Bool(_) => "INTEGER",
+            Value::Number(n) if n.is_i64() || n.is_u64() => "INTEGER",
+            Value::Number(_) => "REAL",
+            _ => "TEXT",
+        }
+    }
+
+    fn quote_ident(name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// End-of-run report: `zn run --summary` prints it to stderr and
+/// `--summary=<path>` writes it to a file. `ZINC_SUMMARY` carries the choice
+/// into the generated program.
+/// String methods whose Rust counterparts return borrowed or lazy values;
+/// these hand back owned `String`s and `Vec<String>`s instead.
+pub mod string {
+    pub fn split(s: &str, sep: &str) -> Vec<String> {
+        if sep.is_empty() {
+            return s.split_whitespace().map(str::to_string).collect();
+        }
+        s.split(sep).map(str::to_string).collect()
+    }
+
+    pub fn trim(s: &str) -> String {
+        s.trim().to_string()
+    }
+
+    pub fn upper(s: &str) -> String {
+        s.to_uppercase()
+    }
+
+    pub fn lower(s: &str) -> String {
+        s.to_lowercase()
+    }
+
+    pub fn replace(s: &str, from: &str, to: &str) -> String {
+        s.replace(from, to)
+    }
+
+    pub fn starts_with(s: &str, prefix: &str) -> bool {
+        s.starts_with(prefix)
+    }
+
+    pub fn ends_with(s: &str, suffix: &str) -> bool {
+        s.ends_with(suffix)
+    }
+}
+
+pub mod summary {
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static REQUESTS: AtomicU64 = AtomicU64::new(0);
+    static BYTES_DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+    static ROWS_WRITTEN: AtomicU64 = AtomicU64::new(0);
+    // Failures turned into `{"error": ...}` values instead of aborting.
+    static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+    #[cfg(feature = "http")]
+    pub(crate) fn count_request() {
+        REQUESTS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "http")]
+    pub(crate) fn count_bytes(n: usize) {
+        BYTES_DOWNLOADED.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "db")]
+    pub(crate) fn count_rows(n: u64) {
+        ROWS_WRITTEN.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_error() {
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report() -> serde_json::Value {
+        let leaks = crate::live_objects()
+            .into_iter()
+            .map(|(_, count)| count)
+            .sum::<usize>();
+        json!({
+            "duration_ms": crate::limits::elapsed().as_millis() as u64,
+            "requests": REQUESTS.load(Ordering::Relaxed),
+            "bytes_downloaded": BYTES_DOWNLOADED.load(Ordering::Relaxed),
+            "db_rows_written": ROWS_WRITTEN.load(Ordering::Relaxed),
+            "errors": ERRORS.load(Ordering::Relaxed),
+            "leaks": leaks,
+        })
+    }
+
+    pub fn emit() {
+        let target = match std::env::var("ZINC_SUMMARY") {
+            Ok(target) if !target.is_empty() => target,
+            _ => return,
+        };
+        let report = json!({ "summary": report() });
+        if target == "stderr" {
+            eprintln!("{}", report);
+        } else if let Err(e) = std::fs::write(&target, format!("{:#}\n", report)) {
+            eprintln!("Failed to write summary to {}: {}", target, e);
+        }
+    }
+}
+
+/// Aligned ASCII tables for eyeballing rows of maps in a terminal. Options:
+/// `columns` (which keys, in order) and `max_width` (cells longer than this
+/// are cut with `…`, default 40).
+pub mod table {
+    use serde_json::Value;
+
+    const DEFAULT_MAX_WIDTH: usize = 40;
+
+    pub fn print<R: serde::Serialize>(rows: &R, options: &Value) {
+        println!("{}", render(rows, options));
+    }
+
+    pub fn render<R: serde::Serialize>(rows: &R, options: &Value) -> String {
+        let rows = match serde_json::to_value(rows).unwrap_or_default() {
+            Value::Array(rows) => rows,
+            Value::Null => Vec::new(),
+            row => vec![row],
+        };
+        if rows.is_empty() {
+            return "(no rows)".to_string();
+        }
+        let max_width = options
+            .get("max_width")
+            .and_then(Value::as_u64)
+            .map_or(DEFAULT_MAX_WIDTH, |w| (w as usize).max(1));
+        let columns = match options.get("columns").and_then(Value::as_array) {
+            Some(columns) => columns
+                .iter()
+                .filter_map(|c| c.as_str().map(str::to_string))
+                .collect(),
+            None => column_names(&rows),
+        };
+
+        let cells = rows
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|column| {
+                        let value = match row {
+                            Value::Object(_) => row.get(column).unwrap_or(&Value::Null),
+                            other => other,
+                        };
+                        (truncate(&cell_text(value), max_width), value.is_number())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let widths = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].0.chars().count())
+                    .chain([truncate(column, max_width).chars().count()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+
+        let border = widths
+            .iter()
+            .map(|w| "-".repeat(w + 2))
+            .collect::<Vec<_>>()
+            .join("+");
+        let border = format!("+{}+", border);
+        let header = columns
+            .iter()
+            .map(|c| (truncate(c, max_width), false))
+            .collect::<Vec<_>>();
+        let mut lines = vec![border.clone(), line(&header, &widths), border.clone()];
+        lines.extend(cells.iter().map(|row| line(row, &widths)));
+        lines.push(border);
+        lines.join("\n")
+    }
+
+    // Keys in first-seen order across all rows.
+    fn column_names(rows: &[Value]) -> Vec<String> {
+        let mut columns = Vec::new();
+        for row in rows {
+            match row {
+                Value::Object(map) => {
+                    for key in map.keys() {
+                        if !columns.contains(key) {
+                            columns.push(key.clone());
+                        }
+                    }
+                }
+                _ if columns.is_empty() => columns.push("value".to_string()),
+                _ => {}
+            }
+        }
+        columns
+    }
+
+    fn cell_text(value: &Value) -> String {
+        match value {
+            Value::Null => String::new(),
+            Value::String(text) => text.replace(['\n', '\r', '\t'], " "),
+            other => other.to_string(),
+        }
+    }
+
+    fn truncate(text: &str, max_width: usize) -> String {
+        if text.chars().count() <= max_width {
+            return text.to_string();
+        }
+        let mut cut = text.chars().take(max_width - 1).collect::<String>();
+        cut.push('…');
+        cut
+    }
+
+    // Numbers are right-aligned so their digits line up.
+    fn line(cells: &[(String, bool)], widths: &[usize]) -> String {
+        let cells = cells
+            .iter()
+            .zip(widths)
+            .map(|((text, numeric), &width)| {
+                if *numeric {
+                    format!(" {:>width$} ", text)
+                } else {
+                    format!(" {:<width$} ", text)
+                }
+            })
+            .collect::<Vec<_>>();
+        format!("|{}|", cells.join("|"))
+    }
+}
+
+/// OpenTelemetry export of the spans spider, db and fs record. Off unless
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set; the standard `OTEL_*` variables
+/// (headers, timeout, `OTEL_SERVICE_NAME`) configure the exporter.
+/// Without the `otel` feature nothing is exported.
+pub mod telemetry {
+    /// Called at the top of every generated `main`.
+    pub fn init() {
+        if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_none() {
+            return;
+        }
+        #[cfg(feature = "otel")]
+        otlp::init();
+        #[cfg(not(feature = "otel"))]
+        eprintln!("OpenTelemetry export disabled: zinc_std was built without `otel`");
+    }
+
+    /// Flushes spans still buffered; called at the end of `main`.
+    pub fn shutdown() {
+        #[cfg(feature = "otel")]
+        otlp::shutdown();
+    }
+
+    #[cfg(feature = "otel")]
+    mod otlp {
+        use opentelemetry::trace::TracerProvider as _;
+        use opentelemetry::KeyValue;
+        use opentelemetry_sdk::trace::TracerProvider;
+        use opentelemetry_sdk::{runtime, Resource};
+        use std::sync::OnceLock;
+        use tracing_subscriber::layer::SubscriberExt;
+        use tracing_subscriber::util::SubscriberInitExt;
+
+        static PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+        pub(super) fn init() {
+            // The batch exporter spawns onto the ambient tokio runtime.
+            let _runtime = crate::runtime().enter();
+            let exporter = match opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .build()
+            {
+                Ok(exporter) => exporter,
+                Err(e) => {
+                    eprintln!("OpenTelemetry export disabled: {}", e);
+                    return;
+                }
+            };
+            let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "zinc".to_string());
+            let provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_resource(Resource::new([KeyValue::new("service.name", service)]))
+                .build();
+            let tracer = provider.tracer("zinc");
+            let _ = tracing_subscriber::registry()
+                .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .try_init();
+            let _ = PROVIDER.set(provider);
+        }
+
+        pub(super) fn shutdown() {
+            if let Some(provider) = PROVIDER.get() {
+                let _runtime = crate::runtime().enter();
+                if let Err(e) = provider.shutdown() {
+                    eprintln!("Failed to flush OpenTelemetry spans: {}", e);
+                }
+            }
+        }
+    }
+}
+
+pub mod text {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    /// ISO 639-3 code of the detected language, e.g. "eng", or "" if unsure.
+    pub fn lang(s: &str) -> String {
+        match whatlang::detect(s) {
+            Some(info) if info.is_reliable() => info.lang().code().to_string(),
+            _ => String::new(),
+        }
+    }
+
+    pub fn words(s: &str) -> Vec<String> {
+        s.unicode_words().map(str::to_string).collect()
+    }
+
+    pub fn sentences(s: &str) -> Vec<String> {
+        s.unicode_sentences()
+            .map(str::trim)
+            .filter(|sentence| !sentence.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[cfg(feature = "xlsx")]
+pub mod xlsx {
+    use anyhow::{anyhow, Result};
+    use calamine::{open_workbook_auto, Data, DataType, Reader};
+    use rust_xlsxwriter::{Format, Workbook};
+    use serde_json::{json, Map, Value};
+
+    /// Reads every sheet as `{"Sheet1": [{header: value, ..}, ..], ..}`. The
+    /// first row of each sheet holds the headers.
+    pub fn read(path: &str) -> Value {
+        read_inner(path).unwrap_or_else(|e| json!({ "error": e.to_string() }))
+    }
+
+    /// Writes `{"name": [rows..], ..}` (or a bare array as "Sheet1") with a
+    /// bold header row. Returns `{"rows": n}` or `{"error": ...}`.
+    pub fn write(path: &str, sheets: &Value) -> String {
+        if crate::skip_for_dry_run(format_args!("write a workbook to {}", path)) {
+            return json!({ "dry_run": true }).to_string();
+        }
+        match write_inner(path, sheets) {
+            Ok(count) => json!({ "rows": count }).to_string(),
+            Err(e) => json!({ "error": e.to_string() }).to_string(),
+        }
+    }
+
+    fn read_inner(path: &str) -> Result<Value> {
+        let mut workbook = open_workbook_auto(path)?;
+        let mut sheets = Map::new();
+        for name in workbook.sheet_names() {
+            let range = workbook.worksheet_range(&name)?;
+            let mut rows = range.rows();
+            let headers = rows
+                .next()
+                .unwrap_or_default()
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| match cell {
+                    Data::Empty => format!("column_{}", i + 1),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>();
+            let records = rows
+                .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+                .map(|row| {
+                    let record = headers
+                        .iter()
+                        .zip(row)
+                        .map(|(header, cell)| (header.clone(), cell_value(cell)))
+                        .collect::<Map<_, _>>();
+                    Value::Object(record)
+                })
+                .collect();
+            sheets.insert(name, Value::Array(records));
+        }
+        Ok(Value::Object(sheets))
+    }
+
+    fn cell_value(cell: &Data) -> Value {
+        match cell {
+            Data::Int(i) => json!(i),
+            Data::Float(f) => json!(f),
+            Data::Bool(b) => json!(b),
+            Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => json!(s),
+            Data::DateTime(_) => match cell.as_datetime() {
+                Some(dt) => json!(dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                None => json!(cell.to_string()),
+            },
+            Data::Error(e) => json!(e.to_string()),
+            Data::Empty => Value::Null,
+        }
+    }
+
+    fn write_inner(path: &str, sheets: &Value) -> Result<usize> {
+        let sheets = match sheets {
+            Value::Object(map) => map.iter().map(|(k, v)| (k.as_str(), v)).collect::<Vec<_>>(),
+            Value::Array(_) => vec![("Sheet1", sheets)],
+            _ => {
+                return Err(anyhow!(
+                    "xlsx.write expects a map of sheets or an array of rows"
+                ))
+            }
+        };
+        let bold = Format::new().set_bold();
+        let mut workbook = Workbook::new();
+        let mut total = 0;
+        for (name, rows) in sheets {
+            let rows = rows
+                .as_array()
+                .ok_or_else(|| anyhow!("sheet `{}` must be an array of rows", name))?;
+            let mut headers: Vec<&String> = Vec::new();
+            for row in rows {
+                let fields = row
+                    .as_object()
+                    .ok_or_else(|| anyhow!("rows in sheet `{}` must be maps", name))?;
+                for key in fields.keys() {
+                    if !headers.contains(&key) {
+                        headers.push(key);
+                    }
+                }
+            }
+
+            let sheet = workbook.add_worksheet();
+            sheet.set_name(name)?;
+            for (col, header) in headers.iter().enumerate() {
+                sheet.write_string_with_format(0, col as u16, header.as_str(), &bold)?;
+            }
+            for (i, row) in rows.iter().enumerate() {
+                let line = i as u32 + 1;
+                for (col, header) in headers.iter().enumerate() {
+                    let col = col as u16;
+                    match row.get(header.as_str()).unwrap_or(&Value::Null) {
+                        Value::Null => {}
+                        Value::Bool(b) => {
+                            sheet.write_boolean(line, col, *b)?;
+                        }
+                        Value::Number(n) => {
+                            sheet.write_number(line, col, n.as_f64().unwrap_or_default())?;
+                        }
+                        Value::String(s) => {
+                            sheet.write_string(line, col, s)?;
+                        }
+                        nested => {
+                            sheet.write_string(line, col, nested.to_string())?;
+                        }
+                    }
+                }
+            }
+            total += rows.len();
+        }
+        workbook.save(path)?;
+        Ok(total)
+    }
 }